@@ -0,0 +1,269 @@
+// animation.rs
+// sprite sheets split into named clips, and the state of a sprite playing them
+use macroquad::prelude::*;
+
+/// Direction a sprite looks in, worked out from where it last moved
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Facing {
+    Down,
+    Up,
+    Left,
+    Right,
+}
+
+impl Facing {
+    /// Dominant axis of a velocity, vertical wins ties
+    fn from_velocity(velocity: Vec2) -> Self {
+        if velocity.x.abs() > velocity.y.abs() {
+            if velocity.x < 0.0 {
+                Facing::Left
+            } else {
+                Facing::Right
+            }
+        } else if velocity.y < 0.0 {
+            Facing::Up
+        } else {
+            Facing::Down
+        }
+    }
+}
+
+/// One row of a sprite sheet
+#[derive(Debug)]
+pub struct ClipDef {
+    pub name: &'static str,
+    pub frames: u32,
+    pub frame_time: f32, // Seconds each frame stays on screen
+    pub looping: bool,   // Clips that don't loop hold their last frame until replaced
+}
+
+impl ClipDef {
+    fn duration(&self) -> f32 {
+        self.frames as f32 * self.frame_time
+    }
+}
+
+/// Sheet with one clip per row and one frame per column, every frame a square
+#[derive(Debug)]
+pub struct SpriteSheet {
+    pub texture: &'static str, // Id the sheet is loaded under by the texture manager
+    pub frame_size: u32,       // Pixels
+    pub clips: &'static [ClipDef],
+}
+
+impl SpriteSheet {
+    /// Row of a clip and its definition
+    pub fn clip(&self, name: &str) -> Option<(u32, &ClipDef)> {
+        self.clips
+            .iter()
+            .enumerate()
+            .find(|(_, clip)| clip.name == name)
+            .map(|(row, clip)| (row as u32, clip))
+    }
+
+    /// Widest clip decides how wide the sheet is
+    pub fn columns(&self) -> u32 {
+        self.clips.iter().map(|clip| clip.frames).max().unwrap_or(0)
+    }
+}
+
+const fn clip(name: &'static str, frames: u32, frame_time: f32, looping: bool) -> ClipDef {
+    ClipDef {
+        name,
+        frames,
+        frame_time,
+        looping,
+    }
+}
+
+/// Clips every walking character has, the procedural sheets draw them in this order
+const CHARACTER_CLIPS: &[ClipDef] = &[
+    clip("idle", 2, 0.5, true),
+    clip("walk_down", 4, 0.12, true),
+    clip("walk_up", 4, 0.12, true),
+    clip("walk_left", 4, 0.12, true),
+    clip("walk_right", 4, 0.12, true),
+    clip("cast", 4, 0.08, false),
+];
+
+pub const WIZARD_SHEET: SpriteSheet = SpriteSheet {
+    texture: "sheet_wizard",
+    frame_size: 32,
+    clips: CHARACTER_CLIPS,
+};
+
+pub const CREATURE_SHEET: SpriteSheet = SpriteSheet {
+    texture: "sheet_creature",
+    frame_size: 24,
+    clips: CHARACTER_CLIPS,
+};
+
+pub const SHEETS: &[&SpriteSheet] = &[&WIZARD_SHEET, &CREATURE_SHEET];
+
+/// Sheet loaded under a texture id
+pub fn sheet_by_texture(id: &str) -> Option<&'static SpriteSheet> {
+    SHEETS.iter().copied().find(|sheet| sheet.texture == id)
+}
+
+/// Which clip of a sheet a sprite is playing and how far into it it is.
+/// Only drawing reads it, so it runs on real time and never touches the simulation.
+#[derive(Clone, Copy, Debug)]
+pub struct Animator {
+    sheet: &'static SpriteSheet,
+    clip: &'static str,
+    facing: Facing,
+    time: f32, // Seconds into the clip
+}
+
+impl Animator {
+    pub fn new(sheet: &'static SpriteSheet) -> Self {
+        Animator {
+            sheet,
+            clip: "idle",
+            facing: Facing::Down,
+            time: 0.0,
+        }
+    }
+
+    pub fn sheet(&self) -> &'static SpriteSheet {
+        self.sheet
+    }
+
+    /// Starts a clip from its first frame, e.g. "cast" when a spell goes off
+    pub fn play(&mut self, clip: &'static str) {
+        self.clip = clip;
+        self.time = 0.0;
+    }
+
+    /// Advances the clip by `dt` seconds and switches between idling and walking.
+    /// A clip that doesn't loop plays to the end first.
+    pub fn update(&mut self, dt: f32, velocity: Vec2) {
+        self.time += dt;
+        let moving = velocity != Vec2::ZERO;
+        if moving {
+            self.facing = Facing::from_velocity(velocity);
+        }
+
+        if let Some((_, current)) = self.sheet.clip(self.clip)
+            && !current.looping
+            && self.time < current.duration()
+        {
+            return;
+        }
+
+        let next = match (moving, self.facing) {
+            (false, _) => "idle",
+            (true, Facing::Down) => "walk_down",
+            (true, Facing::Up) => "walk_up",
+            (true, Facing::Left) => "walk_left",
+            (true, Facing::Right) => "walk_right",
+        };
+        if next != self.clip {
+            self.play(next);
+        }
+    }
+
+    /// Part of the sheet showing the current frame
+    pub fn source_rect(&self) -> Rect {
+        let size = self.sheet.frame_size as f32;
+        let Some((row, clip)) = self.sheet.clip(self.clip) else {
+            return Rect::new(0.0, 0.0, size, size);
+        };
+        let elapsed = (self.time / clip.frame_time) as u32;
+        let frame = if clip.looping {
+            elapsed % clip.frames
+        } else {
+            elapsed.min(clip.frames - 1)
+        };
+        Rect::new(frame as f32 * size, row as f32 * size, size, size)
+    }
+
+    /// Draws the current frame centered on a screen position, `size` pixels wide,
+    /// multiplied by `color`
+    pub fn draw(&self, texture: &Texture2D, center: Vec2, size: f32, color: Color) {
+        draw_texture_ex(
+            texture,
+            center.x - size / 2.0,
+            center.y - size / 2.0,
+            color,
+            DrawTextureParams {
+                dest_size: Some(Vec2::splat(size)),
+                source: Some(self.source_rect()),
+                ..Default::default()
+            },
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Column and row of the frame an animator shows
+    fn frame(animator: &Animator) -> (u32, u32) {
+        let rect = animator.source_rect();
+        let size = animator.sheet.frame_size as f32;
+        ((rect.x / size) as u32, (rect.y / size) as u32)
+    }
+
+    fn row(name: &str) -> u32 {
+        WIZARD_SHEET.clip(name).unwrap().0
+    }
+
+    #[test]
+    fn facing_follows_the_dominant_axis() {
+        let facing = |x, y| Facing::from_velocity(Vec2::new(x, y));
+        assert_eq!(facing(3.0, 1.0), Facing::Right);
+        assert_eq!(facing(-3.0, 1.0), Facing::Left);
+        assert_eq!(facing(0.5, -2.0), Facing::Up);
+        assert_eq!(facing(-0.5, 2.0), Facing::Down);
+        // Diagonals look up or down
+        assert_eq!(facing(1.0, 1.0), Facing::Down);
+        assert_eq!(facing(-1.0, -1.0), Facing::Up);
+    }
+
+    #[test]
+    fn walking_loops_through_the_clip_of_its_direction() {
+        let mut animator = Animator::new(&WIZARD_SHEET);
+        assert_eq!(frame(&animator), (0, row("idle")));
+
+        let east = Vec2::new(1.0, 0.0);
+        animator.update(0.01, east);
+        assert_eq!(frame(&animator), (0, row("walk_right")));
+        animator.update(0.25, east);
+        assert_eq!(frame(&animator), (2, row("walk_right")));
+        // Four frames of 0.12s, then back to the first
+        animator.update(0.25, east);
+        assert_eq!(frame(&animator), (0, row("walk_right")));
+
+        // Turning starts the new clip from its first frame
+        animator.update(0.05, Vec2::new(0.0, -1.0));
+        assert_eq!(frame(&animator), (0, row("walk_up")));
+        animator.update(0.05, Vec2::ZERO);
+        assert_eq!(frame(&animator), (0, row("idle")));
+    }
+
+    #[test]
+    fn casting_plays_to_the_end_before_walking_again() {
+        let mut animator = Animator::new(&CREATURE_SHEET);
+        animator.play("cast");
+        let west = Vec2::new(-1.0, 0.0);
+        animator.update(0.1, west);
+        assert_eq!(frame(&animator), (1, row("cast")));
+        animator.update(0.15, west);
+        // The last frame is held until the clip is over
+        assert_eq!(frame(&animator), (3, row("cast")));
+        animator.update(0.1, west);
+        assert_eq!(frame(&animator), (0, row("walk_left")));
+    }
+
+    #[test]
+    fn unknown_clips_show_the_first_frame() {
+        let mut animator = Animator::new(&WIZARD_SHEET);
+        animator.play("dance");
+        animator.update(0.0, Vec2::ZERO);
+        assert_eq!(frame(&animator), (0, row("idle")));
+        animator.play("dance");
+        assert_eq!(animator.source_rect(), Rect::new(0.0, 0.0, 32.0, 32.0));
+    }
+}
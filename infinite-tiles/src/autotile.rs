@@ -0,0 +1,128 @@
+// autotile.rs
+// edges where one terrain spills over onto its neighbor, picked by a 4-bit neighbor mask
+use crate::tile::{TileId, tile_def};
+use crate::types::TilePos;
+
+// Bits of an edge mask, one per side of a tile
+pub const NORTH: u8 = 1;
+pub const EAST: u8 = 2;
+pub const SOUTH: u8 = 4;
+pub const WEST: u8 = 8;
+
+/// Offset to the neighbor on each side, with that side's bit
+const SIDES: [(i32, i32, u8); 4] = [(0, -1, NORTH), (1, 0, EAST), (0, 1, SOUTH), (-1, 0, WEST)];
+
+/// Whether `neighbor` draws an edge over `tile`. Higher blends spill onto lower
+/// ones, e.g. grass onto sand and sand onto water, tiles with no blend keep hard edges.
+pub fn spills_onto(neighbor: TileId, tile: TileId) -> bool {
+    let (neighbor, tile) = (tile_def(neighbor).blend, tile_def(tile).blend);
+    tile > 0 && neighbor > tile
+}
+
+/// Sides of a tile that a neighbor spills over. `tile_at` is None for tiles that
+/// aren't loaded, those sides stay hard until the neighbor loads.
+pub fn edge_mask(pos: TilePos, tile_at: impl Fn(TilePos) -> Option<TileId>) -> u8 {
+    let Some(tile) = tile_at(pos) else {
+        return 0;
+    };
+    SIDES
+        .iter()
+        .filter(|&&(dx, dy, _)| {
+            pos.offset(dx, dy)
+                .and_then(&tile_at)
+                .is_some_and(|neighbor| spills_onto(neighbor, tile))
+        })
+        .fold(0, |mask, &(_, _, bit)| mask | bit)
+}
+
+/// Splits an edge mask by the terrain spilling over each side, lowest blend first so
+/// the terrain on top is drawn last. Returns the tile and the sides it covers.
+pub fn transitions(
+    pos: TilePos,
+    mask: u8,
+    tile_at: impl Fn(TilePos) -> Option<TileId>,
+) -> Vec<(TileId, u8)> {
+    let mut transitions: Vec<(TileId, u8)> = Vec::new();
+    for (dx, dy, bit) in SIDES {
+        if mask & bit == 0 {
+            continue;
+        }
+        let Some(neighbor) = pos.offset(dx, dy).and_then(&tile_at) else {
+            continue;
+        };
+        match transitions.iter_mut().find(|(tile, _)| *tile == neighbor) {
+            Some((_, sides)) => *sides |= bit,
+            None => transitions.push((neighbor, bit)),
+        }
+    }
+    transitions.sort_by_key(|&(tile, _)| tile_def(tile).blend);
+    transitions
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tile::{GRASS, SAND, STONE, WATER};
+    use std::collections::HashMap;
+
+    /// Tiles around the corner where four chunks meet at tile (0, 0), anything
+    /// else counts as not loaded
+    fn corner(tiles: &[(i32, i32, TileId)]) -> impl Fn(TilePos) -> Option<TileId> + use<> {
+        let tiles: HashMap<TilePos, TileId> = tiles
+            .iter()
+            .map(|&(x, y, tile)| (TilePos { x, y }, tile))
+            .collect();
+        move |pos| tiles.get(&pos).copied()
+    }
+
+    #[test]
+    fn neighbors_spill_over_a_chunk_border() {
+        // Sand at the corner tile of one chunk, its west and north neighbors in others
+        let tile_at = corner(&[
+            (0, 0, SAND),
+            (-1, 0, GRASS),
+            (0, -1, GRASS),
+            (1, 0, WATER),
+            (0, 1, SAND),
+        ]);
+        let mask = edge_mask(TilePos { x: 0, y: 0 }, &tile_at);
+        assert_eq!(mask, NORTH | WEST);
+        assert_eq!(
+            transitions(TilePos { x: 0, y: 0 }, mask, &tile_at),
+            [(GRASS, NORTH | WEST)]
+        );
+
+        // And the other way, sand across the border spills onto the water
+        assert_eq!(edge_mask(TilePos { x: 1, y: 0 }, &tile_at), WEST);
+        // Grass is on top, nothing spills onto it
+        assert_eq!(edge_mask(TilePos { x: -1, y: 0 }, &tile_at), 0);
+    }
+
+    #[test]
+    fn sides_facing_unloaded_chunks_stay_hard() {
+        let tile_at = corner(&[(-1, -1, WATER), (0, -1, GRASS)]);
+        // West and north are past the loaded tiles
+        assert_eq!(edge_mask(TilePos { x: -1, y: -1 }, &tile_at), EAST);
+        assert_eq!(edge_mask(TilePos { x: -1, y: -2 }, &tile_at), 0);
+    }
+
+    #[test]
+    fn transitions_draw_the_highest_blend_last() {
+        let pos = TilePos { x: 0, y: -1 };
+        let tile_at = corner(&[
+            (0, -1, WATER),
+            (0, -2, GRASS),
+            (1, -1, SAND),
+            (0, 0, GRASS),
+            (-1, -1, STONE),
+        ]);
+        let mask = edge_mask(pos, &tile_at);
+        assert_eq!(mask, NORTH | EAST | SOUTH);
+        assert_eq!(
+            transitions(pos, mask, &tile_at),
+            [(SAND, EAST), (GRASS, NORTH | SOUTH)]
+        );
+        // Only the sides in the mask are split
+        assert_eq!(transitions(pos, EAST, &tile_at), [(SAND, EAST)]);
+    }
+}
@@ -0,0 +1,236 @@
+// bench.rs
+// timings of chunk generation, tile reads and drawing, run with
+// `cargo run --release -- --bench`, add `--no-window` to skip drawing. Tile
+// storage is timed next to the Vec<Vec> rows chunks used to keep their tiles in.
+use crate::camera::Camera;
+use crate::casting::CastPlan;
+use crate::config;
+use crate::grid::TileGrid;
+use crate::tile::{Layer, TileId};
+use crate::types::{ChunkPos, LocalTilePos, TilePos, WorldPos};
+use crate::world::{Chunk, World};
+use macroquad::prelude::*;
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+const SEED: u64 = 0xBE7C4;
+const STREAM_JUMPS: i32 = 40; // Times the whole cache area is streamed in fresh
+const READ_PASSES: u32 = 1000; // Times every tile of every loaded layer is read
+const BUILD_PASSES: u32 = 200; // Times every layer of every loaded chunk is stored again
+const DRAW_FRAMES: u32 = 300;
+const HEADLESS_VIEW: Vec2 = Vec2::new(800.0, 600.0); // The default window
+
+/// Prints how long streaming in chunks, storing and reading tiles take, none of
+/// it needs a window
+pub fn run_headless() {
+    let (chunks, elapsed) = time_generation();
+    println!(
+        "generation: {} chunks in {:.1} ms, {:.1} us per chunk",
+        chunks,
+        elapsed.as_secs_f64() * 1e3,
+        elapsed.as_secs_f64() * 1e6 / chunks as f64
+    );
+
+    let (layers, packed, rows) = time_builds();
+    println!(
+        "layer builds: {} layers, {:.2} us per layer packed, {:.2} us as Vec<Vec> rows",
+        layers,
+        packed.as_secs_f64() * 1e6 / layers as f64,
+        rows.as_secs_f64() * 1e6 / layers as f64
+    );
+
+    let (tiles, packed, rows) = time_reads(READ_PASSES, None);
+    println!(
+        "tile reads: {} tiles, {:.2} ns per tile packed, {:.2} ns as Vec<Vec> rows",
+        tiles,
+        packed.as_secs_f64() * 1e9 / tiles as f64,
+        rows.as_secs_f64() * 1e9 / tiles as f64
+    );
+
+    // The reads drawing a frame of the default window does, without drawing
+    let camera = Camera::centered_on(WorldPos::default(), HEADLESS_VIEW);
+    let (tiles, packed, rows) = time_reads(DRAW_FRAMES, Some(&camera));
+    println!(
+        "frame reads: {} tiles a frame, {:.1} us a frame packed, {:.1} us as Vec<Vec> rows",
+        tiles / DRAW_FRAMES as usize,
+        packed.as_secs_f64() * 1e6 / DRAW_FRAMES as f64,
+        rows.as_secs_f64() * 1e6 / DRAW_FRAMES as f64
+    );
+}
+
+/// Prints how long drawing the world takes
+pub async fn run_drawing() {
+    let elapsed = time_drawing().await;
+    println!(
+        "drawing: {} frames in {:.1} ms, {:.2} ms per frame",
+        DRAW_FRAMES,
+        elapsed.as_secs_f64() * 1e3,
+        elapsed.as_secs_f64() * 1e3 / DRAW_FRAMES as f64
+    );
+}
+
+/// A chunk's layers the way chunks stored them before `TileGrid`, a row of tile
+/// ids per row of the chunk
+struct RowLayers {
+    layers: Vec<Vec<Vec<TileId>>>,
+}
+
+impl RowLayers {
+    fn new(chunk: &Chunk) -> Self {
+        let size = config::CHUNK_SIZE;
+        RowLayers {
+            layers: Layer::ALL
+                .iter()
+                .map(|&layer| {
+                    (0..size)
+                        .map(|y| {
+                            (0..size)
+                                .map(|x| chunk.get(layer, LocalTilePos { x, y }))
+                                .collect()
+                        })
+                        .collect()
+                })
+                .collect(),
+        }
+    }
+
+    fn get(&self, layer: Layer, pos: LocalTilePos) -> TileId {
+        self.layers[layer as usize][pos.y as usize][pos.x as usize]
+    }
+}
+
+/// Jumps far enough each time that every chunk around the center is new, so each
+/// jump generates, populates and autotiles a full cache area
+fn time_generation() -> (usize, Duration) {
+    let mut world = World::with_textures(SEED, None);
+    let span = config::CACHE_CHUNKS_RADIUS * 2 + 1;
+    let start = Instant::now();
+    for jump in 0..STREAM_JUMPS {
+        world.stream_chunks(ChunkPos {
+            x: jump * span,
+            y: 0,
+        });
+    }
+    let chunks = (STREAM_JUMPS * span * span) as usize;
+    (chunks, start.elapsed())
+}
+
+/// Stores every layer of the loaded chunks again, packed and as rows
+fn time_builds() -> (usize, Duration, Duration) {
+    let mut world = World::with_textures(SEED, None);
+    world.stream_chunks(ChunkPos { x: 0, y: 0 });
+    let chunks: Vec<&Chunk> = world
+        .loaded_chunks()
+        .filter_map(|pos| world.chunk(pos))
+        .collect();
+    let size = config::CHUNK_SIZE as usize;
+
+    let start = Instant::now();
+    for _ in 0..BUILD_PASSES {
+        for chunk in &chunks {
+            for layer in Layer::ALL {
+                black_box(TileGrid::from_fn(|local| chunk.get(layer, local)));
+            }
+        }
+    }
+    let packed = start.elapsed();
+
+    let start = Instant::now();
+    for _ in 0..BUILD_PASSES {
+        for chunk in &chunks {
+            for layer in Layer::ALL {
+                let mut rows = vec![vec![0; size]; size];
+                for local in LocalTilePos::all() {
+                    rows[local.y as usize][local.x as usize] = chunk.get(layer, local);
+                }
+                black_box(rows);
+            }
+        }
+    }
+    let rows = start.elapsed();
+
+    let layers = BUILD_PASSES as usize * chunks.len() * Layer::COUNT;
+    (layers, packed, rows)
+}
+
+/// Reads every tile of every layer of the loaded chunks, or of the chunks in view
+/// of `camera`, in the order drawing and the simulation go through them. Times
+/// the chunks' packed grids, then the same tiles as rows.
+fn time_reads(passes: u32, camera: Option<&Camera>) -> (usize, Duration, Duration) {
+    let mut world = World::with_textures(SEED, None);
+    world.stream_chunks(ChunkPos { x: 0, y: 0 });
+    let chunks: Vec<&Chunk> = match camera {
+        Some(camera) => {
+            let (start, end) = camera.get_visible_range();
+            (start.chunk.y..=end.chunk.y)
+                .flat_map(|y| (start.chunk.x..=end.chunk.x).map(move |x| ChunkPos { x, y }))
+                .filter_map(|pos| world.chunk(pos))
+                .collect()
+        }
+        None => world
+            .loaded_chunks()
+            .filter_map(|pos| world.chunk(pos))
+            .collect(),
+    };
+    let rows: Vec<RowLayers> = chunks.iter().map(|chunk| RowLayers::new(chunk)).collect();
+
+    let start = Instant::now();
+    let mut sum: TileId = 0;
+    for _ in 0..passes {
+        for chunk in black_box(&chunks) {
+            for layer in Layer::ALL {
+                for local in LocalTilePos::all() {
+                    sum = sum.wrapping_add(chunk.get(layer, local));
+                }
+            }
+        }
+    }
+    let packed = start.elapsed();
+
+    let start = Instant::now();
+    for _ in 0..passes {
+        for chunk in black_box(&rows) {
+            for layer in Layer::ALL {
+                for local in LocalTilePos::all() {
+                    sum = sum.wrapping_add(chunk.get(layer, local));
+                }
+            }
+        }
+    }
+    let rows_elapsed = start.elapsed();
+    black_box(sum);
+
+    let tiles = passes as usize * chunks.len() * Layer::COUNT * config::CHUNK_AREA;
+    (tiles, packed, rows_elapsed)
+}
+
+/// CPU time spent issuing the draw calls of the world around the origin, with
+/// everything in view explored so no tile hides under the fog
+async fn time_drawing() -> Duration {
+    let mut world = World::new(SEED).await;
+    let center = ChunkPos { x: 0, y: 0 };
+    world.stream_chunks(center);
+    world.load_textures().await;
+    let radius = config::VISIBLE_CHUNKS_RADIUS * config::CHUNK_SIZE;
+    world.cast(
+        CastPlan::Reveal {
+            center: TilePos { x: 0, y: 0 },
+            radius,
+        },
+        WorldPos::default(),
+    );
+
+    let mut elapsed = Duration::ZERO;
+    for _ in 0..DRAW_FRAMES {
+        let camera = Camera::centered_on(
+            WorldPos::default(),
+            Vec2::new(screen_width(), screen_height()),
+        );
+        clear_background(BLACK);
+        let start = Instant::now();
+        world.draw(&camera, |_| WHITE);
+        elapsed += start.elapsed();
+        next_frame().await;
+    }
+    elapsed
+}
@@ -0,0 +1,125 @@
+// biome.rs
+// large areas of land sharing their ground and their climate
+use crate::config;
+use crate::hash;
+use crate::tile::{GRASS, ICE, SAND, STONE, TileId, WATER};
+use crate::types::ChunkPos;
+
+const BIOME_SALT: u64 = 0x60;
+const SITE_SALT: u64 = 0x61;
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum Biome {
+    Meadow,
+    Tundra, // Frozen ground, snows instead of raining
+    Desert, // Rarely rains, but when it does it storms
+}
+
+impl Biome {
+    /// Ground tiles generated here, with how often each shows up relative to the others
+    fn terrain(self) -> &'static [(TileId, f32)] {
+        match self {
+            Biome::Meadow => &[(GRASS, 0.6), (WATER, 0.15), (STONE, 0.1), (SAND, 0.15)],
+            Biome::Tundra => &[(GRASS, 0.2), (WATER, 0.1), (STONE, 0.2), (ICE, 0.5)],
+            Biome::Desert => &[(GRASS, 0.05), (WATER, 0.05), (STONE, 0.15), (SAND, 0.75)],
+        }
+    }
+
+    /// Picks a ground tile by the terrain weights, `roll` is in [0, 1)
+    pub fn ground(self, roll: f32) -> TileId {
+        let terrain = self.terrain();
+        let total: f32 = terrain.iter().map(|&(_, weight)| weight).sum();
+        let mut remaining = roll * total;
+        for &(tile, weight) in terrain {
+            if remaining < weight {
+                return tile;
+            }
+            remaining -= weight;
+        }
+        terrain[0].0
+    }
+}
+
+/// The world is split into square cells, each with a site at a random spot inside
+/// it and a biome. A chunk belongs to the closest site, so borders between biomes
+/// wander instead of following the cell grid.
+pub fn biome_at(seed: u64, chunk: ChunkPos) -> Biome {
+    let size = config::BIOME_CELL_CHUNKS;
+    let (cell_x, cell_y) = (chunk.x.div_euclid(size), chunk.y.div_euclid(size));
+
+    let mut closest = (i64::MAX, Biome::Meadow);
+    for y in cell_y - 1..=cell_y + 1 {
+        for x in cell_x - 1..=cell_x + 1 {
+            let site = hash::hash2(seed, x, y, SITE_SALT);
+            let site_x = x as i64 * size as i64 + (site % size as u64) as i64;
+            let site_y = y as i64 * size as i64 + (site >> 32) as i64 % size as i64;
+            let (dx, dy) = (site_x - chunk.x as i64, site_y - chunk.y as i64);
+            let distance = dx * dx + dy * dy;
+            if distance < closest.0 {
+                closest = (distance, cell_biome(seed, x, y));
+            }
+        }
+    }
+    closest.1
+}
+
+fn cell_biome(seed: u64, x: i32, y: i32) -> Biome {
+    match hash::unit(hash::hash2(seed, x, y, BIOME_SALT)) {
+        roll if roll < 0.6 => Biome::Meadow,
+        roll if roll < 0.8 => Biome::Tundra,
+        _ => Biome::Desert,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chunks(radius: i32) -> impl Iterator<Item = ChunkPos> {
+        (-radius..radius).flat_map(move |y| (-radius..radius).map(move |x| ChunkPos { x, y }))
+    }
+
+    #[test]
+    fn every_biome_shows_up_and_mostly_in_one_piece() {
+        let seed = 99;
+        let radius = 6 * config::BIOME_CELL_CHUNKS;
+        let mut seen = Vec::new();
+        let (mut same, mut total) = (0, 0);
+        for chunk in chunks(radius) {
+            let biome = biome_at(seed, chunk);
+            if !seen.contains(&biome) {
+                seen.push(biome);
+            }
+            let east = ChunkPos {
+                x: chunk.x + 1,
+                y: chunk.y,
+            };
+            same += (biome_at(seed, east) == biome) as u32;
+            total += 1;
+        }
+        assert_eq!(seen.len(), 3);
+        // Neighboring chunks almost always share a biome, even across cell borders
+        assert!(same * 10 > total * 8, "{} of {}", same, total);
+    }
+
+    #[test]
+    fn biomes_only_depend_on_the_seed() {
+        for chunk in chunks(30) {
+            assert_eq!(biome_at(1, chunk), biome_at(1, chunk));
+        }
+        assert!(chunks(30).any(|chunk| biome_at(1, chunk) != biome_at(2, chunk)));
+    }
+
+    #[test]
+    fn ground_follows_the_terrain_weights() {
+        assert_eq!(Biome::Meadow.ground(0.0), GRASS);
+        assert_eq!(Biome::Meadow.ground(0.65), WATER);
+        assert_eq!(Biome::Desert.ground(0.5), SAND);
+        assert_eq!(Biome::Tundra.ground(0.99), ICE);
+        // Rolls never leave the table, even at its very end
+        for biome in [Biome::Meadow, Biome::Tundra, Biome::Desert] {
+            let ground = biome.ground(0.999_999_9);
+            assert!(biome.terrain().iter().any(|&(tile, _)| tile == ground));
+        }
+    }
+}
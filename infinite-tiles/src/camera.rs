@@ -1,61 +1,39 @@
 use crate::config;
-use crate::types::ChunkPos;
+use crate::types::WorldPos;
 use macroquad::prelude::*;
 
+/// Where the view is looking. It is worked out from the player every frame,
+/// so it carries no state the simulation depends on.
 pub struct Camera {
-    pub position: Vec2,
+    pub position: WorldPos, // Top-left corner of the view
     pub viewport_size: Vec2,
-    last_chunk: ChunkPos,
 }
 
 impl Camera {
-    pub fn new() -> Self {
+    /// A view of `viewport_size` centered on `target`
+    pub fn centered_on(target: WorldPos, viewport_size: Vec2) -> Self {
         Camera {
-            position: Vec2::ZERO,
-            viewport_size: Vec2::new(screen_width(), screen_height()),
-            last_chunk: ChunkPos { x: 0, y: 0 },
+            position: target - viewport_size * 0.5,
+            viewport_size,
         }
     }
 
-    pub fn update(&mut self, target_pos: Vec2) {
-        // follow target with camera
-        self.position = target_pos - self.viewport_size * 0.5;
-        // update viewport in case of window resizing
-        self.viewport_size = Vec2::new(screen_width(), screen_height());
-    }
-
-    pub fn chunk_changed(&mut self, current_pos: Vec2) -> bool {
-        let current_chunk = ChunkPos::from_world_pos(current_pos.x, current_pos.y);
-        if current_chunk != self.last_chunk {
-            self.last_chunk = current_chunk;
-            true
-        } else {
-            false
-        }
-    }
-
-    pub fn world_to_screen(&self, world_pos: Vec2) -> Vec2 {
+    /// Screen pixels are worked out from the offset to the view, never from the
+    /// distance to the world origin, so they stay exact far from it
+    pub fn world_to_screen(&self, world_pos: WorldPos) -> Vec2 {
         world_pos - self.position
     }
 
-    pub fn screen_to_world(&self, screen_pos: Vec2) -> Vec2 {
-        screen_pos + self.position
+    pub fn screen_to_world(&self, screen_pos: Vec2) -> WorldPos {
+        self.position + screen_pos
     }
 
-    pub fn get_visible_range(&self) -> (Vec2, Vec2) {
+    pub fn get_visible_range(&self) -> (WorldPos, WorldPos) {
         let start = self.position;
         let end = self.position + self.viewport_size;
 
         // closure to add one tile to ensure smooth scrolling
-        (
-            Vec2::new(
-                start.x - config::TILE_SIZE as f32,
-                start.y - config::TILE_SIZE as f32,
-            ),
-            Vec2::new(
-                end.x - config::TILE_SIZE as f32,
-                end.y - config::TILE_SIZE as f32,
-            ),
-        )
+        let tile = Vec2::splat(config::TILE_SIZE as f32);
+        (start - tile, end - tile)
     }
 }
@@ -0,0 +1,191 @@
+// casting.rs
+// turns a cast spell into a projectile or an area of effect
+use crate::config;
+use crate::hash;
+use crate::shape::Shape;
+use crate::spell::{Element, Spell, Verb};
+use crate::tile::{self, Layer, TileId, tile_def};
+use crate::types::{TilePos, WorldPos};
+use crate::weather::Weather;
+use macroquad::prelude::*;
+use std::f32::consts::{FRAC_PI_3, FRAC_PI_6};
+
+/// What happens to the tiles and creatures a spell reaches
+#[derive(Clone, Copy, Debug)]
+pub struct AreaEffect {
+    pub element: Option<Element>,
+    pub damage: f32,
+}
+
+/// How a spell reaches the world
+pub enum CastPlan {
+    /// Travels until it hits something solid, then affects a circle around the impact
+    Projectile {
+        velocity: Vec2,
+        frames: u32,
+        blast_radius: i32,
+        effect: AreaEffect,
+    },
+    /// Affects a shape immediately
+    Area {
+        origin: TilePos,
+        shape: Shape,
+        effect: AreaEffect,
+    },
+    /// Uncovers a disc of fog around a distant tile, walls don't block it
+    Reveal { center: TilePos, radius: i32 },
+}
+
+/// Works out the shape, size and strength of a spell cast from `caster` towards `aim`
+/// under the caster's `weather`. `chaos` is a hash deciding how a chaotic spell goes wrong.
+pub fn plan_cast(
+    spell: &Spell,
+    caster: WorldPos,
+    aim: WorldPos,
+    weather: Weather,
+    chaos: u64,
+) -> Option<CastPlan> {
+    // An element on its own is thrown as a bolt
+    let verb = spell.verb().unwrap_or(Verb::Bolt);
+    let mut direction = (aim - caster).normalize_or(Vec2::X);
+    let mut element = spell.element();
+
+    // Chaotic spells veer off course and sometimes come out as the wrong element
+    if spell.is_chaotic() {
+        let roll = |salt| hash::unit(hash::hash1(chaos, salt, 0));
+        let spread = (roll(0) * 2.0 - 1.0) * config::CHAOS_SPREAD;
+        direction = Vec2::from_angle(spread).rotate(direction);
+        if roll(1) < 0.5 {
+            let index = (roll(2) * Element::ALL.len() as f32) as usize;
+            element = Some(Element::ALL[index]);
+        }
+    }
+
+    let mut scale = spell.potency();
+    if spell.has_modifier("greater") {
+        scale *= 1.5;
+    }
+    if spell.has_modifier("lesser") {
+        scale *= 0.5;
+    }
+    // Storms charge the air
+    if weather == Weather::Storm && element == Some(Element::Lightning) {
+        scale *= config::STORM_LIGHTNING_BOOST;
+    }
+    let wide = spell.has_modifier("wide");
+    let size = |base: f32| ((base * scale).round() as i32).max(1);
+
+    let effect = AreaEffect {
+        element,
+        damage: config::SPELL_BASE_DAMAGE * scale,
+    };
+    let origin = caster.tile();
+
+    let shape = match verb {
+        // Lightning doesn't travel, it strikes the whole path at once
+        Verb::Bolt if element == Some(Element::Lightning) => Shape::Bolt {
+            direction,
+            length: size(10.0),
+        },
+        Verb::Bolt => {
+            let speed = if spell.has_modifier("swift") {
+                config::PROJECTILE_SPEED * 2.0
+            } else {
+                config::PROJECTILE_SPEED
+            };
+            return Some(CastPlan::Projectile {
+                velocity: direction * speed,
+                frames: config::PROJECTILE_RANGE_FRAMES,
+                blast_radius: size(1.0) + i32::from(wide),
+                effect,
+            });
+        }
+        Verb::Burst => Shape::Circle {
+            radius: size(3.0) + i32::from(wide),
+        },
+        Verb::Cone => Shape::Cone {
+            direction,
+            range: size(5.0),
+            half_angle: if wide { FRAC_PI_3 } else { FRAC_PI_6 },
+        },
+        Verb::Line => {
+            // Reaches the aimed tile, but no further than the spell's range
+            let reach = (aim - caster)
+                .length()
+                .min((size(8.0) * config::TILE_SIZE) as f32);
+            let target = caster + direction * reach;
+            Shape::Line { to: target.tile() }
+        }
+        Verb::Wall => Shape::Wall {
+            direction,
+            distance: 2,
+            half_width: size(2.0) + i32::from(wide),
+        },
+        Verb::Reveal => {
+            let reach = (aim - caster)
+                .length()
+                .min((size(24.0) * config::TILE_SIZE) as f32);
+            let target = caster + direction * reach;
+            return Some(CastPlan::Reveal {
+                center: target.tile(),
+                radius: size(4.0) + 2 * i32::from(wide),
+            });
+        }
+    };
+
+    Some(CastPlan::Area {
+        origin,
+        shape,
+        effect,
+    })
+}
+
+/// Color used to draw things made of an element
+pub fn element_color(element: Option<Element>) -> Color {
+    match element {
+        Some(Element::Fire) => ORANGE,
+        Some(Element::Water) => BLUE,
+        Some(Element::Ice) => SKYBLUE,
+        Some(Element::Earth) => BROWN,
+        Some(Element::Lightning) => YELLOW,
+        Some(Element::Light) => WHITE,
+        None => PURPLE,
+    }
+}
+
+/// Radius in tiles of the light something made of an element gives off, 0 for none
+pub fn element_glow(element: Option<Element>) -> i32 {
+    match element {
+        Some(Element::Fire | Element::Lightning) => 3,
+        Some(Element::Light) => 6,
+        _ => 0,
+    }
+}
+
+/// Tile an element leaves behind when it lands on a tile of a layer, None leaves
+/// it unchanged
+pub fn element_residue(element: Element, layer: Layer, tile: TileId) -> Option<TileId> {
+    let def = tile_def(tile);
+    match layer {
+        Layer::Ground => match element {
+            Element::Fire | Element::Lightning if def.flammable => Some(tile::FIRE),
+            Element::Fire if tile == tile::ICE => Some(tile::WATER),
+            Element::Water if tile == tile::FIRE => Some(tile::ASH),
+            Element::Water if !def.solid => Some(tile::WATER),
+            Element::Ice if tile == tile::WATER => Some(tile::ICE),
+            Element::Earth => Some(tile::STONE),
+            _ => None,
+        },
+        // Whatever lies or stands on the ground burns up or is buried
+        Layer::Decoration | Layer::Object => match element {
+            Element::Fire | Element::Lightning if def.flammable => Some(tile::EMPTY),
+            Element::Earth => Some(tile::EMPTY),
+            _ => None,
+        },
+        Layer::Overlay => match element {
+            Element::Ice => Some(tile::FROST),
+            Element::Fire if tile == tile::FROST => Some(tile::EMPTY),
+            _ => None,
+        },
+    }
+}
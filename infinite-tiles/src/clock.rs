@@ -0,0 +1,127 @@
+// clock.rs
+// in-game time, advanced one simulation tick at a time
+use crate::config;
+
+/// Part of the day, each lasting a fixed fraction of it
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum DayPhase {
+    Dawn,
+    Day,
+    Dusk,
+    Night,
+}
+
+impl DayPhase {
+    pub fn name(self) -> &'static str {
+        match self {
+            DayPhase::Dawn => "dawn",
+            DayPhase::Day => "day",
+            DayPhase::Dusk => "dusk",
+            DayPhase::Night => "night",
+        }
+    }
+}
+
+/// Ticks since the world began. Only the simulation advances it, so replays see
+/// the same time of day at the same tick.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct WorldClock {
+    ticks: u64,
+}
+
+impl WorldClock {
+    /// A new world wakes up in the morning of its first day
+    pub fn new() -> Self {
+        WorldClock {
+            ticks: (config::DAY_START * Self::ticks_per_day() as f32) as u64,
+        }
+    }
+
+    pub fn from_ticks(ticks: u64) -> Self {
+        WorldClock { ticks }
+    }
+
+    fn ticks_per_day() -> u64 {
+        (config::DAY_LENGTH_SECONDS * config::TICKS_PER_SECOND) as u64
+    }
+
+    pub fn ticks(self) -> u64 {
+        self.ticks
+    }
+
+    pub fn advance(&mut self) {
+        self.ticks += 1;
+    }
+
+    /// Fraction of the current day, 0 is midnight and 0.5 noon
+    pub fn time_of_day(self) -> f32 {
+        (self.ticks % Self::ticks_per_day()) as f32 / Self::ticks_per_day() as f32
+    }
+
+    /// Days since the world began, the first one is day 1
+    pub fn day(self) -> u64 {
+        self.ticks / Self::ticks_per_day() + 1
+    }
+
+    pub fn phase(self) -> DayPhase {
+        match self.time_of_day() {
+            t if t < config::DAWN_START => DayPhase::Night,
+            t if t < config::DAY_START => DayPhase::Dawn,
+            t if t < config::DUSK_START => DayPhase::Day,
+            t if t < config::NIGHT_START => DayPhase::Dusk,
+            _ => DayPhase::Night,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// First tick of the day whose time of day reaches `fraction`
+    fn tick_at(day: u64, fraction: f32) -> u64 {
+        let per_day = WorldClock::ticks_per_day();
+        (day - 1) * per_day + (fraction * per_day as f32).ceil() as u64
+    }
+
+    #[test]
+    fn phases_change_exactly_at_their_start() {
+        let boundaries = [
+            (0.0, DayPhase::Night),
+            (config::DAWN_START, DayPhase::Dawn),
+            (config::DAY_START, DayPhase::Day),
+            (config::DUSK_START, DayPhase::Dusk),
+            (config::NIGHT_START, DayPhase::Night),
+        ];
+        // Late days too, where the tick count is far beyond f32 precision
+        for day in [1, 2, 1_000_000] {
+            for pair in boundaries.windows(2) {
+                let ((_, before), (start, after)) = (pair[0], pair[1]);
+                let tick = tick_at(day, start);
+                assert_eq!(WorldClock::from_ticks(tick - 1).phase(), before);
+                assert_eq!(WorldClock::from_ticks(tick).phase(), after);
+            }
+        }
+    }
+
+    #[test]
+    fn a_new_day_begins_at_midnight() {
+        let midnight = WorldClock::from_ticks(tick_at(2, 0.0));
+        assert_eq!(midnight.day(), 2);
+        assert_eq!(midnight.time_of_day(), 0.0);
+        assert_eq!(midnight.phase(), DayPhase::Night);
+
+        let before = WorldClock::from_ticks(midnight.ticks() - 1);
+        assert_eq!(before.day(), 1);
+        assert_eq!(before.phase(), DayPhase::Night);
+    }
+
+    #[test]
+    fn new_worlds_start_on_the_first_morning() {
+        let mut clock = WorldClock::new();
+        assert_eq!(clock.day(), 1);
+        assert_eq!(clock.phase(), DayPhase::Day);
+        clock.advance();
+        assert_eq!(clock.ticks(), WorldClock::new().ticks() + 1);
+    }
+}
@@ -1,15 +1,81 @@
-/// config.rs
-/// configuration for chunk rendering
-/// gpu textures
-/// player speed
-/// cache loading
+// config.rs
+// configuration for chunk rendering
+// gpu textures
+// player speed
+// cache loading
 
 pub const TILE_SIZE: i32 = 64;
 pub const CHUNK_SIZE: i32 = 16;
+pub const CHUNK_AREA: usize = (CHUNK_SIZE * CHUNK_SIZE) as usize; // tiles in a chunk
+pub const CHUNK_PIXELS: f32 = (CHUNK_SIZE * TILE_SIZE) as f32; // world pixels across a chunk
 pub const VISIBLE_CHUNKS_RADIUS: i32 = 2;
 pub const PRELOAD_CHUNKS_RADIUS: i32 = 3;
 pub const CACHE_CHUNKS_RADIUS: i32 = 4;
 pub const PLAYER_SIZE: f32 = 32.0;
 pub const PLAYER_SPEED: f32 = 5.0;
+pub const PLAYER_SIGHT_RADIUS: i32 = 8; // tiles the wizard sees around them, walls block the view
+pub const FOG_SIGHT_RADIUS: i32 = 4; // same, while the region is fogged in
+pub const STAFF_LIGHT_RADIUS: i32 = 5; // tiles lit by the wizard's staff
+pub const PLAYER_MAX_HEALTH: f32 = 100.0;
+pub const CREATURE_SPEED: f32 = 1.5;
+pub const CREATURE_SPAWN_CHANCE: f32 = 0.3; // chance a freshly generated chunk holds a creature
+pub const CREATURE_NIGHT_SPAWN_CHANCE: f32 = 0.6; // same, for chunks first reached at night
+pub const CREATURE_FEAR_RADIUS: i32 = 4; // tiles from a fire within which creatures run from it
+pub const CREATURE_FORGET_RADIUS: i32 = 16; // chunks away from the wizard past which sleeping creatures are dropped
+pub const CREATURE_NOTICE_RADIUS: i32 = 6; // tiles from the wizard within which creatures walk up to them
+pub const PATH_SEARCH_TILES: usize = 4096; // tiles a path search looks at before giving up
+pub const PATH_CACHE_SIZE: usize = 256; // paths remembered until one of their chunks changes
 pub const MAX_GPU_TEXTURES: usize = 64;
 pub const MAX_RAM_TEXTURES: usize = 256;
+pub const SAVE_DIR: &str = "saves";
+pub const SAVE_SLOTS: usize = 3;
+pub const INPUT_CONFIG_PATH: &str = "input.cfg";
+pub const REPLAY_DIR: &str = "replays";
+
+// time of day, as fractions of a day: 0 is midnight and 0.5 noon
+pub const DAY_LENGTH_SECONDS: f32 = 600.0;
+pub const DAWN_START: f32 = 0.2;
+pub const DAY_START: f32 = 0.3; // also when a new game starts
+pub const DUSK_START: f32 = 0.7;
+pub const NIGHT_START: f32 = 0.8;
+
+// biomes and weather
+pub const BIOME_CELL_CHUNKS: i32 = 12; // rough width of a biome, borders between them wander
+pub const WEATHER_REGION_CHUNKS: i32 = 8; // width of the square of chunks sharing a sky
+pub const WEATHER_PERIOD_SECONDS: f32 = 120.0; // how long the weather holds before it may turn
+pub const WEATHER_PARTICLES: usize = 300; // drops or flakes on screen, storms have twice as many
+pub const STORM_LIGHTNING_BOOST: f32 = 1.5; // strength multiplier of lightning cast in a storm
+
+// lighting
+pub const MAX_LIGHT_RADIUS: i32 = 6; // lights further off screen than this are ignored
+pub const MAX_LIGHTS: usize = 48; // closest lights to the view center kept each frame
+
+// particles
+pub const MAX_PARTICLES: usize = 2000; // new particles are dropped while this many are alive
+
+// maps
+pub const MINIMAP_RADIUS_CHUNKS: i32 = 2; // chunks shown around the player's chunk
+pub const MINIMAP_TILE_PIXELS: f32 = 2.0;
+
+// incantation matching
+pub const FUZZY_WEAK_DISTANCE: usize = 1; // edits still producing a weakened spell
+pub const FUZZY_CHAOS_DISTANCE: usize = 2; // edits still producing a chaotic spell
+pub const FUZZY_HINT_DISTANCE: usize = 2; // edits within which discovered words are hinted
+pub const FUZZY_MIN_WORD_LEN: usize = 3; // shorter inputs must match exactly
+pub const FUZZY_CHARS_PER_EDIT: usize = 3; // word length needed per tolerated edit
+pub const CONSOLE_LOG_LINES: usize = 5;
+
+// spell effects
+pub const SPELL_BASE_DAMAGE: f32 = 5.0;
+pub const PROJECTILE_SPEED: f32 = 10.0;
+pub const PROJECTILE_RANGE_FRAMES: u32 = 90;
+pub const CHAOS_SPREAD: f32 = 0.8; // max radians a chaotic spell veers off its aim
+
+// terrain generation
+pub const TREE_CHANCE: f32 = 0.05; // chance a grass tile grows a tree
+pub const FLOWERS_CHANCE: f32 = 0.15; // chance a grass tile without a tree grows flowers
+
+// tile simulation
+pub const TICKS_PER_SECOND: f32 = 60.0; // fixed rate the world runs at, whatever the frame rate
+pub const MAX_TICKS_PER_FRAME: u32 = 4; // after a stall the game slows down rather than racing to catch up
+pub const CELLULAR_TICKS_PER_SECOND: f32 = 4.0;
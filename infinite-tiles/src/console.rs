@@ -0,0 +1,225 @@
+// console.rs
+// text console where incantations are typed and cast
+use crate::config;
+use crate::fuzzy::{FuzzyMatcher, WordMatch};
+use crate::input::{Action, InputFrame, InputMap};
+use crate::spell::{MatchQuality, Spell, SpellWord, Spellbook};
+use crate::tokenizer::{TokenCategory, Tokenizer};
+use macroquad::prelude::*;
+use std::collections::VecDeque;
+
+pub struct SpellConsole {
+    open: bool,
+    buffer: String,
+    log: VecDeque<String>,
+    tokenizer: Tokenizer,
+    completion: Option<&'static str>,
+}
+
+impl SpellConsole {
+    pub fn new() -> Self {
+        SpellConsole {
+            open: false,
+            buffer: String::new(),
+            log: VecDeque::with_capacity(config::CONSOLE_LOG_LINES),
+            tokenizer: Tokenizer::new(),
+            completion: None,
+        }
+    }
+
+    pub fn is_open(&self) -> bool {
+        self.open
+    }
+
+    pub fn open(&mut self) {
+        self.open = true;
+    }
+
+    /// Handles typing while the console is open, Cancel closes it and Cast casts.
+    /// Returns the spell when one was cast this frame.
+    pub fn update(
+        &mut self,
+        frame: &InputFrame,
+        input: &InputMap,
+        matcher: &FuzzyMatcher,
+        spellbook: &mut Spellbook,
+    ) -> Option<Spell> {
+        if !self.open {
+            return None;
+        }
+
+        for &c in &frame.chars {
+            if c.is_ascii_alphabetic() || c == ' ' {
+                self.buffer.push(c.to_ascii_lowercase());
+            }
+        }
+
+        if frame.pressed.contains(&KeyCode::Backspace) {
+            self.buffer.pop();
+        }
+
+        // Tab accepts the ghost-text suggestion for what is typed by now, letters
+        // typed in the same tick included
+        self.tokenizer.update(&self.buffer, matcher);
+        self.completion = self.tokenizer.completion(spellbook);
+        if let Some(completion) = self.completion
+            && frame.pressed.contains(&KeyCode::Tab)
+        {
+            self.buffer.push_str(completion);
+            self.buffer.push(' ');
+            self.tokenizer.update(&self.buffer, matcher);
+            self.completion = self.tokenizer.completion(spellbook);
+        }
+
+        if input.is_pressed(Action::Cancel, frame) {
+            self.close();
+        } else if input.is_pressed(Action::Cast, frame) {
+            let incantation = std::mem::take(&mut self.buffer);
+            let spell = self.cast(&incantation, matcher, spellbook);
+            if let Some(spell) = &spell {
+                self.push_log(format!("cast: {}", spell.describe()));
+            }
+            self.close();
+            return spell;
+        }
+
+        None
+    }
+
+    /// Resolves every typed word, reporting near misses and discoveries
+    fn cast(
+        &mut self,
+        incantation: &str,
+        matcher: &FuzzyMatcher,
+        spellbook: &mut Spellbook,
+    ) -> Option<Spell> {
+        let mut spell = Spell::default();
+
+        for typed in incantation.split_whitespace() {
+            let word_match = matcher.resolve(typed);
+
+            if let WordMatch::Known(word, quality) = word_match {
+                if quality == MatchQuality::Exact && spellbook.discover(word.text) {
+                    self.push_log(format!("discovered '{}'", word.text));
+                }
+                spell.words.push(SpellWord { word, quality });
+            }
+
+            if word_match != WordMatch::Unknown && spellbook.is_discovered(typed) {
+                continue;
+            }
+            match matcher.hint(typed, spellbook) {
+                Some(hint) => {
+                    self.push_log(format!("'{}' wavers... did you mean '{}'?", typed, hint))
+                }
+                None if word_match == WordMatch::Unknown => {
+                    self.push_log(format!("'{}' fizzles", typed))
+                }
+                None => {}
+            }
+        }
+
+        if spell.words.is_empty() {
+            self.push_log("nothing happens".to_string());
+            None
+        } else {
+            Some(spell)
+        }
+    }
+
+    pub fn close(&mut self) {
+        self.open = false;
+        self.buffer.clear();
+        self.completion = None;
+    }
+
+    pub fn push_log(&mut self, line: String) {
+        if self.log.len() >= config::CONSOLE_LOG_LINES {
+            self.log.pop_front();
+        }
+        self.log.push_back(line);
+    }
+
+    pub fn draw(&self) {
+        let line_height = 20.0;
+        let bottom = screen_height() - 10.0;
+
+        // Log sits above the prompt line
+        for (i, line) in self.log.iter().rev().enumerate() {
+            let y = bottom - line_height * (i as f32 + 2.0);
+            draw_text(line, 10.0, y, 20.0, DARKGRAY);
+        }
+
+        if self.open {
+            draw_rectangle(
+                0.0,
+                bottom - line_height,
+                screen_width(),
+                line_height + 10.0,
+                Color::new(0.0, 0.0, 0.0, 0.6),
+            );
+            self.draw_buffer(10.0, bottom);
+        }
+    }
+
+    /// Draws the incantation with each word colored by what it was recognized as
+    fn draw_buffer(&self, x: f32, y: f32) {
+        let font_size = 20;
+        let prompt = "> ";
+        let text_x = x + measure_text(prompt, None, font_size, 1.0).width;
+        draw_text(prompt, x, y, font_size as f32, WHITE);
+
+        // The tokenizer is refreshed in update, ignore it if the buffer changed since
+        for token in self.tokenizer.tokens() {
+            let Some(text) = self.buffer.get(token.start..token.end) else {
+                break;
+            };
+            let offset = measure_text(&self.buffer[..token.start], None, font_size, 1.0).width;
+            let color = match token.category() {
+                TokenCategory::Element => ORANGE,
+                TokenCategory::Verb => SKYBLUE,
+                TokenCategory::Modifier => VIOLET,
+                TokenCategory::Unknown => GRAY,
+            };
+            draw_text(text, text_x + offset, y, font_size as f32, color);
+        }
+
+        let cursor_x = text_x + measure_text(&self.buffer, None, font_size, 1.0).width;
+        if let Some(completion) = self.completion {
+            draw_text(
+                completion,
+                cursor_x,
+                y,
+                font_size as f32,
+                Color::new(1.0, 1.0, 1.0, 0.35),
+            );
+        }
+        draw_text("_", cursor_x, y, font_size as f32, WHITE);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tab_completes_letters_typed_in_the_same_tick() {
+        let (matcher, input) = (FuzzyMatcher::new(), InputMap::new());
+        let mut spellbook = Spellbook::new();
+        let mut console = SpellConsole::new();
+        console.open();
+        let typing = |chars: &str, tab: bool| InputFrame {
+            chars: chars.chars().collect(),
+            pressed: if tab { vec![KeyCode::Tab] } else { vec![] },
+            ..InputFrame::default()
+        };
+
+        console.update(&typing("fi", false), &input, &matcher, &mut spellbook);
+        assert_eq!(console.completion, Some("re"));
+
+        // "re" ends the word the old suggestion was for, Tab completes "bo"
+        console.update(&typing("re bo", true), &input, &matcher, &mut spellbook);
+        assert_eq!(console.buffer, "fire bolt ");
+        assert_eq!(console.completion, None);
+    }
+}
@@ -0,0 +1,527 @@
+// entity.rs
+// world objects that are not tiles: creatures, projectiles, dropped items
+use crate::animation::{Animator, SpriteSheet};
+use crate::camera::Camera;
+use crate::casting::AreaEffect;
+use crate::config;
+use crate::hash;
+use crate::lighting;
+use crate::raycast::RayHit;
+use crate::types::{ChunkPos, TilePos, TileRect, WorldPos};
+use crate::world::Unloaded;
+use macroquad::prelude::*;
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+/// How an entity is drawn
+#[derive(Clone, Copy, Debug)]
+pub struct Sprite {
+    pub color: Color,
+    pub size: f32,
+}
+
+impl Sprite {
+    /// Tiles the sprite overlaps when centered on `position`
+    pub fn covers(self, position: WorldPos) -> TileRect {
+        let half = Vec2::splat(self.size / 2.0);
+        TileRect::covering(position - half, position + half)
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Health {
+    pub current: f32,
+    pub max: f32,
+}
+
+/// Behaviour driving an entity's movement
+#[derive(Clone, Copy, Debug)]
+pub enum Ai {
+    Wander { direction: Vec2, frames_left: u32 },
+}
+
+/// Moves in a straight line until it hits a solid tile or its lifetime runs out,
+/// then releases its effect around where it stopped
+#[derive(Clone, Copy, Debug)]
+pub struct Projectile {
+    pub velocity: Vec2,
+    pub frames_left: u32,
+    pub blast_radius: i32,
+    pub effect: AreaEffect,
+}
+
+/// A projectile that went off this frame
+#[derive(Clone, Copy, Debug)]
+pub struct Impact {
+    pub tile: TilePos,
+    pub radius: i32,
+    pub effect: AreaEffect,
+}
+
+/// Tells entities apart for as long as the session runs, ids aren't saved
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Default, Debug)]
+pub struct EntityId(u64);
+
+#[derive(Clone)]
+pub struct Entity {
+    pub id: EntityId,
+    pub position: WorldPos,
+    pub sprite: Sprite,
+    // Optional components
+    pub health: Option<Health>,
+    pub ai: Option<Ai>,
+    pub projectile: Option<Projectile>,
+    pub animator: Option<Animator>, // Drawn from a sprite sheet instead of a plain square
+}
+
+impl Entity {
+    pub fn chunk_pos(&self) -> ChunkPos {
+        self.position.chunk
+    }
+
+    pub fn tile_pos(&self) -> TilePos {
+        self.position.tile()
+    }
+
+    /// Pixels per tick the entity is moving at
+    pub fn velocity(&self) -> Vec2 {
+        match (self.ai, self.projectile) {
+            (_, Some(projectile)) => projectile.velocity,
+            (Some(Ai::Wander { direction, .. }), None) => direction * config::CREATURE_SPEED,
+            (None, None) => Vec2::ZERO,
+        }
+    }
+
+    fn is_dead(&self) -> bool {
+        let out_of_health = self.health.is_some_and(|h| h.current <= 0.0);
+        let expired = self.projectile.is_some_and(|p| p.frames_left == 0);
+        out_of_health || expired
+    }
+}
+
+/// Entities bucketed by the chunk they stand in. Buckets of loaded chunks are
+/// simulated and drawn, buckets of unloaded chunks wait until the chunk returns.
+/// Creatures left far behind are dropped, see `forget_far_creatures`, anything
+/// else sleeps for good. Active buckets are ordered so entities update in the
+/// same order on every run.
+pub struct EntityStore {
+    loaded: HashSet<ChunkPos>,
+    active: BTreeMap<ChunkPos, Vec<Entity>>,
+    dormant: HashMap<ChunkPos, Vec<Entity>>,
+    next_id: u64,
+}
+
+impl EntityStore {
+    pub fn new() -> Self {
+        EntityStore {
+            loaded: HashSet::new(),
+            active: BTreeMap::new(),
+            dormant: HashMap::new(),
+            next_id: 0,
+        }
+    }
+
+    /// Creates an entity with no components, returns it so components can be attached
+    pub fn spawn(&mut self, position: WorldPos, sprite: Sprite) -> &mut Entity {
+        let entity = Entity {
+            id: self.new_id(),
+            position,
+            sprite,
+            health: None,
+            ai: None,
+            projectile: None,
+            animator: None,
+        };
+        let bucket = self.bucket_for(entity.chunk_pos());
+        bucket.push(entity);
+        bucket.last_mut().unwrap()
+    }
+
+    /// Adds an existing entity, e.g. one restored from a save, under a new id
+    pub fn insert(&mut self, mut entity: Entity) {
+        entity.id = self.new_id();
+        self.bucket_for(entity.chunk_pos()).push(entity);
+    }
+
+    fn new_id(&mut self) -> EntityId {
+        self.next_id += 1;
+        EntityId(self.next_id)
+    }
+
+    /// Active entity with the given id
+    pub fn get(&self, id: EntityId) -> Option<&Entity> {
+        self.iter().find(|entity| entity.id == id)
+    }
+
+    /// Bucket an entity standing in the given chunk belongs to
+    fn bucket_for(&mut self, chunk_pos: ChunkPos) -> &mut Vec<Entity> {
+        if self.loaded.contains(&chunk_pos) {
+            self.active.entry(chunk_pos).or_default()
+        } else {
+            self.dormant.entry(chunk_pos).or_default()
+        }
+    }
+
+    /// Wakes up the entities of a chunk that was just loaded
+    pub fn load_chunk(&mut self, pos: ChunkPos) {
+        if !self.loaded.insert(pos) {
+            return;
+        }
+        if let Some(entities) = self.dormant.remove(&pos) {
+            self.active.entry(pos).or_default().extend(entities);
+        }
+    }
+
+    /// Puts the entities of an unloaded chunk to sleep
+    pub fn unload_chunk(&mut self, pos: ChunkPos) {
+        self.loaded.remove(&pos);
+        if let Some(entities) = self.active.remove(&pos) {
+            self.dormant.entry(pos).or_default().extend(entities);
+        }
+    }
+
+    /// Drops the creatures sleeping more than `radius` chunks from `center` on
+    /// either axis, so memory doesn't grow with every chunk ever visited.
+    /// Entities without an ai stay wherever they are.
+    pub fn forget_far_creatures(&mut self, center: ChunkPos, radius: i32) {
+        let radius = radius.unsigned_abs();
+        self.dormant.retain(|pos, bucket| {
+            let distance = pos.x.abs_diff(center.x).max(pos.y.abs_diff(center.y));
+            if distance > radius {
+                bucket.retain(|entity| entity.ai.is_none());
+            }
+            !bucket.is_empty()
+        });
+    }
+
+    /// Iterates over the entities of loaded chunks
+    pub fn iter(&self) -> impl Iterator<Item = &Entity> {
+        self.active.values().flatten()
+    }
+
+    pub fn active_count(&self) -> usize {
+        self.active.values().map(Vec::len).sum()
+    }
+
+    /// Iterates over every entity, including those of unloaded chunks
+    pub fn iter_all(&self) -> impl Iterator<Item = &Entity> {
+        self.active.values().chain(self.dormant.values()).flatten()
+    }
+
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = &mut Entity> {
+        self.active.values_mut().flatten()
+    }
+
+    /// Runs components of every active entity and moves entities between buckets.
+    /// Returns the projectiles that went off. `is_blocked` tells whether something
+    /// solid stands on a rect of tiles, `raycast` finds the first solid tile on a
+    /// line. Random choices are derived from `seed`, which should change every tick.
+    pub fn update(
+        &mut self,
+        is_blocked: impl Fn(TileRect) -> bool,
+        raycast: impl Fn(WorldPos, WorldPos) -> Result<Option<RayHit>, Unloaded>,
+        seed: u64,
+    ) -> Vec<Impact> {
+        let mut moved = Vec::new();
+        let mut impacts = Vec::new();
+
+        for (&chunk_pos, bucket) in self.active.iter_mut() {
+            for entity in bucket.iter_mut() {
+                if let Some(impact) = Self::update_entity(entity, &is_blocked, &raycast, seed) {
+                    impacts.push(impact);
+                }
+            }
+            bucket.retain(|entity| !entity.is_dead());
+
+            // Pull out entities that walked into another chunk
+            let mut i = 0;
+            while i < bucket.len() {
+                if bucket[i].chunk_pos() != chunk_pos {
+                    moved.push(bucket.swap_remove(i));
+                } else {
+                    i += 1;
+                }
+            }
+        }
+
+        // Walking into an unloaded chunk puts the entity to sleep
+        for entity in moved {
+            self.bucket_for(entity.chunk_pos()).push(entity);
+        }
+
+        self.active.retain(|_, bucket| !bucket.is_empty());
+        impacts
+    }
+
+    /// Plays the animations of active entities by `dt` seconds of real time
+    pub fn animate(&mut self, dt: f32) {
+        for entity in self.iter_mut() {
+            let velocity = entity.velocity();
+            if let Some(animator) = &mut entity.animator {
+                animator.update(dt, velocity);
+            }
+        }
+    }
+
+    fn update_entity(
+        entity: &mut Entity,
+        is_blocked: impl Fn(TileRect) -> bool,
+        raycast: impl Fn(WorldPos, WorldPos) -> Result<Option<RayHit>, Unloaded>,
+        seed: u64,
+    ) -> Option<Impact> {
+        // Entities standing in different places make different choices
+        let (position, sprite) = (entity.position, entity.sprite);
+        let roll = |salt| {
+            let chunk = hash::hash2(seed, position.chunk.x, position.chunk.y, salt);
+            let hash = hash::hash2(
+                chunk,
+                position.offset.x.to_bits() as i32,
+                position.offset.y.to_bits() as i32,
+                salt,
+            );
+            hash::unit(hash)
+        };
+
+        if let Some(Ai::Wander {
+            direction,
+            frames_left,
+        }) = &mut entity.ai
+        {
+            if *frames_left == 0 {
+                // Pick a new direction, sometimes standing still
+                let angle = roll(0) * std::f32::consts::TAU;
+                *direction = if roll(1) < 1.0 / 3.0 {
+                    Vec2::ZERO
+                } else {
+                    Vec2::from_angle(angle)
+                };
+                *frames_left = 30 + (roll(2) * 90.0) as u32;
+            }
+            *frames_left -= 1;
+
+            let next = entity.position + *direction * config::CREATURE_SPEED;
+            if is_blocked(sprite.covers(next)) {
+                // Bumped into something, turn around next frame
+                *frames_left = 0;
+            } else {
+                entity.position = next;
+            }
+        }
+
+        if let Some(projectile) = &mut entity.projectile {
+            let from = entity.position;
+            entity.position += projectile.velocity;
+            projectile.frames_left = projectile.frames_left.saturating_sub(1);
+
+            // Fast bolts cross several tiles a frame, check every one on the way
+            let (tile, hit) = match raycast(from, entity.position) {
+                Ok(Some(hit)) => {
+                    entity.position = hit.point;
+                    (hit.tile, true)
+                }
+                Ok(None) => (entity.position.tile(), false),
+                // The loaded world ends on the way, go off before leaving it
+                Err(_) => {
+                    entity.position = from;
+                    (from.tile(), true)
+                }
+            };
+            if hit || projectile.frames_left == 0 {
+                projectile.frames_left = 0;
+                return Some(Impact {
+                    tile,
+                    radius: projectile.blast_radius,
+                    effect: projectile.effect,
+                });
+            }
+        }
+
+        None
+    }
+
+    /// Draws the entities standing on tiles `is_visible` accepts. Animated entities
+    /// whose sheet `sheets` has no texture for yet are drawn as squares.
+    pub fn draw<'a>(
+        &self,
+        camera: &Camera,
+        is_visible: impl Fn(TilePos) -> bool,
+        tint: impl Fn(TilePos) -> Color,
+        sheets: impl Fn(&SpriteSheet) -> Option<&'a Texture2D>,
+    ) {
+        for entity in self.iter().filter(|entity| is_visible(entity.tile_pos())) {
+            let screen_pos = camera.world_to_screen(entity.position);
+            let half = entity.sprite.size / 2.0;
+            let light = tint(entity.tile_pos());
+            match entity
+                .animator
+                .and_then(|animator| Some((animator, sheets(animator.sheet())?)))
+            {
+                Some((animator, texture)) => {
+                    animator.draw(texture, screen_pos, entity.sprite.size, light)
+                }
+                None => draw_rectangle(
+                    screen_pos.x - half,
+                    screen_pos.y - half,
+                    entity.sprite.size,
+                    entity.sprite.size,
+                    lighting::shade(entity.sprite.color, light),
+                ),
+            }
+
+            // Health bar above damaged entities
+            if let Some(health) = entity.health.filter(|h| h.current < h.max) {
+                let fraction = (health.current / health.max).clamp(0.0, 1.0);
+                let y = screen_pos.y - half - 6.0;
+                draw_rectangle(
+                    screen_pos.x - half,
+                    y,
+                    entity.sprite.size,
+                    4.0,
+                    lighting::shade(DARKGRAY, light),
+                );
+                draw_rectangle(
+                    screen_pos.x - half,
+                    y,
+                    entity.sprite.size * fraction,
+                    4.0,
+                    lighting::shade(RED, light),
+                );
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EAST: ChunkPos = ChunkPos { x: 1, y: 0 };
+
+    fn sprite() -> Sprite {
+        Sprite {
+            color: MAROON,
+            size: 24.0,
+        }
+    }
+
+    /// Walks every active entity one tick over open ground
+    fn step(store: &mut EntityStore) {
+        store.update(|_| false, |_, _| Ok(None), 0);
+    }
+
+    /// Ids of the active entities, sorted
+    fn active_ids(store: &EntityStore) -> Vec<EntityId> {
+        let mut ids: Vec<EntityId> = store.iter().map(|entity| entity.id).collect();
+        ids.sort_by_key(|id| id.0);
+        ids
+    }
+
+    #[test]
+    fn entities_sleep_with_their_chunk_and_wake_with_the_same_id() {
+        let mut store = EntityStore::new();
+        store.load_chunk(ChunkPos::default());
+        let home = store.spawn(ChunkPos::default().world_pos(), sprite()).id;
+        let away = store.spawn(EAST.world_pos(), sprite()).id;
+        assert_eq!(active_ids(&store), vec![home]);
+        assert_eq!(store.iter_all().count(), 2);
+
+        store.load_chunk(EAST);
+        assert_eq!(active_ids(&store), vec![home, away]);
+
+        store.unload_chunk(ChunkPos::default());
+        assert_eq!(active_ids(&store), vec![away]);
+        assert!(store.get(home).is_none());
+        assert_eq!(store.iter_all().count(), 2);
+
+        // Loading twice doesn't wake anything twice
+        store.load_chunk(ChunkPos::default());
+        store.load_chunk(ChunkPos::default());
+        assert_eq!(active_ids(&store), vec![home, away]);
+        assert_eq!(store.active_count(), 2);
+    }
+
+    #[test]
+    fn walking_over_a_border_moves_the_entity_to_the_next_chunk() {
+        let mut store = EntityStore::new();
+        store.load_chunk(ChunkPos::default());
+        store.load_chunk(EAST);
+        let start = EAST.world_pos() - Vec2::new(1.0, -100.0);
+        let walker = store.spawn(start, sprite());
+        walker.ai = Some(Ai::Wander {
+            direction: Vec2::X,
+            frames_left: 60,
+        });
+        let id = walker.id;
+
+        step(&mut store);
+        let walker = store.get(id).unwrap();
+        assert_eq!(walker.chunk_pos(), EAST);
+        assert!(!store.active.contains_key(&ChunkPos::default()));
+        assert_eq!(store.active[&EAST].len(), 1);
+
+        // Walking on into a chunk that isn't loaded puts it to sleep there
+        let far = EAST.offset(1, 0).unwrap();
+        store.active.get_mut(&EAST).unwrap()[0].position = far.world_pos() - Vec2::new(1.0, -100.0);
+        step(&mut store);
+        assert_eq!(store.active_count(), 0);
+        assert_eq!(store.dormant[&far][0].id, id);
+        store.load_chunk(far);
+        assert_eq!(active_ids(&store), vec![id]);
+    }
+
+    #[test]
+    fn ids_are_never_handed_out_twice() {
+        let mut store = EntityStore::new();
+        store.load_chunk(ChunkPos::default());
+        let first = store.spawn(ChunkPos::default().world_pos(), sprite()).id;
+        let dying = store.spawn(ChunkPos::default().world_pos(), sprite());
+        dying.health = Some(Health {
+            current: 0.0,
+            max: 10.0,
+        });
+        let dead = dying.id;
+        step(&mut store);
+        assert_eq!(active_ids(&store), vec![first]);
+
+        // A restored entity gets a fresh id even if it was saved with another's
+        let restored = store.get(first).unwrap().clone();
+        store.insert(restored);
+        let ids = active_ids(&store);
+        assert_eq!(ids.len(), 2);
+        assert!(!ids.contains(&dead));
+        assert!(ids[1].0 > dead.0);
+    }
+
+    #[test]
+    fn only_creatures_sleeping_far_away_are_forgotten() {
+        let mut store = EntityStore::new();
+        let near = ChunkPos { x: 3, y: -3 };
+        let far = ChunkPos { x: -4, y: 1 };
+        let mut ids = Vec::new();
+        for chunk in [near, far] {
+            let creature = store.spawn(chunk.world_pos(), sprite());
+            creature.ai = Some(Ai::Wander {
+                direction: Vec2::ZERO,
+                frames_left: 0,
+            });
+            ids.push(creature.id);
+            ids.push(store.spawn(chunk.world_pos(), sprite()).id);
+        }
+        // Loaded chunks keep their creatures however far they are
+        store.load_chunk(ChunkPos { x: 9, y: 9 });
+        let awake = store.spawn(ChunkPos { x: 9, y: 9 }.world_pos(), sprite());
+        awake.ai = Some(Ai::Wander {
+            direction: Vec2::ZERO,
+            frames_left: 0,
+        });
+        let awake = awake.id;
+
+        store.forget_far_creatures(ChunkPos::default(), 3);
+        let mut left: Vec<EntityId> = store.iter_all().map(|entity| entity.id).collect();
+        left.sort_by_key(|id| id.0);
+        assert_eq!(left, vec![ids[0], ids[1], ids[3], awake]);
+
+        store.forget_far_creatures(far, 0);
+        assert_eq!(store.iter_all().count(), 3);
+        assert!(store.iter_all().all(|entity| entity.id != ids[0]));
+    }
+}
@@ -0,0 +1,166 @@
+// fog.rs
+// which tiles of a chunk the wizard has seen, and what the wizard can see from a tile
+use crate::config;
+use crate::shape::Shape;
+use crate::types::{LocalTilePos, TilePos};
+use macroquad::prelude::*;
+use std::collections::HashSet;
+
+/// Drawn over tiles nobody has seen yet, on the main view and on the maps
+pub const FOG_COLOR: Color = Color::new(0.05, 0.05, 0.08, 1.0);
+
+const WORDS: usize = config::CHUNK_AREA.div_ceil(64);
+
+/// One bit per tile of a chunk, set once the tile has been seen
+#[derive(Clone, Copy, PartialEq, Eq, Default, Debug)]
+pub struct Explored {
+    bits: [u64; WORDS],
+}
+
+impl Explored {
+    /// Every tile of the chunk seen, for chunks explored before fog was tracked
+    pub fn all() -> Self {
+        let mut explored = Explored::default();
+        for pos in LocalTilePos::all() {
+            explored.explore(pos);
+        }
+        explored
+    }
+
+    fn index(pos: LocalTilePos) -> (usize, u64) {
+        let bit = pos.index();
+        (bit / 64, 1 << (bit % 64))
+    }
+
+    pub fn is_explored(&self, pos: LocalTilePos) -> bool {
+        let (word, mask) = Self::index(pos);
+        self.bits[word] & mask != 0
+    }
+
+    /// Marks a tile as seen, returns true if it wasn't before
+    pub fn explore(&mut self, pos: LocalTilePos) -> bool {
+        let (word, mask) = Self::index(pos);
+        let new = self.bits[word] & mask == 0;
+        self.bits[word] |= mask;
+        new
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.bits.iter().all(|&word| word == 0)
+    }
+
+    /// Fixed width hex, 16 digits per word
+    pub fn to_hex(self) -> String {
+        self.bits
+            .iter()
+            .map(|word| format!("{:016x}", word))
+            .collect()
+    }
+
+    pub fn from_hex(text: &str) -> Option<Self> {
+        if text.len() != WORDS * 16 || !text.is_ascii() {
+            return None;
+        }
+        let mut explored = Explored::default();
+        for (word, digits) in explored.bits.iter_mut().zip(text.as_bytes().chunks(16)) {
+            *word = u64::from_str_radix(std::str::from_utf8(digits).ok()?, 16).ok()?;
+        }
+        Some(explored)
+    }
+}
+
+/// Tiles within `radius` of `from` with a clear line of sight. Solid tiles block
+/// the view but are seen themselves, so walls show up at the edge of the dark.
+pub fn visible_tiles(
+    from: TilePos,
+    radius: i32,
+    is_solid: impl Fn(TilePos) -> bool,
+) -> HashSet<TilePos> {
+    let mut visible = HashSet::new();
+    for target in (Shape::Circle { radius }).rasterize(from) {
+        for tile in (Shape::Line { to: target }).rasterize(from) {
+            visible.insert(tile);
+            if tile != from && is_solid(tile) {
+                break;
+            }
+        }
+    }
+    visible
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn explored_tiles_survive_hex() {
+        let mut explored = Explored::default();
+        assert!(explored.is_empty());
+        let corners = [
+            LocalTilePos { x: 0, y: 0 },
+            LocalTilePos {
+                x: config::CHUNK_SIZE - 1,
+                y: 0,
+            },
+            LocalTilePos {
+                x: 0,
+                y: config::CHUNK_SIZE - 1,
+            },
+            LocalTilePos {
+                x: config::CHUNK_SIZE - 1,
+                y: config::CHUNK_SIZE - 1,
+            },
+        ];
+        for pos in corners {
+            assert!(explored.explore(pos));
+            assert!(!explored.explore(pos));
+        }
+
+        let hex = explored.to_hex();
+        assert_eq!(hex.len(), WORDS * 16);
+        let read = Explored::from_hex(&hex).unwrap();
+        assert_eq!(read, explored);
+        for pos in LocalTilePos::all() {
+            assert_eq!(read.is_explored(pos), corners.contains(&pos));
+        }
+        assert_eq!(
+            Explored::from_hex(&Explored::all().to_hex()),
+            Some(Explored::all())
+        );
+    }
+
+    #[test]
+    fn broken_hex_is_refused() {
+        let hex = Explored::all().to_hex();
+        assert_eq!(Explored::from_hex(&hex[1..]), None);
+        assert_eq!(Explored::from_hex(&format!("{}0", hex)), None);
+        assert_eq!(Explored::from_hex(&hex.replacen('f', "g", 1)), None);
+        assert_eq!(Explored::from_hex(&format!("é{}", &hex[2..])), None);
+    }
+
+    #[test]
+    fn walls_are_seen_but_hide_what_is_behind_them() {
+        let from = TilePos { x: 0, y: 0 };
+        // A wall along x = 3, except for a gap at y = 0
+        let is_solid = |pos: TilePos| pos.x == 3 && pos.y != 0;
+        let visible = visible_tiles(from, 6, is_solid);
+
+        assert!(visible.contains(&from));
+        assert!(visible.contains(&TilePos { x: 3, y: 2 }));
+        assert!(!visible.contains(&TilePos { x: 5, y: 2 }));
+        // Straight through the gap
+        assert!(visible.contains(&TilePos { x: 6, y: 0 }));
+        // Nothing past the radius, even in the open
+        assert!(visible.contains(&TilePos { x: -6, y: 0 }));
+        assert!(!visible.contains(&TilePos { x: -7, y: 0 }));
+        assert!(!visible.contains(&TilePos { x: -5, y: -5 }));
+    }
+
+    #[test]
+    fn standing_in_a_wall_still_sees_out() {
+        let from = TilePos { x: 10, y: -4 };
+        let visible = visible_tiles(from, 3, |pos| pos == from);
+        assert!(visible.contains(&TilePos { x: 13, y: -4 }));
+        assert!(visible.contains(&TilePos { x: 10, y: -7 }));
+    }
+}
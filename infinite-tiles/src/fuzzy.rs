@@ -0,0 +1,181 @@
+// fuzzy.rs
+// edit-distance matching of typed words against the spell vocabulary
+use crate::config;
+use crate::spell::{MatchQuality, Spellbook, VOCABULARY, Word};
+
+/// Result of resolving one typed word against the vocabulary
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum WordMatch {
+    Known(&'static Word, MatchQuality),
+    Unknown,
+}
+
+/// Matches typed words to vocabulary words within configurable edit distances.
+/// Cheap enough to run on every keystroke: the vocabulary is small and the
+/// distance computation bails out as soon as the threshold is exceeded.
+pub struct FuzzyMatcher {
+    weak_distance: usize,
+    chaos_distance: usize,
+    hint_distance: usize,
+}
+
+impl FuzzyMatcher {
+    pub fn new() -> Self {
+        FuzzyMatcher {
+            weak_distance: config::FUZZY_WEAK_DISTANCE,
+            chaos_distance: config::FUZZY_CHAOS_DISTANCE,
+            hint_distance: config::FUZZY_HINT_DISTANCE,
+        }
+    }
+
+    /// Resolves a typed word to the closest vocabulary word, if any is close enough
+    pub fn resolve(&self, input: &str) -> WordMatch {
+        let input = input.to_lowercase();
+        if input.len() < config::FUZZY_MIN_WORD_LEN {
+            return match VOCABULARY.iter().find(|w| w.text == input) {
+                Some(word) => WordMatch::Known(word, MatchQuality::Exact),
+                None => WordMatch::Unknown,
+            };
+        }
+
+        match Self::closest(&input, VOCABULARY.iter(), self.chaos_distance) {
+            Some((word, 0)) => WordMatch::Known(word, MatchQuality::Exact),
+            Some((word, distance)) if distance <= self.weak_distance => {
+                WordMatch::Known(word, MatchQuality::Weak)
+            }
+            Some((word, _)) => WordMatch::Known(word, MatchQuality::Chaotic),
+            None => WordMatch::Unknown,
+        }
+    }
+
+    /// Suggests a discovered word the player probably meant.
+    /// Undiscovered words are never suggested so hints can't leak the vocabulary.
+    pub fn hint(&self, input: &str, spellbook: &Spellbook) -> Option<&'static str> {
+        let input = input.to_lowercase();
+        if input.len() < config::FUZZY_MIN_WORD_LEN {
+            return None;
+        }
+
+        let discovered = VOCABULARY
+            .iter()
+            .filter(|word| spellbook.is_discovered(word.text));
+        match Self::closest(&input, discovered, self.hint_distance) {
+            Some((word, distance)) if distance > 0 => Some(word.text),
+            _ => None,
+        }
+    }
+
+    /// Finds the candidate with the smallest edit distance, first one wins ties
+    fn closest<'a>(
+        input: &str,
+        candidates: impl Iterator<Item = &'a Word>,
+        threshold: usize,
+    ) -> Option<(&'a Word, usize)> {
+        let mut best: Option<(&Word, usize)> = None;
+        for word in candidates {
+            // Short words tolerate fewer edits, otherwise "ice" would match almost anything
+            let allowed = threshold.min((word.text.len() / config::FUZZY_CHARS_PER_EDIT).max(1));
+            // Only a strictly closer word can replace the current best
+            let limit = match best {
+                Some((_, best_distance)) => allowed.min(best_distance - 1),
+                None => allowed,
+            };
+            if let Some(distance) =
+                bounded_edit_distance(input.as_bytes(), word.text.as_bytes(), limit)
+            {
+                best = Some((word, distance));
+                if distance == 0 {
+                    break;
+                }
+            }
+        }
+        best
+    }
+}
+
+/// Levenshtein distance between `a` and `b`, or None if it exceeds `max`
+fn bounded_edit_distance(a: &[u8], b: &[u8], max: usize) -> Option<usize> {
+    if a.len().abs_diff(b.len()) > max {
+        return None;
+    }
+
+    let mut prev: Vec<usize> = (0..=b.len()).collect();
+    let mut curr = vec![0; b.len() + 1];
+
+    for (i, &ca) in a.iter().enumerate() {
+        curr[0] = i + 1;
+        let mut row_min = curr[0];
+        for (j, &cb) in b.iter().enumerate() {
+            let substitution = prev[j] + usize::from(ca != cb);
+            curr[j + 1] = substitution.min(prev[j + 1] + 1).min(curr[j] + 1);
+            row_min = row_min.min(curr[j + 1]);
+        }
+        // Every later row is at least this large, no point continuing
+        if row_min > max {
+            return None;
+        }
+        std::mem::swap(&mut prev, &mut curr);
+    }
+
+    let distance = prev[b.len()];
+    (distance <= max).then_some(distance)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn quality(input: &str) -> Option<(&'static str, MatchQuality)> {
+        match FuzzyMatcher::new().resolve(input) {
+            WordMatch::Known(word, quality) => Some((word.text, quality)),
+            WordMatch::Unknown => None,
+        }
+    }
+
+    #[test]
+    fn resolve_grades_misses_by_distance() {
+        assert_eq!(quality("fire"), Some(("fire", MatchQuality::Exact)));
+        assert_eq!(quality("FIRE"), Some(("fire", MatchQuality::Exact)));
+        assert_eq!(quality("fira"), Some(("fire", MatchQuality::Weak)));
+        assert_eq!(quality("lightnin"), Some(("lightning", MatchQuality::Weak)));
+        assert_eq!(
+            quality("lihtnin"),
+            Some(("lightning", MatchQuality::Chaotic))
+        );
+        assert_eq!(quality("xyzzy"), None);
+    }
+
+    #[test]
+    fn resolve_is_strict_with_short_words() {
+        // Below the minimum length only exact words count
+        assert_eq!(quality("ic"), None);
+        assert_eq!(quality("ice"), Some(("ice", MatchQuality::Exact)));
+        // Four and five letter words tolerate a single edit
+        assert_eq!(quality("burts"), None);
+        assert_eq!(quality("brst"), Some(("burst", MatchQuality::Weak)));
+    }
+
+    #[test]
+    fn hint_only_suggests_discovered_words() {
+        let matcher = FuzzyMatcher::new();
+        let mut spellbook = Spellbook::new();
+        assert_eq!(matcher.hint("fira", &spellbook), Some("fire"));
+        // Nothing to hint at for a word typed correctly or too short to judge
+        assert_eq!(matcher.hint("fire", &spellbook), None);
+        assert_eq!(matcher.hint("fi", &spellbook), None);
+
+        assert_eq!(matcher.hint("watr", &spellbook), None);
+        spellbook.discover("water");
+        assert_eq!(matcher.hint("watr", &spellbook), Some("water"));
+    }
+
+    #[test]
+    fn edit_distance_stops_at_the_bound() {
+        assert_eq!(bounded_edit_distance(b"kitten", b"sitting", 3), Some(3));
+        assert_eq!(bounded_edit_distance(b"kitten", b"sitting", 2), None);
+        assert_eq!(bounded_edit_distance(b"bolt", b"bolt", 0), Some(0));
+        assert_eq!(bounded_edit_distance(b"", b"ice", 3), Some(3));
+        // Too different in length to be within reach
+        assert_eq!(bounded_edit_distance(b"a", b"abcd", 2), None);
+    }
+}
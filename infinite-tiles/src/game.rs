@@ -0,0 +1,386 @@
+// game.rs
+// one playing session: the world, the wizard and the save slot they belong to
+use crate::animation;
+use crate::camera::Camera;
+use crate::casting::{self, CastPlan, element_color};
+use crate::config;
+use crate::console::SpellConsole;
+use crate::fuzzy::FuzzyMatcher;
+use crate::hash;
+use crate::input::{Action, InputFrame, InputMap};
+use crate::lighting::{self, Lighting, PointLight};
+use crate::map::{self, ChunkImages, WorldMap};
+use crate::menu;
+use crate::particles::{self, Anchor, ParticleSystem};
+use crate::player::Player;
+use crate::save::{self, SaveData, SaveError};
+use crate::simulation::CellularAutomaton;
+use crate::spell::{Spell, Spellbook, Verb};
+use crate::tile::{Layer, tile_def};
+use crate::types::{ChunkPos, TilePos, TileRect, WorldPos};
+use crate::weather::{self, Weather};
+use crate::world::World;
+use macroquad::prelude::*;
+use std::cell::OnceCell;
+use std::collections::BTreeSet;
+
+const CHAOS_SALT: u64 = 0x40;
+
+pub struct Game {
+    slot: Option<usize>, // None for sessions that are never saved, e.g. replays
+    player: Player,
+    world: World,
+    spellbook: Spellbook,
+    console: SpellConsole,
+    matcher: FuzzyMatcher,
+    automaton: CellularAutomaton,
+    chunk_images: ChunkImages,
+    map: WorldMap,
+    lighting: OnceCell<Lighting>, // Needs a window, created on the first draw
+    particles: ParticleSystem,
+    looked_from: Option<(TilePos, i32)>, // Tile and sight radius of the last look around
+    tick: u64,                           // Game ticks simulated since the session started
+    play_time: f64,                      // Seconds spent playing this save, across sessions
+}
+
+/// The parts of a session a replay has to reproduce
+#[derive(PartialEq, Debug)]
+pub struct Snapshot {
+    pub player_position: WorldPos,
+    pub loaded_chunks: BTreeSet<ChunkPos>,
+    pub discovered: Vec<&'static str>,
+}
+
+impl Game {
+    /// Starts a fresh world, in a save slot if there is one
+    pub async fn new(slot: Option<usize>, seed: u64) -> Self {
+        let world = World::new(seed).await;
+        let mut game = Self::from_parts(slot, world, Player::new(), Spellbook::new(), 0.0);
+        game.load_textures().await;
+        game
+    }
+
+    /// Continues the game stored in a save slot
+    pub async fn load(slot: usize) -> Result<Self, SaveError> {
+        let save = SaveData::read(&save::slot_path(slot))?;
+        let play_time = save.play_time;
+        let markers = save.markers.clone();
+        let (world, player, spellbook) = save.restore().await;
+        let mut game = Self::from_parts(Some(slot), world, player, spellbook, play_time);
+        game.map.markers = markers;
+        game.load_textures().await;
+        Ok(game)
+    }
+
+    /// Puts a session together and streams in the area around the wizard
+    pub fn from_parts(
+        slot: Option<usize>,
+        mut world: World,
+        player: Player,
+        spellbook: Spellbook,
+        play_time: f64,
+    ) -> Self {
+        world.stream_chunks(player.get_chunk_pos());
+        Game {
+            slot,
+            player,
+            world,
+            spellbook,
+            console: SpellConsole::new(),
+            matcher: FuzzyMatcher::new(),
+            automaton: CellularAutomaton::new(),
+            chunk_images: ChunkImages::new(),
+            map: WorldMap::new(),
+            lighting: OnceCell::new(),
+            particles: ParticleSystem::new(),
+            looked_from: None,
+            tick: 0,
+            play_time,
+        }
+    }
+
+    pub fn slot(&self) -> Option<usize> {
+        self.slot
+    }
+
+    pub fn save(&self) -> Result<(), SaveError> {
+        let Some(slot) = self.slot else {
+            return Ok(());
+        };
+        let save = SaveData::capture(
+            &self.world,
+            &self.player,
+            &self.spellbook,
+            &self.map.markers,
+            self.play_time,
+        );
+        save.write(&save::slot_path(slot))
+    }
+
+    /// True while the console has the keyboard
+    pub fn is_typing(&self) -> bool {
+        self.console.is_open()
+    }
+
+    pub fn close_console(&mut self) {
+        self.console.close();
+    }
+
+    pub fn is_over(&self) -> bool {
+        self.player.is_dead()
+    }
+
+    /// The view follows the wizard
+    pub fn camera(&self) -> Camera {
+        Camera::centered_on(
+            self.player.get_position(),
+            Vec2::new(screen_width(), screen_height()),
+        )
+    }
+
+    pub fn capture_input(&self, stick: Vec2) -> InputFrame {
+        InputFrame::capture(&self.camera(), stick)
+    }
+
+    /// Advances the session by one tick. Everything here only depends on the
+    /// seed and the input frames, so recorded frames replay the same session.
+    pub fn tick(&mut self, input: &InputMap, frame: &InputFrame) {
+        if self.console.is_open() {
+            // Typing, a finished incantation is cast at the mouse cursor
+            let cast = self
+                .console
+                .update(frame, input, &self.matcher, &mut self.spellbook);
+            if let Some(spell) = cast {
+                self.cast(&spell, frame.mouse);
+            }
+        } else if input.is_pressed(Action::OpenConsole, frame) {
+            self.console.open();
+        } else {
+            // Movement keys are letters, so typing must not move the wizard
+            self.player.update(input.movement(frame));
+        }
+
+        self.play_time += 1.0 / config::TICKS_PER_SECOND as f64;
+        self.world.advance_clock();
+        for impact in self.world.update_entities(self.tick, self.player.tile_pos) {
+            self.particles.attach(
+                particles::SPELL_BURST,
+                Anchor::Tile(impact.tile),
+                element_color(impact.effect.element),
+            );
+        }
+        self.automaton.update(&mut self.world);
+
+        // Burning ground hurts
+        if let Ok(tile) = self
+            .world
+            .tile_at(Layer::Ground, self.player.get_position())
+        {
+            self.player
+                .hurt(tile_def(tile).damage / config::TICKS_PER_SECOND);
+        }
+
+        // Update world if player moved to new chunk
+        if self.player.get_chunk_pos() != self.world.center_chunk() {
+            self.world.stream_chunks(self.player.get_chunk_pos());
+        }
+        // Fog closes in around the wizard
+        let sight = match self.world.weather(self.player.get_chunk_pos()) {
+            Weather::Fog => config::FOG_SIGHT_RADIUS,
+            _ => config::PLAYER_SIGHT_RADIUS,
+        };
+        if self.looked_from != Some((self.player.tile_pos, sight)) {
+            self.world.look_around(self.player.tile_pos, sight);
+            self.looked_from = Some((self.player.tile_pos, sight));
+        }
+        self.tick += 1;
+    }
+
+    /// Releases a spell towards `aim`, unless it doesn't answer at this time of day
+    fn cast(&mut self, spell: &Spell, aim: WorldPos) {
+        let phase = self.world.clock().phase();
+        if let Some(phases) = spell.verb().and_then(Verb::phases)
+            && !phases.contains(&phase)
+        {
+            let names: Vec<&str> = phases.iter().map(|phase| phase.name()).collect();
+            self.console.push_log(format!(
+                "the spell stays silent, it only answers at {}",
+                names.join(" or ")
+            ));
+            return;
+        }
+
+        let caster = self.player.get_position();
+        let chaos = hash::hash1(self.world.seed(), self.tick, CHAOS_SALT);
+        let weather = self.world.weather(self.player.get_chunk_pos());
+        let Some(plan) = casting::plan_cast(spell, caster, aim, weather, chaos) else {
+            return;
+        };
+        self.player.start_cast();
+        if let CastPlan::Area {
+            origin,
+            shape,
+            effect,
+        } = &plan
+        {
+            let color = element_color(effect.element);
+            for tile in shape.rasterize(*origin) {
+                self.particles
+                    .emit(particles::SPELL_BURST, tile.world_center(), 3.0, color);
+            }
+        }
+        let element = spell.element();
+        if let Some(bolt) = self.world.cast(plan, caster) {
+            self.particles.attach(
+                particles::SPELL_TRAIL,
+                Anchor::Entity(bolt),
+                element_color(element),
+            );
+        }
+    }
+
+    /// Moves particles and animations along by a frame of real time. Paused states
+    /// don't call it, so sparks hang in the air with the rest of the world.
+    pub fn update_effects(&mut self, dt: f32) {
+        // Tiles on screen that give off particles
+        let (view_start, view_end) = self.camera().get_visible_range();
+        let area = TileRect::covering(view_start, view_end + Vec2::splat(config::TILE_SIZE as f32));
+        for pos in area.iter() {
+            if let Some(emitter) = self
+                .world
+                .get_tile(Layer::Ground, pos)
+                .and_then(|tile| tile_def(tile).particles)
+            {
+                let rate = particles::emitter_def(emitter).rate;
+                self.particles
+                    .emit(emitter, pos.world_center(), rate * dt, WHITE);
+            }
+        }
+
+        self.player.animate(dt);
+        self.world.entities.animate(dt);
+
+        let world = &self.world;
+        self.particles.update(dt, |anchor| match anchor {
+            Anchor::Entity(id) => world.entities.get(id).map(|entity| entity.position),
+            Anchor::Tile(pos) => Some(pos.world_center()),
+        });
+    }
+
+    /// Loads textures for chunks that were streamed in
+    pub async fn load_textures(&mut self) {
+        self.world.load_textures().await;
+    }
+
+    pub fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            player_position: self.player.get_position(),
+            loaded_chunks: self.world.loaded_chunks().collect(),
+            discovered: self.spellbook.words().collect(),
+        }
+    }
+
+    pub fn open_map(&mut self) {
+        self.map.open(self.player.get_position());
+    }
+
+    /// Pans, zooms and marks the world map, the world itself stays frozen
+    pub fn update_map(&mut self, input: &InputMap, frame: &InputFrame) {
+        self.map.update(input, frame);
+    }
+
+    pub fn draw_map(&self, input: &InputMap) {
+        self.map.draw(
+            &self.world,
+            &self.chunk_images,
+            self.player.get_position(),
+            input,
+        );
+    }
+
+    pub fn draw_spellbook(&self, input: &InputMap) {
+        menu::draw_spellbook_overlay(&self.spellbook, input);
+    }
+
+    /// Way from the wizard to the tile under the cursor, planned over chunks when
+    /// the cursor is too far for a plain search
+    #[cfg(debug_assertions)]
+    fn draw_debug_path(&self, camera: &Camera) {
+        use crate::pathfinding::Planner;
+        let from = self.player.tile_pos;
+        let to = camera.screen_to_world(Vec2::from(mouse_position())).tile();
+        let planner = if from.distance(to) > config::CHUNK_SIZE as f32 {
+            Planner::Chunks
+        } else {
+            Planner::Tiles
+        };
+        let Some(path) = self.world.find_path(from, to, planner) else {
+            return;
+        };
+        for &tile in &path.tiles[1..] {
+            let point = camera.world_to_screen(tile.world_center());
+            draw_circle(point.x, point.y, 4.0, YELLOW);
+        }
+    }
+
+    pub fn draw(&self, input: &InputMap) {
+        let camera = self.camera();
+        let clock = self.world.clock();
+        let lighting = self.lighting.get_or_init(Lighting::new);
+        let staff = PointLight {
+            tile: self.player.tile_pos,
+            radius: config::STAFF_LIGHT_RADIUS,
+            color: lighting::STAFF_LIGHT,
+        };
+        let light_map =
+            self.world
+                .light_map(&camera, lighting::ambient(clock.time_of_day()), &[staff]);
+
+        let tint = |pos| lighting.tint(&light_map, pos);
+        self.world.draw(&camera, tint);
+        self.player.draw(
+            &camera,
+            self.world.sprite_sheet(&animation::WIZARD_SHEET),
+            tint(self.player.tile_pos),
+        );
+        lighting.draw_overlay(&light_map, &camera);
+        // Sparks glow, so they go on top of the darkness when the overlay lights
+        // the screen. Without it they are tinted like everything else.
+        self.particles.draw(&camera, tint);
+        let weather = self.world.weather(self.player.get_chunk_pos());
+        weather::draw_weather(weather, &camera, get_time() as f32);
+        map::draw_minimap(
+            &self.world,
+            &self.chunk_images,
+            self.player.get_position(),
+            &self.map.markers,
+            &format!(
+                "Day {}, {}, {}",
+                clock.day(),
+                clock.phase().name(),
+                weather.name()
+            ),
+        );
+        self.console.draw();
+
+        // Debug info
+        #[cfg(debug_assertions)]
+        {
+            self.world.draw_debug_info(self.player.tile_pos);
+            self.draw_debug_path(&camera);
+            let keys = |action| menu::bound_keys(input, action);
+            let help = format!(
+                "{} to type a spell and {} to cast, {} for the spellbook, {} for the map, {}/{} save/load, {} keep a replay, {} to pause",
+                keys(Action::OpenConsole),
+                keys(Action::Cast),
+                keys(Action::OpenSpellbook),
+                keys(Action::OpenMap),
+                keys(Action::QuickSave),
+                keys(Action::QuickLoad),
+                keys(Action::KeepReplay),
+                keys(Action::Cancel)
+            );
+            draw_text(&help, 10.0, 30.0, 20.0, BLACK);
+        }
+    }
+}
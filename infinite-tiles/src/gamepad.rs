@@ -0,0 +1,130 @@
+// gamepad.rs
+// the left stick of a plugged in controller, read straight from the joystick device
+use macroquad::prelude::*;
+use std::fs::File;
+use std::io::{self, Read};
+
+const EVENT_SIZE: usize = 8; // struct js_event: u32 time, i16 value, u8 type, u8 number
+const EVENT_AXIS: u8 = 0x02;
+const EVENT_INIT: u8 = 0x80; // Set on the made-up events that report the state on open
+const RETRY_POLLS: u32 = 120; // Polls between looks for a controller that was plugged in
+
+/// macroquad doesn't read controllers, so the stick comes from the Linux joystick
+/// device. Elsewhere, or with nothing plugged in, the stick stays centered.
+pub struct Gamepad {
+    device: Option<File>,
+    retry_in: u32,
+    stick: Vec2, // From -1 to 1 on each axis, y grows downwards like the screen
+}
+
+impl Gamepad {
+    pub fn new() -> Self {
+        Gamepad {
+            device: None,
+            retry_in: 0,
+            stick: Vec2::ZERO,
+        }
+    }
+
+    pub fn stick(&self) -> Vec2 {
+        self.stick
+    }
+
+    /// Reads every event since the last poll, once a frame
+    pub fn poll(&mut self) {
+        if self.device.is_none() {
+            if self.retry_in > 0 {
+                self.retry_in -= 1;
+                return;
+            }
+            self.retry_in = RETRY_POLLS;
+            self.device = open_device();
+        }
+        let Some(device) = &mut self.device else {
+            return;
+        };
+
+        let mut buffer = [0u8; EVENT_SIZE * 32];
+        loop {
+            match device.read(&mut buffer) {
+                Ok(0) => break,
+                Ok(read) => {
+                    for event in buffer[..read].chunks_exact(EVENT_SIZE) {
+                        apply_event(&mut self.stick, event);
+                    }
+                }
+                Err(err) if err.kind() == io::ErrorKind::WouldBlock => break,
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+                // Unplugged, let go of the stick and look for it again later
+                Err(_) => {
+                    self.device = None;
+                    self.stick = Vec2::ZERO;
+                    break;
+                }
+            }
+        }
+    }
+}
+
+/// Moves the stick for an axis event, the first two axes are the left stick
+fn apply_event(stick: &mut Vec2, event: &[u8]) {
+    let value = i16::from_ne_bytes([event[4], event[5]]);
+    let (kind, number) = (event[6] & !EVENT_INIT, event[7]);
+    if kind != EVENT_AXIS {
+        return;
+    }
+    let value = (value as f32 / i16::MAX as f32).max(-1.0);
+    match number {
+        0 => stick.x = value,
+        1 => stick.y = value,
+        _ => {}
+    }
+}
+
+/// First joystick device that opens, read without blocking
+#[cfg(target_os = "linux")]
+fn open_device() -> Option<File> {
+    use std::fs::OpenOptions;
+    use std::os::unix::fs::OpenOptionsExt;
+    (0..4).find_map(|number| {
+        OpenOptions::new()
+            .read(true)
+            .custom_flags(libc::O_NONBLOCK)
+            .open(format!("/dev/input/js{}", number))
+            .ok()
+    })
+}
+
+#[cfg(not(target_os = "linux"))]
+fn open_device() -> Option<File> {
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn event(value: i16, kind: u8, number: u8) -> [u8; EVENT_SIZE] {
+        let mut event = [0u8; EVENT_SIZE];
+        event[4..6].copy_from_slice(&value.to_ne_bytes());
+        event[6] = kind;
+        event[7] = number;
+        event
+    }
+
+    #[test]
+    fn the_first_two_axes_move_the_left_stick() {
+        let mut stick = Vec2::ZERO;
+        apply_event(&mut stick, &event(i16::MAX, EVENT_AXIS, 0));
+        apply_event(&mut stick, &event(i16::MIN, EVENT_AXIS | EVENT_INIT, 1));
+        assert_eq!(stick, Vec2::new(1.0, -1.0));
+
+        // Buttons and the right stick leave it alone
+        apply_event(&mut stick, &event(1, 0x01, 0));
+        apply_event(&mut stick, &event(0, EVENT_AXIS, 3));
+        assert_eq!(stick, Vec2::new(1.0, -1.0));
+
+        apply_event(&mut stick, &event(0, EVENT_AXIS, 0));
+        assert_eq!(stick, Vec2::new(0.0, -1.0));
+    }
+}
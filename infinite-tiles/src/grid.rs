@@ -0,0 +1,230 @@
+// grid.rs
+// one layer of a chunk's tiles in a single flat array, packed against a palette
+use crate::config;
+use crate::tile::TileId;
+use crate::types::LocalTilePos;
+
+/// Tiles of one chunk layer. Every tile is stored as an index into a palette of
+/// the tile types the layer uses, packed into as few bits as the palette needs:
+/// a layer of a single type takes no room past its palette, a handful of types
+/// 1, 2 or 4 bits a tile.
+#[derive(Clone, Debug)]
+pub struct TileGrid {
+    palette: Vec<TileId>, // Only grows while the grid is edited, rebuilt grids are compact
+    bits: u32,            // Per packed index, 0 while the palette has a single entry
+    words: Vec<u64>,      // Packed indices in row-major order, none split across words
+}
+
+// A chunk never holds more tile types than it has tiles, so a rebuilt palette
+// always fits the widest packing
+const MAX_ENTRIES: usize = 256;
+const _: () = assert!(config::CHUNK_AREA <= MAX_ENTRIES);
+
+/// Bits an index into a palette of `entries` tile types takes. Always a power of
+/// two, so a whole number of indices fits in each word.
+fn bits_for(entries: usize) -> u32 {
+    match entries {
+        0 | 1 => 0,
+        2 => 1,
+        3..=4 => 2,
+        5..=16 => 4,
+        _ => 8,
+    }
+}
+
+impl TileGrid {
+    /// Every tile the same, e.g. an empty layer
+    pub fn filled(tile: TileId) -> Self {
+        TileGrid {
+            palette: vec![tile],
+            bits: 0,
+            words: Vec::new(),
+        }
+    }
+
+    /// Fills the grid with the tile `tile_at` gives each position
+    pub fn from_fn(mut tile_at: impl FnMut(LocalTilePos) -> TileId) -> Self {
+        let mut palette: Vec<TileId> = Vec::new();
+        let mut entries = [0u8; config::CHUNK_AREA];
+        for (index, entry) in entries.iter_mut().enumerate() {
+            let tile = tile_at(LocalTilePos::from_index(index));
+            *entry = match palette.iter().position(|&known| known == tile) {
+                Some(known) => known as u8,
+                None => {
+                    palette.push(tile);
+                    (palette.len() - 1) as u8
+                }
+            };
+        }
+        let mut grid = TileGrid {
+            bits: bits_for(palette.len()),
+            palette,
+            words: Vec::new(),
+        };
+        grid.words = vec![0; grid.word_count()];
+        if grid.bits > 0 {
+            for (index, &entry) in entries.iter().enumerate() {
+                grid.write(index, entry as usize);
+            }
+        }
+        grid
+    }
+
+    fn word_count(&self) -> usize {
+        match self.bits {
+            0 => 0,
+            bits => config::CHUNK_AREA.div_ceil((64 / bits) as usize),
+        }
+    }
+
+    /// Word holding a packed index and how far it is shifted in there. Indices
+    /// never straddle words, so both come from the index's first bit; with `/`
+    /// and `%` by 64 that is a shift and a mask, and walking a row only adds
+    /// `bits` to it each step.
+    fn slot(&self, index: usize) -> (usize, u32) {
+        let bit = index * self.bits as usize;
+        (bit / 64, (bit % 64) as u32)
+    }
+
+    fn read(&self, index: usize) -> usize {
+        let (word, shift) = self.slot(index);
+        // A single-entry palette has no words, every tile is its one entry
+        match self.words.get(word) {
+            Some(&word) => ((word >> shift) & ((1u64 << self.bits) - 1)) as usize,
+            None => 0,
+        }
+    }
+
+    fn write(&mut self, index: usize, entry: usize) {
+        let (word, shift) = self.slot(index);
+        let mask = (1u64 << self.bits) - 1;
+        self.words[word] = (self.words[word] & !(mask << shift)) | ((entry as u64 & mask) << shift);
+    }
+
+    pub fn get(&self, pos: LocalTilePos) -> TileId {
+        self.palette[self.read(pos.index())]
+    }
+
+    /// Replaces a tile, returns false if it already was that tile
+    pub fn set(&mut self, pos: LocalTilePos, tile: TileId) -> bool {
+        if self.get(pos) == tile {
+            return false;
+        }
+        let entry = match self.palette.iter().position(|&known| known == tile) {
+            Some(entry) => entry,
+            // Edits left the palette full of types the grid may no longer hold,
+            // rebuilding it keeps only the ones in use
+            None if self.palette.len() == MAX_ENTRIES => {
+                let old = std::mem::replace(self, TileGrid::filled(tile));
+                *self = TileGrid::from_fn(|at| if at == pos { tile } else { old.get(at) });
+                return true;
+            }
+            None => {
+                self.palette.push(tile);
+                let bits = bits_for(self.palette.len());
+                if bits != self.bits {
+                    self.repack(bits);
+                }
+                self.palette.len() - 1
+            }
+        };
+        self.write(pos.index(), entry);
+        true
+    }
+
+    /// Moves every index over to a wider packing once the palette outgrows the old one
+    fn repack(&mut self, bits: u32) {
+        let entries: Vec<usize> = (0..config::CHUNK_AREA)
+            .map(|index| self.read(index))
+            .collect();
+        self.bits = bits;
+        self.words = vec![0; self.word_count()];
+        for (index, entry) in entries.into_iter().enumerate() {
+            self.write(index, entry);
+        }
+    }
+
+    /// Tile types the grid may hold, a superset of the ones it does after edits
+    pub fn palette(&self) -> &[TileId] {
+        &self.palette
+    }
+
+    /// Every tile with its position, row by row
+    pub fn iter(&self) -> impl Iterator<Item = (LocalTilePos, TileId)> + '_ {
+        LocalTilePos::all().map(|pos| (pos, self.get(pos)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_holds(grid: &TileGrid, tile_at: impl Fn(LocalTilePos) -> TileId) {
+        for pos in LocalTilePos::all() {
+            assert_eq!(grid.get(pos), tile_at(pos), "at {:?}", pos);
+        }
+    }
+
+    #[test]
+    fn every_packing_width_reads_back_what_was_written() {
+        for (types, bits) in [(1, 0), (2, 1), (3, 2), (4, 2), (5, 4), (16, 4), (17, 8)] {
+            // Scattered so neighbors in a word differ, with tile ids past a byte
+            let tile_at = |pos: LocalTilePos| 1000 + (pos.index() * 7 % types) as TileId;
+            let grid = TileGrid::from_fn(tile_at);
+            assert_eq!(grid.bits, bits, "{} types", types);
+            assert_eq!(grid.palette().len(), types);
+            assert_holds(&grid, tile_at);
+        }
+        // One type per tile, the widest packing
+        let grid = TileGrid::from_fn(|pos| pos.index() as TileId);
+        assert_eq!(grid.bits, 8);
+        assert_holds(&grid, |pos| pos.index() as TileId);
+    }
+
+    #[test]
+    fn set_repacks_as_the_palette_outgrows_each_width() {
+        let mut grid = TileGrid::filled(0);
+        let mut expected = [0; config::CHUNK_AREA];
+        for tile in 1..=17 {
+            // Every tile so far written somewhere, the newest one last
+            let pos = LocalTilePos::from_index(tile as usize * 13 % config::CHUNK_AREA);
+            assert!(grid.set(pos, tile));
+            assert!(!grid.set(pos, tile));
+            expected[pos.index()] = tile;
+
+            let bits = match tile + 1 {
+                2 => 1,
+                3..=4 => 2,
+                5..=16 => 4,
+                _ => 8,
+            };
+            assert_eq!(grid.bits, bits, "{} types", tile + 1);
+            assert_holds(&grid, |pos| expected[pos.index()]);
+        }
+    }
+
+    #[test]
+    fn a_full_palette_drops_the_types_no_longer_used() {
+        let mut grid = TileGrid::from_fn(|pos| pos.index() as TileId);
+        let mut expected: Vec<TileId> = (0..config::CHUNK_AREA as TileId).collect();
+        // Each new type replaces one the grid held, the palette fills up and is rebuilt
+        for step in 0..600 {
+            let pos = LocalTilePos::from_index(step * 37 % config::CHUNK_AREA);
+            let tile = 5000 + step as TileId;
+            assert!(grid.set(pos, tile));
+            expected[pos.index()] = tile;
+            assert!(grid.palette().len() <= MAX_ENTRIES);
+        }
+        assert_holds(&grid, |pos| expected[pos.index()]);
+    }
+
+    #[test]
+    fn writes_stay_inside_their_own_slot() {
+        let mut grid = TileGrid::from_fn(|pos| (pos.index() % 2) as TileId);
+        // An entry too wide for one bit doesn't spill into the neighbors
+        grid.write(5, 0b11);
+        assert_eq!(grid.read(4), 0);
+        assert_eq!(grid.read(5), 1);
+        assert_eq!(grid.read(6), 0);
+    }
+}
@@ -0,0 +1,25 @@
+// hash.rs
+// deterministic hashing used wherever results must only depend on the world seed
+
+/// splitmix64 finalizer, scrambles every input bit into every output bit
+pub fn mix(mut z: u64) -> u64 {
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
+
+/// Hashes a 2D integer coordinate together with a seed and a salt
+pub fn hash2(seed: u64, x: i32, y: i32, salt: u64) -> u64 {
+    let packed = (x as u32 as u64) << 32 | y as u32 as u64;
+    mix(packed ^ mix(seed ^ salt.wrapping_mul(0x9E37_79B9_7F4A_7C15)))
+}
+
+/// Hashes a single value, e.g. a simulation tick, together with a seed and a salt
+pub fn hash1(seed: u64, value: u64, salt: u64) -> u64 {
+    mix(value ^ mix(seed ^ salt.wrapping_mul(0x9E37_79B9_7F4A_7C15)))
+}
+
+/// Maps a hash to a float in [0, 1)
+pub fn unit(hash: u64) -> f32 {
+    (hash >> 40) as f32 / (1u64 << 24) as f32
+}
@@ -0,0 +1,777 @@
+// input.rs
+// maps physical keys and sticks to game actions, so controls can be rebound
+use crate::camera::Camera;
+use crate::types::WorldPos;
+use macroquad::prelude::*;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io;
+use std::path::Path;
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum Action {
+    MoveUp,
+    MoveDown,
+    MoveLeft,
+    MoveRight,
+    OpenConsole,
+    Cast,
+    Cancel,
+    OpenSpellbook,
+    OpenMap,
+    QuickSave,
+    QuickLoad,
+    KeepReplay, // Writes the recording so far
+    OpenControls,
+    SaveGame,
+    QuitToTitle, // Saves first
+    Dismiss,     // Leaves the game over screen
+}
+
+/// Where an action is read. Two actions only clash if they are read in the same place.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum Context {
+    World,    // Walking around, and the screens opened from there
+    Console,  // Typing an incantation
+    Paused,   // The pause menu
+    GameOver, // After the wizard died
+}
+
+impl Action {
+    pub const ALL: [Action; 16] = [
+        Action::MoveUp,
+        Action::MoveDown,
+        Action::MoveLeft,
+        Action::MoveRight,
+        Action::OpenConsole,
+        Action::Cast,
+        Action::Cancel,
+        Action::OpenSpellbook,
+        Action::OpenMap,
+        Action::QuickSave,
+        Action::QuickLoad,
+        Action::KeepReplay,
+        Action::OpenControls,
+        Action::SaveGame,
+        Action::QuitToTitle,
+        Action::Dismiss,
+    ];
+
+    /// Name used in the config file
+    pub fn name(self) -> &'static str {
+        match self {
+            Action::MoveUp => "move_up",
+            Action::MoveDown => "move_down",
+            Action::MoveLeft => "move_left",
+            Action::MoveRight => "move_right",
+            Action::OpenConsole => "open_console",
+            Action::Cast => "cast",
+            Action::Cancel => "cancel",
+            Action::OpenSpellbook => "open_spellbook",
+            Action::OpenMap => "open_map",
+            Action::QuickSave => "quick_save",
+            Action::QuickLoad => "quick_load",
+            Action::KeepReplay => "keep_replay",
+            Action::OpenControls => "open_controls",
+            Action::SaveGame => "save_game",
+            Action::QuitToTitle => "quit_to_title",
+            Action::Dismiss => "dismiss",
+        }
+    }
+
+    fn contexts(self) -> &'static [Context] {
+        match self {
+            Action::Cast => &[Context::Console],
+            Action::Cancel => &[Context::World, Context::Console, Context::Paused],
+            Action::OpenControls | Action::SaveGame | Action::QuitToTitle => &[Context::Paused],
+            Action::Dismiss => &[Context::GameOver],
+            _ => &[Context::World],
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum GamepadAxis {
+    LeftX,
+    LeftY,
+}
+
+/// Something physical that triggers an action
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Binding {
+    Key(KeyCode),
+    /// A stick pushed past the dead zone, `positive` picks the direction. Sticks
+    /// are only ever held, so they drive actions like walking and never press.
+    Axis {
+        axis: GamepadAxis,
+        positive: bool,
+    },
+}
+
+const AXIS_DEAD_ZONE: f32 = 0.25;
+const AXIS_BIND_THRESHOLD: f32 = 0.5; // How far the stick is pushed to bind it
+
+impl Binding {
+    fn value(self, frame: &InputFrame) -> f32 {
+        match self {
+            Binding::Key(key) => {
+                if frame.down.contains(&key) {
+                    1.0
+                } else {
+                    0.0
+                }
+            }
+            Binding::Axis { axis, positive } => {
+                let value = match axis {
+                    GamepadAxis::LeftX => frame.stick.x,
+                    GamepadAxis::LeftY => frame.stick.y,
+                };
+                let value = if positive { value } else { -value };
+                if value > AXIS_DEAD_ZONE { value } else { 0.0 }
+            }
+        }
+    }
+
+    pub fn name(self) -> String {
+        match self {
+            Binding::Key(key) => key_name(key).unwrap_or("?").to_string(),
+            Binding::Axis { axis, positive } => {
+                let axis = match axis {
+                    GamepadAxis::LeftX => "left_x",
+                    GamepadAxis::LeftY => "left_y",
+                };
+                format!("pad:{}{}", axis, if positive { '+' } else { '-' })
+            }
+        }
+    }
+
+    fn parse(name: &str) -> Option<Binding> {
+        if let Some(axis) = name.strip_prefix("pad:") {
+            let (axis, positive) = match axis.split_at_checked(axis.len().checked_sub(1)?)? {
+                (axis, "+") => (axis, true),
+                (axis, "-") => (axis, false),
+                _ => return None,
+            };
+            let axis = match axis {
+                "left_x" => GamepadAxis::LeftX,
+                "left_y" => GamepadAxis::LeftY,
+                _ => return None,
+            };
+            return Some(Binding::Axis { axis, positive });
+        }
+        key_from_name(name).map(Binding::Key)
+    }
+
+    /// The direction the stick is pushed furthest in, if it is pushed far enough
+    /// to mean it, e.g. to bind it in the controls menu
+    pub fn from_stick(stick: Vec2) -> Option<Binding> {
+        let (axis, value) = if stick.x.abs() >= stick.y.abs() {
+            (GamepadAxis::LeftX, stick.x)
+        } else {
+            (GamepadAxis::LeftY, stick.y)
+        };
+        (value.abs() > AXIS_BIND_THRESHOLD).then_some(Binding::Axis {
+            axis,
+            positive: value > 0.0,
+        })
+    }
+
+    /// Whether the console takes the key as part of the incantation being typed,
+    /// Tab included since it completes the word
+    fn is_typing(self) -> bool {
+        match self {
+            Binding::Key(key) => {
+                (KeyCode::A as u16..=KeyCode::Z as u16).contains(&(key as u16))
+                    || matches!(key, KeyCode::Space | KeyCode::Backspace | KeyCode::Tab)
+            }
+            Binding::Axis { .. } => false,
+        }
+    }
+}
+
+/// What a new binding would clash with
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Conflict {
+    Action(Action),
+    Typing, // The key is read as text while the console is open
+}
+
+impl Conflict {
+    pub fn name(self) -> &'static str {
+        match self {
+            Conflict::Action(action) => action.name(),
+            Conflict::Typing => "console typing",
+        }
+    }
+}
+
+/// Everything the player did during one game tick. The game only reads input
+/// through these, so replaying recorded frames replays the session exactly.
+#[derive(Clone, Default, PartialEq, Debug)]
+pub struct InputFrame {
+    pub down: Vec<KeyCode>,    // Held keys
+    pub pressed: Vec<KeyCode>, // Keys that went down this tick
+    pub chars: Vec<char>,      // Typed text
+    pub stick: Vec2,           // Left stick, from -1 to 1 on each axis
+    pub mouse: WorldPos,       // Cursor in the world
+}
+
+impl InputFrame {
+    /// Reads the keyboard and mouse for this frame, the stick is polled elsewhere
+    pub fn capture(camera: &Camera, stick: Vec2) -> Self {
+        // Sorted so identical input gives identical frames
+        let sorted = |keys: HashSet<KeyCode>| {
+            let mut keys: Vec<KeyCode> = keys.into_iter().collect();
+            keys.sort_by_key(|&key| key as u16);
+            keys
+        };
+        InputFrame {
+            down: sorted(get_keys_down()),
+            pressed: sorted(get_keys_pressed()),
+            chars: std::iter::from_fn(get_char_pressed).collect(),
+            stick,
+            mouse: camera.screen_to_world(Vec2::from(mouse_position())),
+        }
+    }
+
+    /// Adds input captured after this frame, so presses and typing from frames
+    /// drawn between two ticks reach the next tick
+    pub fn merge(&mut self, later: InputFrame) {
+        for key in later.pressed {
+            if !self.pressed.contains(&key) {
+                self.pressed.push(key);
+            }
+        }
+        self.pressed.sort_by_key(|&key| key as u16);
+        self.chars.extend(later.chars);
+        self.down = later.down;
+        self.stick = later.stick;
+        self.mouse = later.mouse;
+    }
+
+    /// Input left for the tick after this one: keys stay held, presses and
+    /// typing have been used up
+    pub fn held(&self) -> InputFrame {
+        InputFrame {
+            down: self.down.clone(),
+            stick: self.stick,
+            mouse: self.mouse,
+            ..InputFrame::default()
+        }
+    }
+}
+
+/// Every action with the bindings that trigger it
+#[derive(Clone, PartialEq, Debug)]
+pub struct InputMap {
+    bindings: HashMap<Action, Vec<Binding>>,
+}
+
+impl InputMap {
+    pub fn new() -> Self {
+        use KeyCode::*;
+        let defaults: [(Action, &[Binding]); Action::ALL.len()] = [
+            (
+                Action::MoveUp,
+                &[
+                    Binding::Key(W),
+                    Binding::Key(Up),
+                    stick(GamepadAxis::LeftY, false),
+                ],
+            ),
+            (
+                Action::MoveDown,
+                &[
+                    Binding::Key(S),
+                    Binding::Key(Down),
+                    stick(GamepadAxis::LeftY, true),
+                ],
+            ),
+            (
+                Action::MoveLeft,
+                &[
+                    Binding::Key(A),
+                    Binding::Key(Left),
+                    stick(GamepadAxis::LeftX, false),
+                ],
+            ),
+            (
+                Action::MoveRight,
+                &[
+                    Binding::Key(D),
+                    Binding::Key(Right),
+                    stick(GamepadAxis::LeftX, true),
+                ],
+            ),
+            (Action::OpenConsole, &[Binding::Key(Enter)]),
+            (Action::Cast, &[Binding::Key(Enter)]),
+            (Action::Cancel, &[Binding::Key(Escape)]),
+            (Action::OpenSpellbook, &[Binding::Key(Tab)]),
+            (Action::OpenMap, &[Binding::Key(M)]),
+            (Action::QuickSave, &[Binding::Key(F5)]),
+            (Action::QuickLoad, &[Binding::Key(F9)]),
+            (Action::KeepReplay, &[Binding::Key(F8)]),
+            (Action::OpenControls, &[Binding::Key(C)]),
+            (Action::SaveGame, &[Binding::Key(S)]),
+            (Action::QuitToTitle, &[Binding::Key(Q)]),
+            (Action::Dismiss, &[Binding::Key(Enter)]),
+        ];
+        InputMap {
+            bindings: defaults
+                .into_iter()
+                .map(|(action, bindings)| (action, bindings.to_vec()))
+                .collect(),
+        }
+    }
+
+    /// Reads the bindings from a config file, the defaults are used if there is none.
+    /// Actions missing from the file keep their default bindings.
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let mut map = Self::new();
+        let text = match fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(map),
+            Err(err) => return Err(err),
+        };
+
+        for (number, line) in text.lines().enumerate() {
+            if line.trim_start().starts_with('#') {
+                continue;
+            }
+            for problem in map.read_line(line) {
+                eprintln!("{}:{}: {}", path.display(), number + 1, problem);
+            }
+        }
+        Ok(map)
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let mut out =
+            String::from("# action followed by its keys, e.g. move_up W Up pad:left_y-\n");
+        for action in Action::ALL {
+            out += &self.line(action);
+            out += "\n";
+        }
+        fs::write(path, out)
+    }
+
+    /// `action key key...`, as written to the config file
+    pub fn line(&self, action: Action) -> String {
+        let mut line = action.name().to_string();
+        for binding in self.bindings(action) {
+            line += &format!(" {}", binding.name());
+        }
+        line
+    }
+
+    /// Replaces an action's bindings with the ones on a line written by `line`.
+    /// Returns what couldn't be read, keys that can't be are left out.
+    pub fn read_line(&mut self, line: &str) -> Vec<String> {
+        let mut fields = line.split_whitespace();
+        let Some(name) = fields.next() else {
+            return Vec::new();
+        };
+        let Some(action) = Action::ALL.into_iter().find(|action| action.name() == name) else {
+            return vec![format!("unknown action '{}'", name)];
+        };
+        let mut problems = Vec::new();
+        let bindings = self.bindings.entry(action).or_default();
+        bindings.clear();
+        for binding in fields {
+            match Binding::parse(binding) {
+                Some(binding) => bindings.push(binding),
+                None => problems.push(format!("unknown input '{}'", binding)),
+            }
+        }
+        problems
+    }
+
+    pub fn bindings(&self, action: Action) -> &[Binding] {
+        self.bindings.get(&action).map_or(&[], Vec::as_slice)
+    }
+
+    /// How strongly the action is held, from 0 to 1
+    pub fn strength(&self, action: Action, frame: &InputFrame) -> f32 {
+        self.bindings(action)
+            .iter()
+            .map(|binding| binding.value(frame))
+            .fold(0.0, f32::max)
+            .min(1.0)
+    }
+
+    pub fn is_pressed(&self, action: Action, frame: &InputFrame) -> bool {
+        self.bindings(action).iter().any(|binding| match binding {
+            Binding::Key(key) => frame.pressed.contains(key),
+            Binding::Axis { .. } => false,
+        })
+    }
+
+    /// Walking direction from the four move actions, at most unit length
+    pub fn movement(&self, frame: &InputFrame) -> Vec2 {
+        let strength = |action| self.strength(action, frame);
+        let direction = Vec2::new(
+            strength(Action::MoveRight) - strength(Action::MoveLeft),
+            strength(Action::MoveDown) - strength(Action::MoveUp),
+        );
+        direction.clamp_length_max(1.0)
+    }
+
+    /// What would fire together with `action` if it were bound to `binding`:
+    /// other actions read at the same time, or typing if the console reads it
+    pub fn conflicts(&self, action: Action, binding: Binding) -> Vec<Conflict> {
+        let contexts = action.contexts();
+        let typing = (contexts.contains(&Context::Console) && binding.is_typing())
+            .then_some(Conflict::Typing);
+        let actions = Action::ALL
+            .into_iter()
+            .filter(|&other| other != action)
+            .filter(|other| {
+                other
+                    .contexts()
+                    .iter()
+                    .any(|context| contexts.contains(context))
+            })
+            .filter(|&other| self.bindings(other).contains(&binding))
+            .map(Conflict::Action);
+        typing.into_iter().chain(actions).collect()
+    }
+
+    /// Adds a binding, refusing ones that clash with another action
+    pub fn bind(&mut self, action: Action, binding: Binding) -> Result<(), Vec<Conflict>> {
+        let conflicts = self.conflicts(action, binding);
+        if !conflicts.is_empty() {
+            return Err(conflicts);
+        }
+        let bindings = self.bindings.entry(action).or_default();
+        if !bindings.contains(&binding) {
+            bindings.push(binding);
+        }
+        Ok(())
+    }
+
+    pub fn clear(&mut self, action: Action) {
+        self.bindings.insert(action, Vec::new());
+    }
+}
+
+const fn stick(axis: GamepadAxis, positive: bool) -> Binding {
+    Binding::Axis { axis, positive }
+}
+
+pub fn key_name(key: KeyCode) -> Option<&'static str> {
+    KEY_NAMES
+        .iter()
+        .find(|&&(_, named)| named == key)
+        .map(|&(name, _)| name)
+}
+
+pub fn key_from_name(name: &str) -> Option<KeyCode> {
+    KEY_NAMES
+        .iter()
+        .find(|(key_name, _)| key_name.eq_ignore_ascii_case(name))
+        .map(|&(_, key)| key)
+}
+
+/// Keys that can be written in the config file
+const KEY_NAMES: &[(&str, KeyCode)] = &[
+    ("A", KeyCode::A),
+    ("B", KeyCode::B),
+    ("C", KeyCode::C),
+    ("D", KeyCode::D),
+    ("E", KeyCode::E),
+    ("F", KeyCode::F),
+    ("G", KeyCode::G),
+    ("H", KeyCode::H),
+    ("I", KeyCode::I),
+    ("J", KeyCode::J),
+    ("K", KeyCode::K),
+    ("L", KeyCode::L),
+    ("M", KeyCode::M),
+    ("N", KeyCode::N),
+    ("O", KeyCode::O),
+    ("P", KeyCode::P),
+    ("Q", KeyCode::Q),
+    ("R", KeyCode::R),
+    ("S", KeyCode::S),
+    ("T", KeyCode::T),
+    ("U", KeyCode::U),
+    ("V", KeyCode::V),
+    ("W", KeyCode::W),
+    ("X", KeyCode::X),
+    ("Y", KeyCode::Y),
+    ("Z", KeyCode::Z),
+    ("0", KeyCode::Key0),
+    ("1", KeyCode::Key1),
+    ("2", KeyCode::Key2),
+    ("3", KeyCode::Key3),
+    ("4", KeyCode::Key4),
+    ("5", KeyCode::Key5),
+    ("6", KeyCode::Key6),
+    ("7", KeyCode::Key7),
+    ("8", KeyCode::Key8),
+    ("9", KeyCode::Key9),
+    ("Up", KeyCode::Up),
+    ("Down", KeyCode::Down),
+    ("Left", KeyCode::Left),
+    ("Right", KeyCode::Right),
+    ("Space", KeyCode::Space),
+    ("Enter", KeyCode::Enter),
+    ("KpEnter", KeyCode::KpEnter),
+    ("Escape", KeyCode::Escape),
+    ("Tab", KeyCode::Tab),
+    ("Backspace", KeyCode::Backspace),
+    ("LeftShift", KeyCode::LeftShift),
+    ("RightShift", KeyCode::RightShift),
+    ("LeftControl", KeyCode::LeftControl),
+    ("RightControl", KeyCode::RightControl),
+    ("LeftAlt", KeyCode::LeftAlt),
+    ("RightAlt", KeyCode::RightAlt),
+    ("F1", KeyCode::F1),
+    ("F2", KeyCode::F2),
+    ("F3", KeyCode::F3),
+    ("F4", KeyCode::F4),
+    ("F5", KeyCode::F5),
+    ("F6", KeyCode::F6),
+    ("F7", KeyCode::F7),
+    ("F8", KeyCode::F8),
+    ("F9", KeyCode::F9),
+    ("F10", KeyCode::F10),
+    ("F11", KeyCode::F11),
+    ("F12", KeyCode::F12),
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn presses_between_ticks_reach_the_next_tick_once() {
+        let mut pending = InputFrame::default();
+        pending.merge(InputFrame {
+            down: vec![KeyCode::W],
+            pressed: vec![KeyCode::W],
+            chars: vec!['w'],
+            ..InputFrame::default()
+        });
+        pending.merge(InputFrame {
+            down: vec![KeyCode::A, KeyCode::W],
+            pressed: vec![KeyCode::A],
+            chars: vec!['a'],
+            ..InputFrame::default()
+        });
+        assert_eq!(pending.pressed, [KeyCode::A, KeyCode::W]);
+        assert_eq!(pending.chars, ['w', 'a']);
+        assert_eq!(pending.down, [KeyCode::A, KeyCode::W]);
+
+        let next = pending.held();
+        assert_eq!(next.down, pending.down);
+        assert!(next.pressed.is_empty() && next.chars.is_empty());
+    }
+
+    #[test]
+    fn actions_only_clash_when_read_at_the_same_time() {
+        let input = InputMap::new();
+        // Enter opens the console while walking and casts while typing
+        assert!(
+            input
+                .conflicts(Action::OpenConsole, Binding::Key(KeyCode::Enter))
+                .is_empty()
+        );
+        assert_eq!(
+            input.conflicts(Action::OpenMap, Binding::Key(KeyCode::W)),
+            [Conflict::Action(Action::MoveUp)]
+        );
+        // Cancel is read in both, so it clashes with either side
+        assert_eq!(
+            input.conflicts(Action::Cancel, Binding::Key(KeyCode::Enter)),
+            [
+                Conflict::Action(Action::OpenConsole),
+                Conflict::Action(Action::Cast)
+            ]
+        );
+        assert!(
+            input
+                .conflicts(Action::OpenMap, Binding::Key(KeyCode::F1))
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn menu_actions_only_clash_inside_their_menu() {
+        let input = InputMap::new();
+        // S walks down, but the pause menu doesn't read walking
+        assert_eq!(
+            input.conflicts(Action::OpenControls, Binding::Key(KeyCode::S)),
+            [Conflict::Action(Action::SaveGame)]
+        );
+        assert_eq!(
+            input.conflicts(Action::QuitToTitle, Binding::Key(KeyCode::Escape)),
+            [Conflict::Action(Action::Cancel)]
+        );
+        assert!(
+            input
+                .conflicts(Action::Dismiss, Binding::Key(KeyCode::Q))
+                .is_empty()
+        );
+        assert_eq!(
+            input.conflicts(Action::OpenMap, Binding::Key(KeyCode::F5)),
+            [Conflict::Action(Action::QuickSave)]
+        );
+    }
+
+    #[test]
+    fn every_default_binding_can_be_written_out() {
+        let input = InputMap::new();
+        for action in Action::ALL {
+            assert!(!input.bindings(action).is_empty(), "{:?}", action);
+            for &binding in input.bindings(action) {
+                assert_eq!(Binding::parse(&binding.name()), Some(binding));
+            }
+        }
+    }
+
+    #[test]
+    fn console_actions_cant_take_typing_keys() {
+        let input = InputMap::new();
+        for key in [KeyCode::Q, KeyCode::Space, KeyCode::Backspace, KeyCode::Tab] {
+            assert_eq!(
+                input.conflicts(Action::Cast, Binding::Key(key)),
+                [Conflict::Typing]
+            );
+        }
+        assert_eq!(
+            input.conflicts(Action::Cancel, Binding::Key(KeyCode::W)),
+            [Conflict::Typing, Conflict::Action(Action::MoveUp)]
+        );
+        // Digits aren't part of incantations and walking doesn't read text
+        assert!(
+            input
+                .conflicts(Action::Cast, Binding::Key(KeyCode::Key1))
+                .is_empty()
+        );
+        assert!(
+            input
+                .conflicts(Action::OpenMap, Binding::Key(KeyCode::Q))
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn bind_refuses_clashes_and_ignores_repeats() {
+        let mut input = InputMap::new();
+        assert_eq!(
+            input.bind(Action::Cast, Binding::Key(KeyCode::Z)),
+            Err(vec![Conflict::Typing])
+        );
+        assert_eq!(input.bindings(Action::Cast), [Binding::Key(KeyCode::Enter)]);
+
+        input
+            .bind(Action::OpenMap, Binding::Key(KeyCode::F1))
+            .unwrap();
+        input
+            .bind(Action::OpenMap, Binding::Key(KeyCode::F1))
+            .unwrap();
+        assert_eq!(
+            input.bindings(Action::OpenMap),
+            [Binding::Key(KeyCode::M), Binding::Key(KeyCode::F1)]
+        );
+
+        input.clear(Action::MoveUp);
+        input
+            .bind(Action::OpenMap, Binding::Key(KeyCode::W))
+            .unwrap();
+        assert!(input.bindings(Action::MoveUp).is_empty());
+    }
+
+    #[test]
+    fn bindings_survive_the_config_file() {
+        let path = std::env::temp_dir().join(format!("input-{}.cfg", std::process::id()));
+        let mut input = InputMap::new();
+        input.clear(Action::MoveUp);
+        input
+            .bind(Action::MoveUp, Binding::Key(KeyCode::I))
+            .unwrap();
+        input
+            .bind(Action::OpenMap, Binding::Key(KeyCode::F2))
+            .unwrap();
+        // An inverted stick
+        input.clear(Action::MoveDown);
+        input
+            .bind(Action::MoveUp, stick(GamepadAxis::LeftY, true))
+            .unwrap();
+        input.clear(Action::OpenSpellbook);
+        input.save(&path).unwrap();
+
+        let read = InputMap::load(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        for action in Action::ALL {
+            assert_eq!(
+                read.bindings(action),
+                input.bindings(action),
+                "{:?}",
+                action
+            );
+        }
+    }
+
+    #[test]
+    fn sticks_walk_once_past_the_dead_zone() {
+        let input = InputMap::new();
+        let tilted = |stick| InputFrame {
+            stick,
+            ..InputFrame::default()
+        };
+        assert_eq!(input.movement(&tilted(Vec2::new(0.2, -0.1))), Vec2::ZERO);
+        assert_eq!(
+            input.movement(&tilted(Vec2::new(0.6, -0.8))),
+            Vec2::new(0.6, -0.8)
+        );
+        // Keys and the stick together never walk faster than either
+        let both = InputFrame {
+            down: vec![KeyCode::D],
+            ..tilted(Vec2::new(0.5, 0.0))
+        };
+        assert_eq!(input.movement(&both), Vec2::X);
+        assert!(!input.is_pressed(Action::MoveRight, &both));
+    }
+
+    #[test]
+    fn the_stick_binds_the_way_it_is_pushed_furthest() {
+        assert_eq!(Binding::from_stick(Vec2::new(0.3, 0.2)), None);
+        assert_eq!(
+            Binding::from_stick(Vec2::new(0.3, -0.9)),
+            Some(stick(GamepadAxis::LeftY, false))
+        );
+        assert_eq!(
+            Binding::from_stick(Vec2::new(0.7, 0.6)),
+            Some(stick(GamepadAxis::LeftX, true))
+        );
+
+        for name in ["pad:left_x+", "pad:left_y-"] {
+            assert_eq!(Binding::parse(name).unwrap().name(), name);
+        }
+        for name in ["pad:left_x", "pad:right_x+", "pad:", "pad:+"] {
+            assert_eq!(Binding::parse(name), None, "{}", name);
+        }
+    }
+
+    #[test]
+    fn missing_config_lines_keep_their_defaults() {
+        let path = std::env::temp_dir().join(format!("input-partial-{}.cfg", std::process::id()));
+        fs::write(&path, "# comment\nopen_map F3 Nonsense\nno_such_action K\n").unwrap();
+        let read = InputMap::load(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(read.bindings(Action::OpenMap), [Binding::Key(KeyCode::F3)]);
+        assert_eq!(
+            read.bindings(Action::MoveUp),
+            InputMap::new().bindings(Action::MoveUp)
+        );
+        assert!(
+            InputMap::load(Path::new("no/such/input.cfg"))
+                .unwrap()
+                .bindings(Action::Cast)
+                .contains(&Binding::Key(KeyCode::Enter))
+        );
+    }
+}
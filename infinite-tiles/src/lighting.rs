@@ -0,0 +1,290 @@
+// lighting.rs
+// light level of every tile on screen from the time of day and nearby light sources
+use crate::camera::Camera;
+use crate::config;
+use crate::fog;
+use crate::types::{TilePos, TileRect};
+use macroquad::miniquad::{BlendFactor, BlendState, BlendValue, Equation};
+use macroquad::prelude::*;
+use std::cell::RefCell;
+
+const NIGHT_AMBIENT: Color = Color::new(0.12, 0.14, 0.3, 1.0);
+pub const STAFF_LIGHT: Color = Color::new(1.0, 0.85, 0.6, 1.0);
+
+const VERTEX_SHADER: &str = r#"#version 100
+attribute vec3 position;
+attribute vec2 texcoord;
+
+varying lowp vec2 uv;
+
+uniform mat4 Model;
+uniform mat4 Projection;
+
+void main() {
+    gl_Position = Projection * Model * vec4(position, 1);
+    uv = texcoord;
+}"#;
+
+const FRAGMENT_SHADER: &str = r#"#version 100
+varying lowp vec2 uv;
+
+uniform sampler2D Texture;
+
+void main() {
+    gl_FragColor = texture2D(Texture, uv);
+}"#;
+
+/// Something giving off light, e.g. burning ground or the wizard's staff
+#[derive(Clone, Copy, Debug)]
+pub struct PointLight {
+    pub tile: TilePos,
+    pub radius: i32, // Tiles, the light fades out towards the edge
+    pub color: Color,
+}
+
+/// A color as it looks under a light level, alpha is kept
+pub fn shade(color: Color, light: Color) -> Color {
+    Color::new(
+        color.r * light.r,
+        color.g * light.g,
+        color.b * light.b,
+        color.a,
+    )
+}
+
+/// Light of the sky at a time of day, 0 is midnight and 0.5 noon
+pub fn ambient(time_of_day: f32) -> Color {
+    let sun = 0.5 - 0.5 * (time_of_day * std::f32::consts::TAU).cos();
+    // Full daylight most of the day, with a short dusk and dawn
+    let t = ((sun - 0.25) * 2.0).clamp(0.0, 1.0);
+    Color::from_vec(NIGHT_AMBIENT.to_vec().lerp(WHITE.to_vec(), t))
+}
+
+/// Light level of each tile in a rectangle, one color per tile
+pub struct LightMap {
+    origin: TilePos, // Top-left tile
+    width: i32,
+    height: i32,
+    ambient: Color, // Outside the rectangle
+    levels: Vec<Vec3>,
+}
+
+impl LightMap {
+    /// Adds up the ambient light and every light reaching the tiles of `area`.
+    /// Light doesn't pass solid tiles, but lights them up.
+    pub fn build(
+        area: TileRect,
+        ambient: Color,
+        lights: &[PointLight],
+        is_solid: impl Fn(TilePos) -> bool,
+    ) -> Self {
+        let (min, max) = (area.min, area.max);
+        let width = (max.x - min.x + 1).max(0);
+        let height = (max.y - min.y + 1).max(0);
+        let mut map = LightMap {
+            origin: min,
+            width,
+            height,
+            ambient,
+            levels: vec![ambient.to_vec().truncate(); (width * height) as usize],
+        };
+
+        for light in lights {
+            let color = light.color.to_vec().truncate();
+            let reach = light.radius as f32 + 1.0;
+            for tile in fog::visible_tiles(light.tile, light.radius, &is_solid) {
+                if let Some(index) = map.index(tile) {
+                    let falloff = 1.0 - light.tile.distance(tile) / reach;
+                    map.levels[index] += color * falloff.max(0.0);
+                }
+            }
+        }
+        map
+    }
+
+    fn index(&self, pos: TilePos) -> Option<usize> {
+        let (x, y) = (pos.x - self.origin.x, pos.y - self.origin.y);
+        (x >= 0 && y >= 0 && x < self.width && y < self.height)
+            .then(|| (y * self.width + x) as usize)
+    }
+
+    /// Light falling on a tile, capped at full brightness
+    pub fn level(&self, pos: TilePos) -> Color {
+        match self.index(pos) {
+            Some(index) => {
+                let level = self.levels[index].min(Vec3::ONE);
+                Color::new(level.x, level.y, level.z, 1.0)
+            }
+            None => self.ambient,
+        }
+    }
+}
+
+/// Puts a light map on screen. With shaders the map is smoothed and multiplied over
+/// everything drawn so far, without them the world tints each tile with its level.
+pub struct Lighting {
+    material: Option<Material>,
+    texture: RefCell<Option<Texture2D>>,
+}
+
+impl Lighting {
+    pub fn new() -> Self {
+        let material = load_material(
+            ShaderSource::Glsl {
+                vertex: VERTEX_SHADER,
+                fragment: FRAGMENT_SHADER,
+            },
+            MaterialParams {
+                pipeline_params: PipelineParams {
+                    // Multiplies the screen by the light map
+                    color_blend: Some(BlendState::new(
+                        Equation::Add,
+                        BlendFactor::Zero,
+                        BlendFactor::Value(BlendValue::SourceColor),
+                    )),
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+        )
+        .inspect_err(|err| {
+            eprintln!(
+                "lighting shader unavailable, tinting tiles instead: {}",
+                err
+            );
+        })
+        .ok();
+        Lighting {
+            material,
+            texture: RefCell::new(None),
+        }
+    }
+
+    /// Color to draw a tile, sprite or particle with, the overlay lights them when
+    /// shaders are available
+    pub fn tint(&self, map: &LightMap, pos: TilePos) -> Color {
+        if self.material.is_some() {
+            WHITE
+        } else {
+            map.level(pos)
+        }
+    }
+
+    pub fn draw_overlay(&self, map: &LightMap, camera: &Camera) {
+        let Some(material) = &self.material else {
+            return;
+        };
+        if map.width == 0 || map.height == 0 {
+            return;
+        }
+
+        let mut bytes = Vec::with_capacity(map.levels.len() * 4);
+        for y in 0..map.height {
+            for x in 0..map.width {
+                let pos = TilePos {
+                    x: map.origin.x + x,
+                    y: map.origin.y + y,
+                };
+                bytes.extend(<[u8; 4]>::from(map.level(pos)));
+            }
+        }
+
+        // Reuse the texture while the screen keeps its size
+        let mut cached = self.texture.borrow_mut();
+        let texture = match cached.as_ref() {
+            Some(texture)
+                if texture.width() as i32 == map.width && texture.height() as i32 == map.height =>
+            {
+                texture.update_from_bytes(map.width as u32, map.height as u32, &bytes);
+                texture
+            }
+            _ => {
+                let texture = Texture2D::from_rgba8(map.width as u16, map.height as u16, &bytes);
+                texture.set_filter(FilterMode::Linear);
+                cached.insert(texture)
+            }
+        };
+
+        let tile_size = config::TILE_SIZE as f32;
+        let top_left = camera.world_to_screen(map.origin.world_pos());
+        gl_use_material(material);
+        draw_texture_ex(
+            texture,
+            top_left.x,
+            top_left.y,
+            WHITE,
+            DrawTextureParams {
+                dest_size: Some(Vec2::new(map.width as f32, map.height as f32) * tile_size),
+                ..Default::default()
+            },
+        );
+        gl_use_default_material();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DARK: Color = Color::new(0.1, 0.1, 0.1, 1.0);
+
+    fn area(radius: i32) -> TileRect {
+        TileRect {
+            min: TilePos {
+                x: -radius,
+                y: -radius,
+            },
+            max: TilePos {
+                x: radius,
+                y: radius,
+            },
+        }
+    }
+
+    fn torch(tile: TilePos) -> PointLight {
+        PointLight {
+            tile,
+            radius: 4,
+            color: Color::new(0.8, 0.4, 0.0, 1.0),
+        }
+    }
+
+    #[test]
+    fn light_fades_with_distance() {
+        let origin = TilePos { x: 0, y: 0 };
+        let map = LightMap::build(area(8), DARK, &[torch(origin)], |_| false);
+
+        // The full light on its own tile, over the ambient
+        let level = map.level(origin).to_vec();
+        assert!(level.abs_diff_eq(Vec4::new(0.9, 0.5, 0.1, 1.0), 1e-6));
+        let red = |x| map.level(TilePos { x, y: 0 }).r;
+        for x in 0..5 {
+            assert!(red(x) > red(x + 1), "{} is no brighter than {}", x, x + 1);
+        }
+        // Past the radius only the ambient light is left
+        assert_eq!(map.level(TilePos { x: 5, y: 0 }), DARK);
+        assert_eq!(map.level(TilePos { x: 4, y: 4 }), DARK);
+        // Outside the map too
+        assert_eq!(map.level(TilePos { x: 100, y: 0 }), DARK);
+    }
+
+    #[test]
+    fn walls_are_lit_but_cast_shadows() {
+        let origin = TilePos { x: 0, y: 0 };
+        let wall = TilePos { x: 2, y: 0 };
+        let map = LightMap::build(area(8), DARK, &[torch(origin)], |pos| pos == wall);
+
+        assert!(map.level(wall).r > DARK.r);
+        assert_eq!(map.level(TilePos { x: 3, y: 0 }), DARK);
+        // The other side of the torch is open
+        assert!(map.level(TilePos { x: -3, y: 0 }).r > DARK.r);
+    }
+
+    #[test]
+    fn lights_add_up_to_full_brightness() {
+        let origin = TilePos { x: 0, y: 0 };
+        let lights = [torch(origin), torch(origin), torch(origin)];
+        let map = LightMap::build(area(8), DARK, &lights, |_| false);
+        assert_eq!(map.level(origin), Color::new(1.0, 1.0, 0.1, 1.0));
+    }
+}
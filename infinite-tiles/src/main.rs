@@ -1,45 +1,68 @@
 // main.rs
+mod animation;
+mod autotile;
+mod bench;
+mod biome;
 mod camera;
+mod casting;
+mod clock;
 mod config;
+mod console;
+mod entity;
+mod fog;
+mod fuzzy;
+mod game;
+mod gamepad;
+mod grid;
+mod hash;
+mod input;
+mod lighting;
+mod map;
+mod menu;
+mod particles;
+mod pathfinding;
 mod player;
+mod raycast;
+mod replay;
+mod save;
+mod shape;
+mod simulation;
+mod spell;
+mod state;
 mod texture;
+mod tile;
+mod tokenizer;
 mod types;
+mod weather;
 mod world;
 
-use camera::Camera;
 use macroquad::prelude::*;
-use player::Player;
-use world::World;
+use state::StateMachine;
 
-#[macroquad::main("Infinite Tiles")]
-async fn main() {
-    let mut camera = Camera::new();
-    let mut player = Player::new();
-    let mut world = World::new().await;
-
-    loop {
-        clear_background(WHITE);
-
-        // Update
-        player.update();
-        camera.update(player.get_position());
-
-        // Update world if player moved to new chunk
-        if camera.chunk_changed(player.get_position()) {
-            world.update(player.get_chunk_pos()).await;
+fn main() {
+    // Only drawing needs a window, the rest of the bench runs without one
+    let args: Vec<String> = std::env::args().collect();
+    let bench = args.iter().any(|arg| arg == "--bench");
+    if bench {
+        bench::run_headless();
+        if args.iter().any(|arg| arg == "--no-window") {
+            return;
         }
+    }
+    macroquad::Window::new("Infinite Tiles", run(bench));
+}
 
-        // Draw
-        world.draw(&camera);
-        player.draw(&camera);
+async fn run(bench: bool) {
+    if bench {
+        bench::run_drawing().await;
+        return;
+    }
 
-        // Debug info
-        #[cfg(debug_assertions)]
-        {
-            world.draw_debug_info();
-            draw_text("WASD/Arrow Keys to move", 10.0, 30.0, 20.0, BLACK);
-        }
+    let mut states = StateMachine::new().await;
 
+    loop {
+        clear_background(WHITE);
+        states.frame().await;
         next_frame().await;
     }
 }
@@ -0,0 +1,239 @@
+// map.rs
+// minimap in the corner of the screen and the full-screen map of every visited chunk
+use crate::config;
+use crate::fog::FOG_COLOR;
+use crate::input::{Action, InputFrame, InputMap};
+use crate::menu;
+use crate::tile::{Layer, tile_def};
+use crate::types::{ChunkPos, LocalTilePos, WorldPos};
+use crate::world::World;
+use macroquad::prelude::*;
+use std::cell::RefCell;
+use std::collections::HashMap;
+
+const MIN_ZOOM: f32 = 1.0; // Screen pixels per tile
+const MAX_ZOOM: f32 = 16.0;
+const PAN_SPEED: f32 = 12.0; // Screen pixels per frame
+const MARKER_PICK_RADIUS: f32 = 10.0; // Screen pixels
+
+/// One pixel per tile images of chunks, only rebuilt when a chunk changes.
+/// Pixels take the tile's map color, so animated tiles don't rebuild them.
+pub struct ChunkImages {
+    cache: RefCell<HashMap<ChunkPos, (u32, Texture2D)>>,
+}
+
+impl ChunkImages {
+    pub fn new() -> Self {
+        ChunkImages {
+            cache: RefCell::new(HashMap::new()),
+        }
+    }
+
+    /// Image of a visited chunk, None if the wizard has never been near it
+    fn get(&self, world: &World, pos: ChunkPos) -> Option<Texture2D> {
+        let revision = world.chunk_revision(pos)?;
+        if let Some((cached, texture)) = self.cache.borrow().get(&pos)
+            && *cached == revision
+        {
+            return Some(texture.clone());
+        }
+
+        let chunk = world.map_chunk(pos)?;
+        let mut bytes = Vec::with_capacity(config::CHUNK_AREA * 4);
+        for local in LocalTilePos::all() {
+            let color = if chunk.is_explored(local) {
+                // The topmost layer that shows on maps
+                Layer::ALL
+                    .iter()
+                    .rev()
+                    .map(|&layer| tile_def(chunk.get(layer, local)).map_color)
+                    .find(|color| color.a > 0.0)
+                    .unwrap_or(FOG_COLOR)
+            } else {
+                FOG_COLOR
+            };
+            bytes.extend(<[u8; 4]>::from(color));
+        }
+        let size = config::CHUNK_SIZE as u16;
+        let texture = Texture2D::from_rgba8(size, size, &bytes);
+        texture.set_filter(FilterMode::Nearest);
+        self.cache
+            .borrow_mut()
+            .insert(pos, (revision, texture.clone()));
+        Some(texture)
+    }
+}
+
+/// Corner widget showing the chunks around the wizard, with a caption underneath
+pub fn draw_minimap(
+    world: &World,
+    images: &ChunkImages,
+    player_pos: WorldPos,
+    markers: &[WorldPos],
+    caption: &str,
+) {
+    let radius = config::MINIMAP_RADIUS_CHUNKS;
+    let chunk_pixels = config::CHUNK_SIZE as f32 * config::MINIMAP_TILE_PIXELS;
+    let size = (radius * 2 + 1) as f32 * chunk_pixels;
+    let origin = Vec2::new(screen_width() - size - 10.0, 10.0);
+    let center = player_pos.chunk;
+
+    draw_rectangle(origin.x, origin.y, size, size, BLACK);
+    for dy in -radius..=radius {
+        for dx in -radius..=radius {
+            let pos = ChunkPos {
+                x: center.x + dx,
+                y: center.y + dy,
+            };
+            if let Some(texture) = images.get(world, pos) {
+                let x = origin.x + (dx + radius) as f32 * chunk_pixels;
+                let y = origin.y + (dy + radius) as f32 * chunk_pixels;
+                draw_texture_ex(
+                    &texture,
+                    x,
+                    y,
+                    WHITE,
+                    DrawTextureParams {
+                        dest_size: Some(Vec2::splat(chunk_pixels)),
+                        ..Default::default()
+                    },
+                );
+            }
+        }
+    }
+
+    // World pixels to minimap pixels, relative to the top-left shown chunk
+    let top_left = ChunkPos {
+        x: center.x - radius,
+        y: center.y - radius,
+    }
+    .world_pos();
+    let scale = config::MINIMAP_TILE_PIXELS / config::TILE_SIZE as f32;
+    let to_minimap = |world_pos: WorldPos| origin + (world_pos - top_left) * scale;
+    let inside =
+        |point: Vec2| point.cmpge(origin).all() && point.cmple(origin + Vec2::splat(size)).all();
+
+    for &marker in markers {
+        let point = to_minimap(marker);
+        if inside(point) {
+            draw_circle(point.x, point.y, 3.0, RED);
+        }
+    }
+    let player = to_minimap(player_pos);
+    draw_circle(player.x, player.y, 3.0, WHITE);
+    draw_rectangle_lines(origin.x, origin.y, size, size, 2.0, WHITE);
+    draw_text(caption, origin.x, origin.y + size + 20.0, 20.0, BLACK);
+}
+
+/// Full-screen map of every visited chunk, panned and zoomed by the player
+pub struct WorldMap {
+    pub markers: Vec<WorldPos>, // Kept in the save
+    center: WorldPos,           // In the middle of the screen
+    zoom: f32,                  // Screen pixels per tile
+}
+
+impl WorldMap {
+    pub fn new() -> Self {
+        WorldMap {
+            markers: Vec::new(),
+            center: WorldPos::default(),
+            zoom: 4.0,
+        }
+    }
+
+    /// Opens the map centered on the wizard
+    pub fn open(&mut self, player_pos: WorldPos) {
+        self.center = player_pos;
+    }
+
+    fn world_to_screen(&self, world_pos: WorldPos) -> Vec2 {
+        let screen_center = Vec2::new(screen_width(), screen_height()) / 2.0;
+        (world_pos - self.center) * (self.zoom / config::TILE_SIZE as f32) + screen_center
+    }
+
+    fn screen_to_world(&self, screen_pos: Vec2) -> WorldPos {
+        let screen_center = Vec2::new(screen_width(), screen_height()) / 2.0;
+        self.center + (screen_pos - screen_center) * (config::TILE_SIZE as f32 / self.zoom)
+    }
+
+    /// Movement keys pan, the wheel zooms, left click places a marker and
+    /// right click removes the one under the cursor
+    pub fn update(&mut self, input: &InputMap, frame: &InputFrame) {
+        self.center += input.movement(frame) * PAN_SPEED * config::TILE_SIZE as f32 / self.zoom;
+
+        let (_, wheel) = mouse_wheel();
+        if wheel != 0.0 {
+            let factor = if wheel > 0.0 { 1.25 } else { 0.8 };
+            self.zoom = (self.zoom * factor).clamp(MIN_ZOOM, MAX_ZOOM);
+        }
+
+        let cursor = Vec2::from(mouse_position());
+        if is_mouse_button_pressed(MouseButton::Left) {
+            self.markers.push(self.screen_to_world(cursor));
+        }
+        if is_mouse_button_pressed(MouseButton::Right)
+            && let Some(index) = self.markers.iter().position(|&marker| {
+                self.world_to_screen(marker).distance(cursor) <= MARKER_PICK_RADIUS
+            })
+        {
+            self.markers.remove(index);
+        }
+    }
+
+    pub fn draw(
+        &self,
+        world: &World,
+        images: &ChunkImages,
+        player_pos: WorldPos,
+        input: &InputMap,
+    ) {
+        clear_background(FOG_COLOR);
+
+        let chunk_pixels = config::CHUNK_SIZE as f32 * self.zoom;
+        let top_left = self.screen_to_world(Vec2::ZERO);
+        let bottom_right = self.screen_to_world(Vec2::new(screen_width(), screen_height()));
+        let (first, last) = (top_left.chunk, bottom_right.chunk);
+
+        for y in first.y..=last.y {
+            for x in first.x..=last.x {
+                let pos = ChunkPos { x, y };
+                let Some(texture) = images.get(world, pos) else {
+                    continue;
+                };
+                let screen = self.world_to_screen(pos.world_pos());
+                draw_texture_ex(
+                    &texture,
+                    screen.x,
+                    screen.y,
+                    WHITE,
+                    DrawTextureParams {
+                        dest_size: Some(Vec2::splat(chunk_pixels)),
+                        ..Default::default()
+                    },
+                );
+            }
+        }
+
+        for (i, &marker) in self.markers.iter().enumerate() {
+            let point = self.world_to_screen(marker);
+            draw_circle(point.x, point.y, 5.0, RED);
+            draw_text(
+                format!("{}", i + 1),
+                point.x + 7.0,
+                point.y + 5.0,
+                18.0,
+                WHITE,
+            );
+        }
+        let player = self.world_to_screen(player_pos);
+        draw_circle(player.x, player.y, 5.0, WHITE);
+
+        draw_text("WORLD MAP", 20.0, 40.0, 36.0, GOLD);
+        let hint = format!(
+            "Move to pan, wheel to zoom, left click to mark, right click to unmark, {} or {} to close",
+            menu::bound_keys(input, Action::OpenMap),
+            menu::bound_keys(input, Action::Cancel)
+        );
+        draw_text(&hint, 20.0, screen_height() - 20.0, 20.0, LIGHTGRAY);
+    }
+}
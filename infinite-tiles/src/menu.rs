@@ -0,0 +1,335 @@
+// menu.rs
+// title screen listing the save slots, the key rebinding screen, and the overlays
+// drawn over a running game
+use crate::config;
+use crate::input::{Action, Binding, InputMap};
+use crate::save::{self, SaveError, SlotSummary};
+use crate::spell::{Spellbook, VOCABULARY, WordCategory};
+use macroquad::prelude::*;
+
+/// What the player picked on the title screen
+pub enum MenuAction {
+    NewGame(usize),
+    Continue(usize),
+}
+
+enum Slot {
+    Empty,
+    Saved(SlotSummary),
+    Unreadable(SaveError),
+}
+
+/// Something that destroys a save, pressed once and waiting for a second press
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Confirm {
+    Delete,
+    Overwrite,
+}
+
+pub struct TitleMenu {
+    slots: Vec<Slot>,
+    selected: usize,
+    confirm: Option<Confirm>,
+}
+
+impl TitleMenu {
+    pub fn new() -> Self {
+        let mut menu = TitleMenu {
+            slots: Vec::new(),
+            selected: 0,
+            confirm: None,
+        };
+        menu.refresh();
+        menu
+    }
+
+    /// Re-reads the save slots from disk
+    pub fn refresh(&mut self) {
+        self.slots = (0..config::SAVE_SLOTS)
+            .map(|slot| match save::read_slot_summary(slot) {
+                None => Slot::Empty,
+                Some(Ok(summary)) => Slot::Saved(summary),
+                Some(Err(err)) => Slot::Unreadable(err),
+            })
+            .collect();
+    }
+
+    pub fn update(&mut self) -> Option<MenuAction> {
+        if is_key_pressed(KeyCode::Up) || is_key_pressed(KeyCode::W) {
+            self.selected = (self.selected + self.slots.len() - 1) % self.slots.len();
+            self.confirm = None;
+        }
+        if is_key_pressed(KeyCode::Down) || is_key_pressed(KeyCode::S) {
+            self.selected = (self.selected + 1) % self.slots.len();
+            self.confirm = None;
+        }
+
+        if is_key_pressed(KeyCode::Delete) || is_key_pressed(KeyCode::X) {
+            if self.confirm == Some(Confirm::Delete) {
+                if let Err(err) = save::delete_slot(self.selected) {
+                    eprintln!("failed to delete slot: {}", err);
+                }
+                self.confirm = None;
+                self.refresh();
+            } else if !matches!(self.slots[self.selected], Slot::Empty) {
+                self.confirm = Some(Confirm::Delete);
+            }
+        }
+
+        if is_key_pressed(KeyCode::N) {
+            return self.new_game();
+        }
+        if is_key_pressed(KeyCode::Enter) {
+            return match self.slots[self.selected] {
+                Slot::Saved(_) => Some(MenuAction::Continue(self.selected)),
+                Slot::Empty | Slot::Unreadable(_) => self.new_game(),
+            };
+        }
+        None
+    }
+
+    /// Starts a new world in the selected slot. A slot holding a save, even one
+    /// that can't be read, is only overwritten on the second press.
+    fn new_game(&mut self) -> Option<MenuAction> {
+        if matches!(self.slots[self.selected], Slot::Empty)
+            || self.confirm == Some(Confirm::Overwrite)
+        {
+            self.confirm = None;
+            return Some(MenuAction::NewGame(self.selected));
+        }
+        self.confirm = Some(Confirm::Overwrite);
+        None
+    }
+
+    pub fn draw(&self) {
+        clear_background(Color::new(0.08, 0.06, 0.15, 1.0));
+        draw_text("SPELLCASTER", 60.0, 100.0, 60.0, GOLD);
+
+        let now = save::unix_now();
+        for (i, slot) in self.slots.iter().enumerate() {
+            let y = 180.0 + i as f32 * 90.0;
+            let color = if i == self.selected { WHITE } else { GRAY };
+            if i == self.selected {
+                draw_rectangle_lines(50.0, y - 30.0, 620.0, 80.0, 2.0, GOLD);
+            }
+
+            draw_text(format!("Slot {}", i + 1), 70.0, y, 28.0, color);
+            let details = match slot {
+                Slot::Empty => "Empty".to_string(),
+                Slot::Saved(summary) => format!(
+                    "Seed {}  |  Played {}  |  {} spells  |  {}",
+                    summary.seed,
+                    format_duration(summary.play_time),
+                    summary.spells_discovered,
+                    format_last_played(summary.last_played, now)
+                ),
+                Slot::Unreadable(err) => format!("Unreadable: {}", err),
+            };
+            draw_text(&details, 70.0, y + 30.0, 20.0, color);
+        }
+
+        let help = match self.confirm {
+            Some(Confirm::Delete) => "Press Delete again to erase this slot",
+            Some(Confirm::Overwrite) => "Press again to replace this save with a new world",
+            None => "Up/Down select, Enter continue, N new world, Delete erase",
+        };
+        draw_text(help, 60.0, screen_height() - 40.0, 20.0, LIGHTGRAY);
+    }
+}
+
+/// Lists every action with its bindings and lets the player add or clear keys
+pub struct ControlsMenu {
+    selected: usize,
+    listening: bool, // Waiting for the key to bind to the selected action
+    message: Option<String>,
+}
+
+impl ControlsMenu {
+    pub fn new() -> Self {
+        ControlsMenu {
+            selected: 0,
+            listening: false,
+            message: None,
+        }
+    }
+
+    /// Returns true once the player leaves the screen
+    pub fn update(&mut self, input: &mut InputMap, stick: Vec2) -> bool {
+        let action = Action::ALL[self.selected];
+
+        if self.listening {
+            let binding = match get_last_key_pressed() {
+                Some(KeyCode::Escape) => {
+                    self.listening = false;
+                    self.message = None;
+                    return false;
+                }
+                Some(key) => Binding::Key(key),
+                None => match Binding::from_stick(stick) {
+                    Some(binding) => binding,
+                    None => return false,
+                },
+            };
+            self.listening = false;
+            self.message = match input.bind(action, binding) {
+                Ok(()) => None,
+                Err(conflicts) => Some(format!(
+                    "{} is already used by {}",
+                    binding.name(),
+                    conflicts
+                        .iter()
+                        .map(|other| other.name())
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                )),
+            };
+            return false;
+        }
+
+        if is_key_pressed(KeyCode::Up) {
+            self.selected = (self.selected + Action::ALL.len() - 1) % Action::ALL.len();
+            self.message = None;
+        }
+        if is_key_pressed(KeyCode::Down) {
+            self.selected = (self.selected + 1) % Action::ALL.len();
+            self.message = None;
+        }
+        if is_key_pressed(KeyCode::Enter) {
+            self.listening = true;
+        }
+        if is_key_pressed(KeyCode::Backspace) {
+            input.clear(action);
+        }
+        is_key_pressed(KeyCode::Escape)
+    }
+
+    pub fn draw(&self, input: &InputMap) {
+        dim_screen();
+        draw_text("CONTROLS", 60.0, 80.0, 48.0, GOLD);
+
+        // Every action has to fit above the help line of a 600 pixel window
+        for (i, action) in Action::ALL.into_iter().enumerate() {
+            let y = 120.0 + i as f32 * 24.0;
+            let color = if i == self.selected { WHITE } else { GRAY };
+            let bindings = input
+                .bindings(action)
+                .iter()
+                .map(|binding| binding.name())
+                .collect::<Vec<_>>()
+                .join("  ");
+            draw_text(action.name(), 60.0, y, 22.0, color);
+            draw_text(&bindings, 280.0, y, 22.0, color);
+        }
+
+        if let Some(message) = &self.message {
+            draw_text(message, 60.0, screen_height() - 70.0, 20.0, RED);
+        }
+        let help = if self.listening {
+            "Press the key or push the stick to add, Esc to cancel"
+        } else {
+            "Up/Down select, Enter add a key, Backspace clear, Esc back"
+        };
+        draw_text(help, 60.0, screen_height() - 40.0, 20.0, LIGHTGRAY);
+    }
+}
+
+/// Dims the frozen game and lists the pause options with the keys bound to them
+pub fn draw_pause_overlay(input: &InputMap) {
+    dim_screen();
+    let x = screen_width() / 2.0 - 150.0;
+    let y = screen_height() / 2.0;
+    draw_text("PAUSED", x, y - 40.0, 48.0, WHITE);
+    let options = [
+        (Action::Cancel, "resume"),
+        (Action::SaveGame, "save"),
+        (Action::OpenControls, "controls"),
+        (Action::QuitToTitle, "save and quit to title"),
+    ];
+    for (i, (action, label)) in options.into_iter().enumerate() {
+        let line = format!("{} {}", bound_keys(input, action), label);
+        draw_text(&line, x, y + i as f32 * 30.0, 24.0, WHITE);
+    }
+}
+
+/// Lists the vocabulary by category, words not discovered yet stay hidden
+pub fn draw_spellbook_overlay(spellbook: &Spellbook, input: &InputMap) {
+    dim_screen();
+    draw_text("SPELLBOOK", 60.0, 80.0, 48.0, GOLD);
+
+    let columns = [
+        ("Elements", WordCategory::Element, ORANGE),
+        ("Verbs", WordCategory::Verb, SKYBLUE),
+        ("Modifiers", WordCategory::Modifier, VIOLET),
+    ];
+    for (column, (heading, category, color)) in columns.into_iter().enumerate() {
+        let x = 60.0 + column as f32 * 220.0;
+        draw_text(heading, x, 140.0, 28.0, WHITE);
+        let words = VOCABULARY.iter().filter(|word| word.category == category);
+        for (row, word) in words.enumerate() {
+            let y = 180.0 + row as f32 * 30.0;
+            if spellbook.is_discovered(word.text) {
+                draw_text(word.text, x, y, 24.0, color);
+            } else {
+                draw_text("???", x, y, 24.0, GRAY);
+            }
+        }
+    }
+
+    let hint = format!(
+        "{} or {} to close",
+        bound_keys(input, Action::OpenSpellbook),
+        bound_keys(input, Action::Cancel)
+    );
+    draw_text(&hint, 60.0, screen_height() - 40.0, 20.0, LIGHTGRAY);
+}
+
+pub fn draw_game_over_overlay(input: &InputMap) {
+    dim_screen();
+    let x = screen_width() / 2.0 - 150.0;
+    let y = screen_height() / 2.0;
+    draw_text("YOU PERISHED", x, y - 40.0, 48.0, RED);
+    let line = format!("{} return to title", bound_keys(input, Action::Dismiss));
+    draw_text(&line, x, y, 24.0, WHITE);
+}
+
+/// e.g. `Escape/Q`, so the overlays name whatever the player bound
+pub(crate) fn bound_keys(input: &InputMap, action: Action) -> String {
+    let names: Vec<String> = input
+        .bindings(action)
+        .iter()
+        .map(|binding| binding.name())
+        .collect();
+    if names.is_empty() {
+        "(unbound)".to_string()
+    } else {
+        names.join("/")
+    }
+}
+
+fn dim_screen() {
+    draw_rectangle(
+        0.0,
+        0.0,
+        screen_width(),
+        screen_height(),
+        Color::new(0.0, 0.0, 0.0, 0.5),
+    );
+}
+
+fn format_duration(seconds: f64) -> String {
+    let minutes = (seconds / 60.0) as u64;
+    format!("{}h {:02}m", minutes / 60, minutes % 60)
+}
+
+fn format_last_played(timestamp: u64, now: u64) -> String {
+    if timestamp == 0 {
+        return "last played unknown".to_string();
+    }
+    let ago = now.saturating_sub(timestamp);
+    match ago {
+        0..60 => "just now".to_string(),
+        60..3600 => format!("{} min ago", ago / 60),
+        3600..86400 => format!("{} h ago", ago / 3600),
+        _ => format!("{} days ago", ago / 86400),
+    }
+}
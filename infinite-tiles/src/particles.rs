@@ -0,0 +1,206 @@
+// particles.rs
+// short-lived visual particles thrown off by spells and burning tiles
+use crate::camera::Camera;
+use crate::config;
+use crate::entity::EntityId;
+use crate::types::{TilePos, WorldPos};
+use macroquad::prelude::*;
+use macroquad::rand::gen_range;
+
+/// Index into the emitter registry
+pub type EmitterId = usize;
+
+pub const SPELL_TRAIL: EmitterId = 0;
+pub const SPELL_BURST: EmitterId = 1;
+pub const EMBERS: EmitterId = 2;
+
+/// How an emitter spawns particles and how they behave
+pub struct EmitterDef {
+    pub rate: f32,            // Particles per second while the emitter runs
+    pub burst: u32,           // Particles spawned at once when the emitter starts
+    pub duration: f32,        // Seconds the emitter runs, 0 for as long as its anchor exists
+    pub lifetime: (f32, f32), // Seconds, shortest and longest
+    pub speed: (f32, f32),    // Pixels per second, slowest and fastest
+    pub direction: f32,       // Radians, 0 points right and positive angles turn down
+    pub spread: f32,          // Radians either side of `direction`
+    pub gravity: f32,         // Pixels per second squared, pulling down
+    pub size: f32,
+    pub start_color: Color,
+    pub end_color: Color, // Faded to over each particle's lifetime
+}
+
+impl EmitterDef {
+    const DEFAULT: EmitterDef = EmitterDef {
+        rate: 0.0,
+        burst: 0,
+        duration: 0.0,
+        lifetime: (0.5, 1.0),
+        speed: (10.0, 30.0),
+        direction: 0.0,
+        spread: std::f32::consts::PI,
+        gravity: 0.0,
+        size: 3.0,
+        start_color: WHITE,
+        end_color: Color::new(1.0, 1.0, 1.0, 0.0),
+    };
+}
+
+/// Indexed by `EmitterId`, spell emitters are white so they can be tinted by element
+pub const EMITTERS: &[EmitterDef] = &[
+    // Spell trail, behind projectiles
+    EmitterDef {
+        rate: 60.0,
+        lifetime: (0.2, 0.5),
+        speed: (5.0, 25.0),
+        size: 4.0,
+        ..EmitterDef::DEFAULT
+    },
+    // Spell burst, where a spell lands
+    EmitterDef {
+        burst: 40,
+        duration: 0.01,
+        lifetime: (0.3, 0.8),
+        speed: (60.0, 220.0),
+        gravity: 120.0,
+        ..EmitterDef::DEFAULT
+    },
+    // Embers rising off burning ground
+    EmitterDef {
+        rate: 1.5,
+        lifetime: (0.6, 1.4),
+        speed: (20.0, 50.0),
+        direction: -std::f32::consts::FRAC_PI_2,
+        spread: 0.5,
+        gravity: -10.0,
+        size: 2.5,
+        start_color: YELLOW,
+        end_color: Color::new(0.8, 0.1, 0.0, 0.0),
+        ..EmitterDef::DEFAULT
+    },
+];
+
+pub fn emitter_def(id: EmitterId) -> &'static EmitterDef {
+    &EMITTERS[id]
+}
+
+/// What an emitter follows
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Anchor {
+    Entity(EntityId), // Stops when the entity is gone, e.g. a projectile that hit
+    Tile(TilePos),    // Center of the tile
+}
+
+struct Emitter {
+    def: EmitterId,
+    anchor: Anchor,
+    tint: Color,
+    age: f32,
+    owed: f32, // Fraction of a particle carried over to the next frame
+}
+
+struct Particle {
+    def: EmitterId,
+    tint: Color,
+    position: WorldPos,
+    velocity: Vec2,
+    age: f32,
+    lifetime: f32,
+}
+
+/// Every live particle and emitter. Particles live in a pool allocated once, when it
+/// is full new particles are dropped so a big fight can't blow the frame budget.
+/// Particles are only for looks, the simulation never reads them.
+pub struct ParticleSystem {
+    particles: Vec<Particle>,
+    emitters: Vec<Emitter>,
+}
+
+impl ParticleSystem {
+    pub fn new() -> Self {
+        ParticleSystem {
+            particles: Vec::with_capacity(config::MAX_PARTICLES),
+            emitters: Vec::new(),
+        }
+    }
+
+    /// Starts an emitter, its colors are multiplied by `tint`
+    pub fn attach(&mut self, def: EmitterId, anchor: Anchor, tint: Color) {
+        self.emitters.push(Emitter {
+            def,
+            anchor,
+            tint,
+            age: 0.0,
+            owed: emitter_def(def).burst as f32,
+        });
+    }
+
+    /// Spawns particles at a point without an emitter, `count` may be fractional
+    /// and is rounded up or down at random
+    pub fn emit(&mut self, def: EmitterId, position: WorldPos, count: f32, tint: Color) {
+        let whole = count.floor() as u32 + u32::from(gen_range(0.0, 1.0) < count.fract());
+        for _ in 0..whole {
+            self.spawn(def, position, tint);
+        }
+    }
+
+    fn spawn(&mut self, def_id: EmitterId, position: WorldPos, tint: Color) {
+        if self.particles.len() >= config::MAX_PARTICLES {
+            return;
+        }
+        let def = emitter_def(def_id);
+        let angle = def.direction + gen_range(-def.spread, def.spread);
+        self.particles.push(Particle {
+            def: def_id,
+            tint,
+            position,
+            velocity: Vec2::from_angle(angle) * gen_range(def.speed.0, def.speed.1),
+            age: 0.0,
+            lifetime: gen_range(def.lifetime.0, def.lifetime.1),
+        });
+    }
+
+    /// Runs emitters and moves particles by `dt` seconds. `locate` finds where an
+    /// anchor is in the world, None once it is gone.
+    pub fn update(&mut self, dt: f32, locate: impl Fn(Anchor) -> Option<WorldPos>) {
+        let mut emitters = std::mem::take(&mut self.emitters);
+        emitters.retain_mut(|emitter| {
+            let def = emitter_def(emitter.def);
+            let Some(position) = locate(emitter.anchor) else {
+                return false;
+            };
+            emitter.owed += def.rate * dt;
+            while emitter.owed >= 1.0 {
+                self.spawn(emitter.def, position, emitter.tint);
+                emitter.owed -= 1.0;
+            }
+            emitter.age += dt;
+            def.duration == 0.0 || emitter.age < def.duration
+        });
+        self.emitters = emitters;
+
+        self.particles.retain_mut(|particle| {
+            particle.age += dt;
+            particle.velocity.y += emitter_def(particle.def).gravity * dt;
+            particle.position += particle.velocity * dt;
+            particle.age < particle.lifetime
+        });
+    }
+
+    /// Draws every particle multiplied by the color `light` gives its tile
+    pub fn draw(&self, camera: &Camera, light: impl Fn(TilePos) -> Color) {
+        for particle in &self.particles {
+            let def = emitter_def(particle.def);
+            let t = (particle.age / particle.lifetime).clamp(0.0, 1.0);
+            let color = def.start_color.to_vec().lerp(def.end_color.to_vec(), t)
+                * particle.tint.to_vec()
+                * light(particle.position.tile()).to_vec();
+            let screen = camera.world_to_screen(particle.position);
+            draw_circle(
+                screen.x,
+                screen.y,
+                def.size * (1.0 - 0.5 * t),
+                Color::from_vec(color),
+            );
+        }
+    }
+}
@@ -0,0 +1,470 @@
+// pathfinding.rs
+// A* paths over walkable tiles, planned over whole chunks first for long distances
+use crate::config;
+use crate::types::{ChunkPos, TilePos};
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashMap, HashSet};
+use std::hash::Hash;
+use std::sync::Arc;
+
+/// How a path is searched for
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum Planner {
+    /// A* over tiles, finds the cheapest path but gets slow over long distances
+    Tiles,
+    /// A route over chunks first, then A* over the tiles of the chunks on the route.
+    /// Much faster far away, the path may cost a little more than the cheapest.
+    Chunks,
+}
+
+/// Tiles to walk through from the start to the goal, both included
+#[derive(Clone, PartialEq, Debug)]
+pub struct Path {
+    pub tiles: Vec<TilePos>,
+    pub cost: f32, // Move costs of every tile entered, added up
+}
+
+/// Cheapest path from `from` to `to` stepping between side neighbors. `move_cost`
+/// is the cost of entering a tile, at least 1, or None if it can't be walked on,
+/// e.g. because it's solid or unloaded. Gives up after looking at `max_tiles` tiles.
+pub fn find_path(
+    from: TilePos,
+    to: TilePos,
+    max_tiles: usize,
+    mut move_cost: impl FnMut(TilePos) -> Option<f32>,
+) -> Option<Path> {
+    move_cost(to)?;
+    let (tiles, cost) = astar(
+        from,
+        to,
+        max_tiles,
+        |pos, next| {
+            for neighbor in pos.neighbors() {
+                if let Some(cost) = move_cost(neighbor) {
+                    next.push((neighbor, cost));
+                }
+            }
+        },
+        // Every step costs at least 1, so this never overestimates
+        |pos| (pos.x.abs_diff(to.x) as f32) + (pos.y.abs_diff(to.y) as f32),
+    )?;
+    Some(Path { tiles, cost })
+}
+
+/// Path from `from` to `to` that only goes through the chunks of the cheapest
+/// route over chunks. Chunks are as costly as the average walkable tile in them,
+/// and neighbors are connected if a tile on either side of their border can be
+/// walked on. None if there's no route, or the tiles along it don't connect.
+pub fn find_route(
+    from: TilePos,
+    to: TilePos,
+    max_tiles: usize,
+    mut move_cost: impl FnMut(TilePos) -> Option<f32>,
+) -> Option<Path> {
+    move_cost(to)?;
+    let start = from.to_chunk_local().0;
+    let goal = to.to_chunk_local().0;
+    let size = config::CHUNK_SIZE as f32;
+
+    let mut averages: HashMap<ChunkPos, Option<f32>> = HashMap::new();
+    let (route, _) = astar(
+        start,
+        goal,
+        max_tiles,
+        |chunk, next| {
+            for (dx, dy) in [(0, -1), (1, 0), (0, 1), (-1, 0)] {
+                let Some(neighbor) = chunk.offset(dx, dy) else {
+                    continue;
+                };
+                let average = *averages
+                    .entry(neighbor)
+                    .or_insert_with(|| average_cost(neighbor, &mut move_cost));
+                if let Some(average) = average
+                    && crosses_border(chunk, (dx, dy), &mut move_cost)
+                {
+                    next.push((neighbor, average * size));
+                }
+            }
+        },
+        |chunk| (chunk.x.abs_diff(goal.x) + chunk.y.abs_diff(goal.y)) as f32 * size,
+    )?;
+
+    let corridor: HashSet<ChunkPos> = route.into_iter().collect();
+    find_path(from, to, max_tiles, |pos| {
+        if corridor.contains(&pos.to_chunk_local().0) {
+            move_cost(pos)
+        } else {
+            None
+        }
+    })
+}
+
+/// Average move cost of the walkable tiles of a chunk, None if there are none
+fn average_cost(chunk: ChunkPos, move_cost: impl FnMut(TilePos) -> Option<f32>) -> Option<f32> {
+    let (count, total) = chunk
+        .tile_rect()
+        .iter()
+        .filter_map(move_cost)
+        .fold((0, 0.0), |(count, total), cost| (count + 1, total + cost));
+    (count > 0).then(|| total / count as f32)
+}
+
+/// Whether a tile on a chunk's side facing `direction` and the tile across the
+/// border from it can both be walked on
+fn crosses_border(
+    chunk: ChunkPos,
+    (dx, dy): (i32, i32),
+    mut move_cost: impl FnMut(TilePos) -> Option<f32>,
+) -> bool {
+    let rect = chunk.tile_rect();
+    (0..config::CHUNK_SIZE).any(|i| {
+        let inside = match (dx, dy) {
+            (1, _) => TilePos {
+                x: rect.max.x,
+                y: rect.min.y + i,
+            },
+            (-1, _) => TilePos {
+                x: rect.min.x,
+                y: rect.min.y + i,
+            },
+            (_, 1) => TilePos {
+                x: rect.min.x + i,
+                y: rect.max.y,
+            },
+            _ => TilePos {
+                x: rect.min.x + i,
+                y: rect.min.y,
+            },
+        };
+        inside
+            .offset(dx, dy)
+            .is_some_and(|outside| move_cost(inside).is_some() && move_cost(outside).is_some())
+    })
+}
+
+/// A node waiting to be looked at, the heap pops the lowest estimate first
+struct Open<N> {
+    estimate: f32, // Cost so far plus the heuristic
+    cost: f32,
+    node: N,
+}
+
+impl<N> Ord for Open<N> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        // Between equal estimates the one further along is closer to the goal
+        other
+            .estimate
+            .total_cmp(&self.estimate)
+            .then(self.cost.total_cmp(&other.cost))
+    }
+}
+
+impl<N> PartialOrd for Open<N> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<N> PartialEq for Open<N> {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl<N> Eq for Open<N> {}
+
+/// A* from `start` to `goal`. `neighbors` pushes the nodes next to a node with the
+/// cost of stepping there, `heuristic` must never overestimate what's left. Returns
+/// the nodes along the way and the total cost, None if the goal can't be reached
+/// or more than `max_nodes` nodes had to be expanded.
+fn astar<N: Copy + Eq + Hash>(
+    start: N,
+    goal: N,
+    max_nodes: usize,
+    mut neighbors: impl FnMut(N, &mut Vec<(N, f32)>),
+    heuristic: impl Fn(N) -> f32,
+) -> Option<(Vec<N>, f32)> {
+    // Cheapest known cost of every node reached so far and where it was reached from
+    let mut best: HashMap<N, (f32, Option<N>)> = HashMap::from([(start, (0.0, None))]);
+    let mut open = BinaryHeap::from([Open {
+        estimate: heuristic(start),
+        cost: 0.0,
+        node: start,
+    }]);
+    let mut next = Vec::new();
+    let mut expanded = 0;
+
+    while let Some(Open { cost, node, .. }) = open.pop() {
+        if node == goal {
+            let mut nodes = vec![goal];
+            while let Some(previous) = best[nodes.last()?].1 {
+                nodes.push(previous);
+            }
+            nodes.reverse();
+            return Some((nodes, cost));
+        }
+        // Already reached more cheaply after this entry was pushed
+        if cost > best[&node].0 {
+            continue;
+        }
+        expanded += 1;
+        if expanded > max_nodes {
+            return None;
+        }
+
+        next.clear();
+        neighbors(node, &mut next);
+        for &(neighbor, step) in &next {
+            let cost = cost + step;
+            if best.get(&neighbor).is_none_or(|&(known, _)| cost < known) {
+                best.insert(neighbor, (cost, Some(node)));
+                open.push(Open {
+                    estimate: cost + heuristic(neighbor),
+                    cost,
+                    node: neighbor,
+                });
+            }
+        }
+    }
+    None
+}
+
+/// Paths searched for before, each kept until a chunk its search looked at
+/// changes how its tiles are walked
+pub struct PathCache {
+    paths: HashMap<(TilePos, TilePos, Planner), CachedPath>,
+}
+
+struct CachedPath {
+    path: Option<Arc<Path>>, // Not finding one is worth remembering too
+    chunks: HashSet<ChunkPos>,
+}
+
+impl PathCache {
+    pub fn new() -> Self {
+        PathCache {
+            paths: HashMap::new(),
+        }
+    }
+
+    /// Path between two tiles, only searched for if it isn't cached yet.
+    /// `move_cost` is as for `find_path`.
+    pub fn find(
+        &mut self,
+        from: TilePos,
+        to: TilePos,
+        planner: Planner,
+        mut move_cost: impl FnMut(TilePos) -> Option<f32>,
+    ) -> Option<Arc<Path>> {
+        let key = (from, to, planner);
+        if let Some(cached) = self.paths.get(&key) {
+            return cached.path.clone();
+        }
+
+        let mut chunks = HashSet::new();
+        let mut last_chunk = None;
+        let looking_at = |pos: TilePos| {
+            // Searches mostly look at tiles next to the last one
+            let chunk = pos.to_chunk_local().0;
+            if last_chunk != Some(chunk) {
+                chunks.insert(chunk);
+                last_chunk = Some(chunk);
+            }
+            move_cost(pos)
+        };
+        let limit = config::PATH_SEARCH_TILES;
+        let path = match planner {
+            Planner::Tiles => find_path(from, to, limit, looking_at),
+            Planner::Chunks => find_route(from, to, limit, looking_at),
+        }
+        .map(Arc::new);
+
+        // Forgetting everything is simpler than tracking which path is the oldest
+        if self.paths.len() >= config::PATH_CACHE_SIZE {
+            self.paths.clear();
+        }
+        self.paths.insert(
+            key,
+            CachedPath {
+                path: path.clone(),
+                chunks,
+            },
+        );
+        path
+    }
+
+    /// Forgets the paths whose search looked at tiles of a chunk
+    pub fn invalidate(&mut self, chunk: ChunkPos) {
+        self.paths
+            .retain(|_, cached| !cached.chunks.contains(&chunk));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+
+    /// Move costs of a small map drawn with characters: `.` is open ground,
+    /// `~` is water costing 4 and `#` is a wall. Row 0 is the first line.
+    fn map(rows: &'static [&'static str]) -> impl Fn(TilePos) -> Option<f32> {
+        move |pos| {
+            let row = rows.get(usize::try_from(pos.y).ok()?)?;
+            match row.as_bytes().get(usize::try_from(pos.x).ok()?)? {
+                b'.' => Some(1.0),
+                b'~' => Some(4.0),
+                _ => None,
+            }
+        }
+    }
+
+    fn assert_walkable(path: &Path, move_cost: impl Fn(TilePos) -> Option<f32>) {
+        for pair in path.tiles.windows(2) {
+            assert!(
+                pair[0].neighbors().any(|n| n == pair[1]),
+                "{:?} jumps",
+                pair
+            );
+        }
+        let cost: f32 = path.tiles[1..].iter().map(|&t| move_cost(t).unwrap()).sum();
+        assert_eq!(cost, path.cost);
+    }
+
+    #[test]
+    fn path_goes_around_walls() {
+        let rows = &[
+            ".....", //
+            ".###.", //
+            "...#.", //
+        ];
+        let from = TilePos { x: 0, y: 2 };
+        let to = TilePos { x: 2, y: 2 };
+        let path = find_path(from, to, 100, map(rows)).unwrap();
+        assert_eq!(path.tiles.first(), Some(&from));
+        assert_eq!(path.tiles.last(), Some(&to));
+        assert_eq!(path.cost, 2.0);
+
+        let to = TilePos { x: 4, y: 2 };
+        let path = find_path(from, to, 100, map(rows)).unwrap();
+        assert_eq!(path.cost, 8.0);
+        assert_walkable(&path, map(rows));
+    }
+
+    #[test]
+    fn path_wades_through_water_only_when_it_pays_off() {
+        let detour = &[
+            "..~..", //
+            "..#..", //
+            ".....", //
+        ];
+        let from = TilePos { x: 0, y: 0 };
+        let to = TilePos { x: 4, y: 0 };
+        // Through the water is 1 + 4 + 1 + 1, around the wall is 8
+        let path = find_path(from, to, 100, map(detour)).unwrap();
+        assert_eq!(path.cost, 7.0);
+        assert!(path.tiles.contains(&TilePos { x: 2, y: 0 }));
+
+        let short_way = &[
+            "..~..", //
+            ".....", //
+        ];
+        let path = find_path(from, to, 100, map(short_way)).unwrap();
+        assert_eq!(path.cost, 6.0);
+        assert!(!path.tiles.contains(&TilePos { x: 2, y: 0 }));
+    }
+
+    #[test]
+    fn no_path_into_walls_or_past_the_search_limit() {
+        let rows = &[
+            "..#..", //
+            "..#..", //
+            "..#..", //
+        ];
+        let from = TilePos { x: 0, y: 0 };
+        assert_eq!(
+            find_path(from, TilePos { x: 4, y: 1 }, 100, map(rows)),
+            None
+        );
+        assert_eq!(
+            find_path(from, TilePos { x: 2, y: 1 }, 100, map(rows)),
+            None
+        );
+
+        let open = |_| Some(1.0);
+        let far = TilePos { x: 30, y: 30 };
+        assert!(find_path(from, far, 10_000, open).is_some());
+        assert_eq!(find_path(from, far, 50, open), None);
+    }
+
+    /// Open ground over a few chunks with a wall along a chunk border, except for
+    /// a gap near its far end
+    fn walled_chunks(pos: TilePos) -> Option<f32> {
+        let size = config::CHUNK_SIZE;
+        let inside = (-size..size * 4).contains(&pos.x) && (-size..size * 3).contains(&pos.y);
+        let wall = pos.x == size * 2 && pos.y < size * 2 + 4;
+        (inside && !wall).then_some(1.0)
+    }
+
+    #[test]
+    fn route_over_chunks_finds_the_gap_in_the_wall() {
+        let size = config::CHUNK_SIZE;
+        let from = TilePos { x: 3, y: 2 };
+        let to = TilePos {
+            x: size * 3 + 5,
+            y: 1,
+        };
+        let direct = find_path(from, to, 100_000, walled_chunks).unwrap();
+        let route = find_route(from, to, 100_000, walled_chunks).unwrap();
+        assert_eq!(route.tiles.first(), Some(&from));
+        assert_eq!(route.tiles.last(), Some(&to));
+        assert!(route.tiles.contains(&TilePos {
+            x: size * 2,
+            y: size * 2 + 4
+        }));
+        assert!(route.cost >= direct.cost);
+        assert_walkable(&route, walled_chunks);
+    }
+
+    #[test]
+    fn route_in_the_corner_of_the_grid_stays_on_it() {
+        let open = |_| Some(1.0);
+        let corner = TilePos {
+            x: i32::MAX,
+            y: i32::MIN,
+        };
+        let from = TilePos {
+            x: i32::MAX - 40,
+            y: i32::MIN + 3,
+        };
+        let route = find_route(from, corner, 100_000, open).unwrap();
+        assert_eq!(route.tiles.first(), Some(&from));
+        assert_eq!(route.tiles.last(), Some(&corner));
+    }
+
+    #[test]
+    fn cached_paths_are_kept_until_a_chunk_they_looked_at_changes() {
+        let searches = Cell::new(0);
+        let counting = |pos| {
+            searches.set(searches.get() + 1);
+            walled_chunks(pos)
+        };
+        let mut cache = PathCache::new();
+        let from = TilePos { x: 1, y: 1 };
+        let to = TilePos { x: 6, y: 3 };
+
+        let path = cache.find(from, to, Planner::Tiles, counting).unwrap();
+        let looked_at = searches.replace(0);
+        assert!(looked_at > 0);
+        assert_eq!(cache.find(from, to, Planner::Tiles, counting), Some(path));
+        assert_eq!(searches.get(), 0);
+
+        // A chunk the search never got near
+        cache.invalidate(ChunkPos { x: 3, y: 2 });
+        cache.find(from, to, Planner::Tiles, counting);
+        assert_eq!(searches.get(), 0);
+
+        cache.invalidate(ChunkPos { x: 0, y: 0 });
+        cache.find(from, to, Planner::Tiles, counting);
+        assert_eq!(searches.get(), looked_at);
+    }
+}
@@ -1,83 +1,126 @@
+use crate::animation::{self, Animator};
 use crate::camera::Camera;
 use crate::config;
-use crate::types::{ChunkPos, TilePos};
+use crate::entity::Health;
+use crate::lighting;
+use crate::types::{ChunkPos, TilePos, WorldPos};
 use macroquad::prelude::*;
 
 pub struct Player {
-    pub position: Vec2,
+    pub position: WorldPos,
     pub chunk_pos: ChunkPos,
     pub tile_pos: TilePos,
+    pub health: Health,
     velocity: Vec2,
+    animator: Animator,
 }
 
 impl Player {
     pub fn new() -> Self {
         // Start at world origin
-        let position = Vec2::ZERO;
+        let position = WorldPos::default();
         Player {
             position,
-            chunk_pos: ChunkPos::from_world_pos(position.x, position.y),
-            tile_pos: TilePos::from_world_pos(position.x, position.y),
+            chunk_pos: position.chunk,
+            tile_pos: position.tile(),
+            health: Health {
+                current: config::PLAYER_MAX_HEALTH,
+                max: config::PLAYER_MAX_HEALTH,
+            },
             velocity: Vec2::ZERO,
+            animator: Animator::new(&animation::WIZARD_SHEET),
         }
     }
 
-    pub fn update(&mut self) {
-        // Handle input
-        let mut input_dir = Vec2::ZERO;
+    /// Moves the player without walking, e.g. when a save is loaded
+    pub fn set_position(&mut self, position: WorldPos) {
+        self.position = position;
+        self.chunk_pos = position.chunk;
+        self.tile_pos = position.tile();
+    }
 
-        if is_key_down(KeyCode::W) || is_key_down(KeyCode::Up) {
-            input_dir.y -= 1.0;
-        }
-        if is_key_down(KeyCode::S) || is_key_down(KeyCode::Down) {
-            input_dir.y += 1.0;
-        }
-        if is_key_down(KeyCode::A) || is_key_down(KeyCode::Left) {
-            input_dir.x -= 1.0;
-        }
-        if is_key_down(KeyCode::D) || is_key_down(KeyCode::Right) {
-            input_dir.x += 1.0;
-        }
+    pub fn hurt(&mut self, amount: f32) {
+        self.health.current = (self.health.current - amount).max(0.0);
+    }
 
-        // Normalize diagonal movement
-        if input_dir != Vec2::ZERO {
-            input_dir = input_dir.normalize();
-        }
+    pub fn is_dead(&self) -> bool {
+        self.health.current <= 0.0
+    }
 
+    /// Walks in `direction`, which is at most unit length
+    pub fn update(&mut self, direction: Vec2) {
         // Apply movement
-        self.velocity = input_dir * config::PLAYER_SPEED;
+        self.velocity = direction * config::PLAYER_SPEED;
         self.position += self.velocity;
 
         // Update position trackers
-        self.chunk_pos = ChunkPos::from_world_pos(self.position.x, self.position.y);
-        self.tile_pos = TilePos::from_world_pos(self.position.x, self.position.y);
+        self.chunk_pos = self.position.chunk;
+        self.tile_pos = self.position.tile();
     }
 
-    pub fn draw(&self, camera: &Camera) {
+    /// Plays the wizard's animation by `dt` seconds of real time
+    pub fn animate(&mut self, dt: f32) {
+        self.animator.update(dt, self.velocity);
+    }
+
+    /// Raises the staff, called when a spell goes off
+    pub fn start_cast(&mut self) {
+        self.animator.play("cast");
+    }
+
+    /// Draws the wizard from `sheet`, or as a square while it isn't loaded, multiplied
+    /// by `light`, the level of light falling on them
+    pub fn draw(&self, camera: &Camera, sheet: Option<&Texture2D>, light: Color) {
         // Convert world position to screen position
         let screen_pos = camera.world_to_screen(self.position);
 
         // Draw player
-        draw_rectangle(
-            screen_pos.x - config::PLAYER_SIZE / 2.0,
-            screen_pos.y - config::PLAYER_SIZE / 2.0,
-            config::PLAYER_SIZE,
-            config::PLAYER_SIZE,
-            BLUE,
-        );
+        match sheet {
+            Some(texture) => self
+                .animator
+                .draw(texture, screen_pos, config::PLAYER_SIZE, light),
+            None => draw_rectangle(
+                screen_pos.x - config::PLAYER_SIZE / 2.0,
+                screen_pos.y - config::PLAYER_SIZE / 2.0,
+                config::PLAYER_SIZE,
+                config::PLAYER_SIZE,
+                lighting::shade(BLUE, light),
+            ),
+        }
+
+        // Health bar once the wizard has been hurt
+        if self.health.current < self.health.max {
+            let fraction = self.health.current / self.health.max;
+            let x = screen_pos.x - config::PLAYER_SIZE / 2.0;
+            let y = screen_pos.y - config::PLAYER_SIZE / 2.0 - 8.0;
+            draw_rectangle(
+                x,
+                y,
+                config::PLAYER_SIZE,
+                5.0,
+                lighting::shade(DARKGRAY, light),
+            );
+            draw_rectangle(
+                x,
+                y,
+                config::PLAYER_SIZE * fraction,
+                5.0,
+                lighting::shade(RED, light),
+            );
+        }
 
         // Debug info (optional)
         #[cfg(debug_assertions)]
         {
             draw_text(
-                &format!("Chunk: ({}, {})", self.chunk_pos.x, self.chunk_pos.y),
+                format!("Chunk: ({}, {})", self.chunk_pos.x, self.chunk_pos.y),
                 10.0,
                 50.0,
                 20.0,
                 BLACK,
             );
             draw_text(
-                &format!("Tile: ({}, {})", self.tile_pos.x, self.tile_pos.y),
+                format!("Tile: ({}, {})", self.tile_pos.x, self.tile_pos.y),
                 10.0,
                 70.0,
                 20.0,
@@ -90,7 +133,7 @@ impl Player {
         self.chunk_pos
     }
 
-    pub fn get_position(&self) -> Vec2 {
+    pub fn get_position(&self) -> WorldPos {
         self.position
     }
 }
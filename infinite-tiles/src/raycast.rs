@@ -0,0 +1,194 @@
+// raycast.rs
+// tiles a straight line passes through, stepped one tile border at a time
+use crate::config;
+use crate::types::{TilePos, WorldPos};
+use macroquad::prelude::*;
+
+/// Where a ray first ran into something
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct RayHit {
+    pub tile: TilePos,   // Tile it ran into
+    pub point: WorldPos, // Where it crossed into that tile
+    pub distance: f32,   // Pixels from the start of the ray
+}
+
+/// Every tile the segment from `from` to `to` passes through, in order, with the
+/// distance in pixels from `from` at which the segment enters it. This is a DDA
+/// walk: unlike a rasterized line it visits every tile the segment touches, so a
+/// ray can't slip through the corner where two walls meet. A segment passing
+/// exactly through a corner steps sideways before it steps up or down.
+pub fn tiles_along(from: WorldPos, to: WorldPos) -> impl Iterator<Item = (TilePos, f32)> {
+    let delta = to - from;
+    let length = delta.length();
+    let direction = delta.normalize_or_zero();
+    let tile_size = config::TILE_SIZE as f32;
+
+    let mut tile = from.tile();
+    let last = to.tile();
+    // Position inside the first tile, in pixels from its top-left corner
+    let inside = from - tile.world_pos();
+    let step = IVec2::new(direction.x.signum() as i32, direction.y.signum() as i32);
+    let border = |inside: f32, direction: f32| {
+        if direction > 0.0 {
+            (tile_size - inside) / direction
+        } else if direction < 0.0 {
+            inside / -direction
+        } else {
+            f32::INFINITY
+        }
+    };
+    // Distance along the ray to the next vertical and horizontal tile border
+    let mut next = Vec2::new(border(inside.x, direction.x), border(inside.y, direction.y));
+    let across = Vec2::new(tile_size / direction.x.abs(), tile_size / direction.y.abs());
+
+    let mut started = false;
+    std::iter::from_fn(move || {
+        if !started {
+            started = true;
+            return Some((tile, 0.0));
+        }
+        if tile == last {
+            return None;
+        }
+        let (distance, moved) = if next.x < next.y {
+            let distance = next.x;
+            next.x += across.x;
+            (distance, tile.offset(step.x, 0))
+        } else {
+            let distance = next.y;
+            next.y += across.y;
+            (distance, tile.offset(0, step.y))
+        };
+        // Rounding can carry the walk a hair past the end, it never enters a tile there
+        if distance > length {
+            return None;
+        }
+        tile = moved?;
+        Some((tile, distance))
+    })
+}
+
+/// First tile `is_solid` accepts on the way from `from` to `to`, None if the way
+/// is clear. The tile the ray starts in doesn't count, a caster never blocks
+/// their own spell. Errors from `is_solid` end the walk and are passed on.
+pub fn raycast<E>(
+    from: WorldPos,
+    to: WorldPos,
+    mut is_solid: impl FnMut(TilePos) -> Result<bool, E>,
+) -> Result<Option<RayHit>, E> {
+    let direction = (to - from).normalize_or_zero();
+    for (tile, distance) in tiles_along(from, to).skip(1) {
+        if is_solid(tile)? {
+            return Ok(Some(RayHit {
+                tile,
+                point: from + direction * distance,
+                distance,
+            }));
+        }
+    }
+    Ok(None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::convert::Infallible;
+
+    fn center(x: i32, y: i32) -> WorldPos {
+        TilePos { x, y }.world_center()
+    }
+
+    fn tiles(from: WorldPos, to: WorldPos) -> Vec<(i32, i32)> {
+        tiles_along(from, to).map(|(t, _)| (t.x, t.y)).collect()
+    }
+
+    fn assert_steps_to_neighbors(tiles: &[(i32, i32)]) {
+        for pair in tiles.windows(2) {
+            let step = (pair[1].0 - pair[0].0).abs() + (pair[1].1 - pair[0].1).abs();
+            assert_eq!(step, 1, "{:?} is not a side step", pair);
+        }
+    }
+
+    #[test]
+    fn straight_ray_visits_each_tile_once() {
+        assert_eq!(
+            tiles(center(-2, 3), center(2, 3)),
+            [(-2, 3), (-1, 3), (0, 3), (1, 3), (2, 3)]
+        );
+        assert_eq!(
+            tiles(center(0, 1), center(0, -1)),
+            [(0, 1), (0, 0), (0, -1)]
+        );
+        assert_eq!(tiles(center(5, 5), center(5, 5)), [(5, 5)]);
+    }
+
+    #[test]
+    fn diagonal_ray_visits_every_tile_it_touches() {
+        let from = center(-3, -1) + Vec2::new(10.0, -7.0);
+        let to = center(4, 2) + Vec2::new(-3.0, 20.0);
+        let tiles = tiles(from, to);
+        assert_eq!(tiles.first(), Some(&(-3, -1)));
+        assert_eq!(tiles.last(), Some(&(4, 2)));
+        // Every column and row crossing is a step of its own
+        assert_eq!(tiles.len(), 1 + 7 + 3);
+        assert_steps_to_neighbors(&tiles);
+    }
+
+    #[test]
+    fn distances_grow_along_the_ray() {
+        let from = center(0, 0);
+        let to = center(-6, 4);
+        let along: Vec<(TilePos, f32)> = tiles_along(from, to).collect();
+        for pair in along.windows(2) {
+            assert!(pair[0].1 <= pair[1].1);
+        }
+        let length = (to - from).length();
+        assert!(along.iter().all(|&(_, distance)| distance <= length));
+    }
+
+    #[test]
+    fn ray_crosses_chunk_borders_far_from_the_origin() {
+        let size = crate::config::CHUNK_SIZE;
+        let x = 40_000_000 * size;
+        let tiles = tiles(center(x - 2, -1), center(x + 1, 0));
+        assert_eq!(tiles.first(), Some(&(x - 2, -1)));
+        assert_eq!(tiles.last(), Some(&(x + 1, 0)));
+        assert_steps_to_neighbors(&tiles);
+    }
+
+    #[test]
+    fn raycast_stops_at_the_first_wall_but_not_in_the_start_tile() {
+        let walls = [
+            TilePos { x: 0, y: 0 },
+            TilePos { x: 3, y: 0 },
+            TilePos { x: 5, y: 0 },
+        ];
+        let is_solid = |tile| Ok::<_, Infallible>(walls.contains(&tile));
+        let hit = raycast(center(0, 0), center(8, 0), is_solid)
+            .unwrap()
+            .unwrap();
+        assert_eq!(hit.tile, TilePos { x: 3, y: 0 });
+        assert_eq!(hit.distance, 2.5 * crate::config::TILE_SIZE as f32);
+        assert_eq!(hit.point.tile(), hit.tile);
+
+        let clear = raycast(center(0, 1), center(8, 1), is_solid).unwrap();
+        assert_eq!(clear, None);
+    }
+
+    #[test]
+    fn raycast_catches_walls_meeting_at_a_corner() {
+        // Two walls touching diagonally, a rasterized line slips between them
+        let walls = [TilePos { x: 1, y: 0 }, TilePos { x: 0, y: 1 }];
+        let is_solid = |tile| Ok::<_, Infallible>(walls.contains(&tile));
+        let hit = raycast(center(0, 0), center(1, 1), is_solid).unwrap();
+        assert!(hit.is_some());
+    }
+
+    #[test]
+    fn raycast_passes_on_errors() {
+        let result = raycast(center(0, 0), center(4, 0), |tile| {
+            if tile.x == 2 { Err(tile) } else { Ok(false) }
+        });
+        assert_eq!(result, Err(TilePos { x: 2, y: 0 }));
+    }
+}
@@ -0,0 +1,377 @@
+// replay.rs
+// input recorded tick by tick, played back from the same seed it reaches the same state
+use crate::config;
+use crate::input::{self, Action, InputFrame, InputMap};
+use crate::save::{self, SaveError, field, parse};
+use macroquad::prelude::*;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+pub const REPLAY_VERSION: u32 = 4;
+const MAGIC: &str = "spellcaster-replay";
+
+/// A new game's seed, the controls it was played with and the input of every
+/// tick since it started
+#[derive(PartialEq, Debug)]
+pub struct Replay {
+    pub seed: u64,
+    pub input: InputMap, // Keys are played back through these, not the current controls
+    pub frames: Vec<InputFrame>,
+}
+
+impl Replay {
+    pub fn new(seed: u64, input: InputMap) -> Self {
+        Replay {
+            seed,
+            input,
+            frames: Vec::new(),
+        }
+    }
+
+    pub fn record(&mut self, frame: InputFrame) {
+        self.frames.push(frame);
+    }
+
+    pub fn write(&self, path: &Path) -> Result<(), SaveError> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(path, self.to_text())?;
+        Ok(())
+    }
+
+    pub fn read(path: &Path) -> Result<Self, SaveError> {
+        Self::from_text(&fs::read_to_string(path)?)
+    }
+
+    /// One line per tick, runs of identical ticks are written once with a repeat count
+    pub fn to_text(&self) -> String {
+        let mut out = format!("{} {}\nseed {}\n", MAGIC, REPLAY_VERSION, self.seed);
+        for action in Action::ALL {
+            out += &format!("bind {}\n", self.input.line(action));
+        }
+        let mut frames = self.frames.iter().peekable();
+        while let Some(frame) = frames.next() {
+            out += &format_frame(frame);
+            let mut repeats = 0;
+            while frames.next_if_eq(&frame).is_some() {
+                repeats += 1;
+            }
+            if repeats > 0 {
+                out += &format!("repeat {}\n", repeats);
+            }
+        }
+        out
+    }
+
+    pub fn from_text(text: &str) -> Result<Self, SaveError> {
+        let mut lines = text.lines();
+        let version = match lines.next().unwrap_or_default().split_once(' ') {
+            Some((MAGIC, version)) => {
+                let version = parse::<u32>(version)?;
+                if !(1..=REPLAY_VERSION).contains(&version) {
+                    return Err(SaveError::UnsupportedVersion(version));
+                }
+                version
+            }
+            _ => return Err(SaveError::Corrupt("not a replay file".to_string())),
+        };
+
+        // Older replays didn't keep their controls, the defaults are the best guess
+        let mut replay = Replay::new(0, InputMap::new());
+        let mut has_seed = false;
+        for line in lines {
+            let mut fields = line.split_whitespace();
+            let values: Vec<&str> = fields.clone().skip(1).collect();
+            match fields.next() {
+                Some("seed") => {
+                    replay.seed = parse(field(&values, 0)?)?;
+                    has_seed = true;
+                }
+                Some("bind") => {
+                    let line = line.trim_start().trim_start_matches("bind");
+                    if let Some(problem) = replay.input.read_line(line).into_iter().next() {
+                        return Err(SaveError::Corrupt(problem));
+                    }
+                }
+                Some("frame") => replay.frames.push(parse_frame(&values, version)?),
+                Some("repeat") => {
+                    let frame = replay.frames.last().cloned().ok_or_else(|| {
+                        SaveError::Corrupt("repeat before the first frame".to_string())
+                    })?;
+                    let count: usize = parse(field(&values, 0)?)?;
+                    replay.frames.extend(std::iter::repeat_n(frame, count));
+                }
+                Some(other) => {
+                    return Err(SaveError::Corrupt(format!("unknown record '{}'", other)));
+                }
+                None => {}
+            }
+        }
+
+        if !has_seed {
+            return Err(SaveError::Corrupt("missing world seed".to_string()));
+        }
+        Ok(replay)
+    }
+}
+
+/// `frame mouse_x mouse_y stick_x stick_y down pressed chars`, lists are comma
+/// separated with `-` for an empty one, typed characters are written as code points
+fn format_frame(frame: &InputFrame) -> String {
+    let keys = |keys: &[KeyCode]| {
+        let names: Vec<&str> = keys
+            .iter()
+            .filter_map(|&key| input::key_name(key))
+            .collect();
+        list(&names)
+    };
+    let chars: Vec<String> = frame
+        .chars
+        .iter()
+        .map(|&c| (c as u32).to_string())
+        .collect();
+    format!(
+        "frame {} {} {} {} {} {}\n",
+        save::format_position(frame.mouse),
+        frame.stick.x,
+        frame.stick.y,
+        keys(&frame.down),
+        keys(&frame.pressed),
+        list(&chars)
+    )
+}
+
+fn list<T: AsRef<str>>(items: &[T]) -> String {
+    if items.is_empty() {
+        "-".to_string()
+    } else {
+        items
+            .iter()
+            .map(AsRef::as_ref)
+            .collect::<Vec<_>>()
+            .join(",")
+    }
+}
+
+fn parse_frame(values: &[&str], version: u32) -> Result<InputFrame, SaveError> {
+    // Versions 2 and 3 were written while sticks weren't read and left them out
+    let has_stick = !matches!(version, 2 | 3);
+    let first = if has_stick { 4 } else { 2 };
+    let number = |index| parse::<f32>(field(values, index)?);
+    let items =
+        |index| field(values, index).map(|list| list.split(',').filter(|item| *item != "-"));
+    let keys = |index| {
+        items(index)?
+            .map(|name| {
+                input::key_from_name(name)
+                    .ok_or_else(|| SaveError::Corrupt(format!("unknown key '{}'", name)))
+            })
+            .collect::<Result<Vec<_>, _>>()
+    };
+
+    Ok(InputFrame {
+        mouse: save::parse_position(values, 0)?,
+        stick: if has_stick {
+            Vec2::new(number(2)?, number(3)?)
+        } else {
+            Vec2::ZERO
+        },
+        down: keys(first)?,
+        pressed: keys(first + 1)?,
+        chars: items(first + 2)?
+            .map(|code| {
+                char::from_u32(parse(code)?)
+                    .ok_or_else(|| SaveError::Corrupt(format!("invalid character {}", code)))
+            })
+            .collect::<Result<Vec<_>, _>>()?,
+    })
+}
+
+/// Where keep_replay writes the recording of the current session
+pub fn new_replay_path() -> PathBuf {
+    Path::new(config::REPLAY_DIR).join(format!("{}.replay", save::unix_now()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::{Game, Snapshot};
+    use crate::input::InputMap;
+    use crate::player::Player;
+    use crate::spell::Spellbook;
+    use crate::types::{ChunkPos, TilePos, WorldPos};
+    use crate::world::World;
+    use std::collections::BTreeSet;
+
+    /// Plays a replay on a world without textures or a window
+    fn run_headless(replay: &Replay) -> Snapshot {
+        let world = World::with_textures(replay.seed, None);
+        let mut game = Game::from_parts(None, world, Player::new(), Spellbook::new(), 0.0);
+        for frame in &replay.frames {
+            game.tick(&replay.input, frame);
+        }
+        game.snapshot()
+    }
+
+    fn holding(key: KeyCode, ticks: usize) -> impl Iterator<Item = InputFrame> {
+        std::iter::repeat_n(
+            InputFrame {
+                down: vec![key],
+                ..InputFrame::default()
+            },
+            ticks,
+        )
+    }
+
+    fn pressing(key: KeyCode) -> InputFrame {
+        InputFrame {
+            down: vec![key],
+            pressed: vec![key],
+            mouse: WorldPos::default() + Vec2::new(400.0, 0.0),
+            ..InputFrame::default()
+        }
+    }
+
+    /// Walks east into the next chunk, casts a spell with words that weren't
+    /// known yet, then walks south into the next chunk
+    fn scripted_session() -> Replay {
+        let mut replay = Replay::new(0xC0FFEE, InputMap::new());
+        replay.frames.extend(holding(KeyCode::D, 400));
+        replay.frames.push(pressing(KeyCode::Enter));
+        replay.frames.push(InputFrame {
+            chars: "water burst".chars().collect(),
+            ..InputFrame::default()
+        });
+        replay.frames.push(pressing(KeyCode::Enter));
+        replay.frames.extend(holding(KeyCode::S, 250));
+        replay
+    }
+
+    #[test]
+    fn replay_reaches_the_recorded_state() {
+        let snapshot = run_headless(&scripted_session());
+
+        let step = config::PLAYER_SPEED;
+        assert_eq!(
+            snapshot.player_position,
+            WorldPos::default() + Vec2::new(400.0 * step, 250.0 * step)
+        );
+        assert_eq!(snapshot.discovered, ["bolt", "burst", "fire", "water"]);
+
+        let radius = config::CACHE_CHUNKS_RADIUS;
+        let center = ChunkPos { x: 1, y: 1 };
+        let expected: BTreeSet<ChunkPos> = (-radius..=radius)
+            .flat_map(|dy| {
+                (-radius..=radius).map(move |dx| ChunkPos {
+                    x: center.x + dx,
+                    y: center.y + dy,
+                })
+            })
+            .collect();
+        assert_eq!(snapshot.loaded_chunks, expected);
+    }
+
+    #[test]
+    fn replaying_twice_gives_the_same_state() {
+        let replay = scripted_session();
+        assert_eq!(run_headless(&replay), run_headless(&replay));
+    }
+
+    #[test]
+    fn replay_survives_being_written_out() {
+        let replay = scripted_session();
+        let text = replay.to_text();
+        // Holding a key for hundreds of ticks collapses into a single repeat line
+        let ticks = text.lines().filter(|line| !line.starts_with("bind"));
+        assert!(ticks.count() < 10);
+
+        let read = Replay::from_text(&text).unwrap();
+        assert_eq!(read, replay);
+        assert_eq!(run_headless(&read), run_headless(&replay));
+    }
+
+    #[test]
+    fn replay_plays_back_through_its_own_controls() {
+        let mut input = InputMap::new();
+        input.clear(Action::MoveRight);
+        input
+            .bind(Action::MoveRight, input::Binding::Key(KeyCode::L))
+            .unwrap();
+        let mut replay = Replay::new(0xC0FFEE, input);
+        replay.frames.extend(holding(KeyCode::L, 100));
+        replay.frames.extend(holding(KeyCode::D, 100));
+
+        let read = Replay::from_text(&replay.to_text()).unwrap();
+        assert_eq!(read, replay);
+        // L walked east, D did nothing with these controls
+        let step = config::PLAYER_SPEED;
+        assert_eq!(
+            run_headless(&read).player_position,
+            WorldPos::default() + Vec2::new(100.0 * step, 0.0)
+        );
+    }
+
+    #[test]
+    fn replays_from_older_versions_still_read() {
+        // Version 1 had a stick that never moved and no controls, 2 dropped the stick
+        let frames = [
+            (1, "frame 0 0 0 0 D,Enter Enter 104,105"),
+            (2, "frame 0 0 D,Enter Enter 104,105"),
+        ];
+        for (version, frame) in frames {
+            let text = format!(
+                "spellcaster-replay {}\nseed 7\n{}\nrepeat 2\n",
+                version, frame
+            );
+            let replay = Replay::from_text(&text).unwrap();
+            assert_eq!(replay.seed, 7);
+            assert_eq!(replay.input, InputMap::new());
+            assert_eq!(
+                replay.frames,
+                vec![
+                    InputFrame {
+                        down: vec![KeyCode::D, KeyCode::Enter],
+                        pressed: vec![KeyCode::Enter],
+                        chars: vec!['h', 'i'],
+                        ..InputFrame::default()
+                    };
+                    3
+                ],
+                "version {}",
+                version
+            );
+        }
+    }
+
+    #[test]
+    fn stick_walking_survives_being_written_out() {
+        let mut replay = Replay::new(0xC0FFEE, InputMap::new());
+        let tilted = InputFrame {
+            stick: Vec2::new(0.6, -0.8),
+            ..InputFrame::default()
+        };
+        replay.frames.extend(std::iter::repeat_n(tilted, 100));
+
+        let read = Replay::from_text(&replay.to_text()).unwrap();
+        assert_eq!(read, replay);
+        let walked = run_headless(&read).player_position - WorldPos::default();
+        let step = config::PLAYER_SPEED;
+        assert!(walked.abs_diff_eq(Vec2::new(0.6, -0.8) * 100.0 * step, 1e-3));
+    }
+
+    #[test]
+    fn aiming_millions_of_tiles_out_survives_being_written_out() {
+        let far = TilePos {
+            x: 5_000_000,
+            y: -3_000_000,
+        }
+        .world_center();
+        let mut replay = Replay::new(1, InputMap::new());
+        replay.record(InputFrame {
+            mouse: far + Vec2::new(0.25, -0.5),
+            ..InputFrame::default()
+        });
+        assert_eq!(Replay::from_text(&replay.to_text()).unwrap(), replay);
+    }
+}
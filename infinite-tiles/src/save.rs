@@ -0,0 +1,646 @@
+// save.rs
+// versioned save files capturing everything that isn't regenerated from the seed
+use crate::animation::{self, Animator};
+use crate::clock::WorldClock;
+use crate::config;
+use crate::entity::{Ai, Entity, EntityId, Health, Sprite};
+use crate::fog::Explored;
+use crate::player::Player;
+use crate::spell::Spellbook;
+use crate::tile::{self, Layer, TileId};
+use crate::types::{ChunkPos, WorldPos};
+use crate::world::{Chunk, World};
+use macroquad::prelude::*;
+use std::fmt;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Bump whenever the layout changes, and teach `migrate` to upgrade the previous version
+pub const SAVE_VERSION: u32 = 7;
+const MAGIC: &str = "spellcaster-save";
+
+#[derive(Debug)]
+pub enum SaveError {
+    Io(io::Error),
+    Corrupt(String),
+    UnsupportedVersion(u32),
+}
+
+impl fmt::Display for SaveError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SaveError::Io(err) => write!(f, "i/o error: {}", err),
+            SaveError::Corrupt(reason) => write!(f, "corrupt save: {}", reason),
+            SaveError::UnsupportedVersion(version) => {
+                write!(f, "unsupported save version {}", version)
+            }
+        }
+    }
+}
+
+impl From<io::Error> for SaveError {
+    fn from(err: io::Error) -> Self {
+        SaveError::Io(err)
+    }
+}
+
+/// Everything needed to resume a game. Unmodified chunks are not stored,
+/// they are generated again from the seed.
+pub struct SaveData {
+    pub seed: u64,
+    pub clock: u64,       // World clock ticks
+    pub play_time: f64,   // Seconds
+    pub last_played: u64, // Unix timestamp of the save
+    pub player_position: WorldPos,
+    pub player_health: f32,
+    pub discovered: Vec<String>,
+    pub visited: Vec<ChunkPos>,
+    pub chunks: Vec<Chunk>,
+    pub explored: Vec<(ChunkPos, Explored)>,
+    pub entities: Vec<Entity>,
+    pub markers: Vec<WorldPos>, // Placed by the player on the world map
+}
+
+impl SaveData {
+    pub fn capture(
+        world: &World,
+        player: &Player,
+        spellbook: &Spellbook,
+        markers: &[WorldPos],
+        play_time: f64,
+    ) -> Self {
+        SaveData {
+            seed: world.seed(),
+            clock: world.clock().ticks(),
+            play_time,
+            last_played: unix_now(),
+            player_position: player.get_position(),
+            player_health: player.health.current,
+            discovered: spellbook.words().map(str::to_string).collect(),
+            visited: world.visited_chunks().collect(),
+            chunks: world.modified_chunks().cloned().collect(),
+            explored: world.explored_chunks().collect(),
+            // Projectiles in flight are not worth keeping
+            entities: world
+                .entities
+                .iter_all()
+                .filter(|entity| entity.projectile.is_none())
+                .cloned()
+                .collect(),
+            markers: markers.to_vec(),
+        }
+    }
+
+    /// Rebuilds the world, player and spellbook. The caller still has to stream
+    /// in the chunks around the player with `World::stream_chunks`.
+    pub async fn restore(self) -> (World, Player, Spellbook) {
+        let mut player = Player::new();
+        player.set_position(self.player_position);
+        player.health.current = self.player_health.min(player.health.max);
+
+        let mut spellbook = Spellbook::new();
+        for word in &self.discovered {
+            spellbook.discover(word);
+        }
+
+        let world = World::restore(
+            self.seed,
+            WorldClock::from_ticks(self.clock),
+            self.visited,
+            self.chunks,
+            self.explored,
+            self.entities,
+        )
+        .await;
+        (world, player, spellbook)
+    }
+
+    /// Writes the save next to its destination first and then renames it into place,
+    /// so a crash mid-save leaves the previous save intact
+    pub fn write(&self, path: &Path) -> Result<(), SaveError> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let temp_path = path.with_extension("tmp");
+        {
+            let mut file = fs::File::create(&temp_path)?;
+            file.write_all(self.to_text().as_bytes())?;
+            file.sync_all()?;
+        }
+        fs::rename(&temp_path, path)?;
+        Ok(())
+    }
+
+    pub fn read(path: &Path) -> Result<Self, SaveError> {
+        Self::from_text(&fs::read_to_string(path)?)
+    }
+
+    pub fn to_text(&self) -> String {
+        let mut out = format!("{} {}\n", MAGIC, SAVE_VERSION);
+        out += &format!("seed {}\n", self.seed);
+        out += &format!("meta {} {}\n", self.play_time, self.last_played);
+        out += &format!("clock {}\n", self.clock);
+        out += &format!(
+            "player {} {}\n",
+            format_position(self.player_position),
+            self.player_health
+        );
+        out += &format!("spellbook {}\n", self.discovered.join(" "));
+
+        out += "visited";
+        for pos in &self.visited {
+            out += &format!(" {} {}", pos.x, pos.y);
+        }
+        out += "\n";
+
+        out += "markers";
+        for &marker in &self.markers {
+            out += &format!(" {}", format_position(marker));
+        }
+        out += "\n";
+
+        for chunk in &self.chunks {
+            out += &format!("chunk {} {}", chunk.pos.x, chunk.pos.y);
+            for tile in chunk.tiles() {
+                out += &format!(" {}", tile);
+            }
+            out += "\n";
+        }
+
+        for (pos, explored) in &self.explored {
+            out += &format!("explored {} {} {}\n", pos.x, pos.y, explored.to_hex());
+        }
+
+        for entity in &self.entities {
+            let color = entity.sprite.color;
+            out += &format!(
+                "entity {} {} {} {} {} {}",
+                format_position(entity.position),
+                color.r,
+                color.g,
+                color.b,
+                color.a,
+                entity.sprite.size
+            );
+            match entity.health {
+                Some(health) => out += &format!(" {} {}", health.current, health.max),
+                None => out += " - -",
+            }
+            out += if entity.ai.is_some() {
+                " wander\n"
+            } else {
+                " -\n"
+            };
+        }
+
+        out
+    }
+
+    pub fn from_text(text: &str) -> Result<Self, SaveError> {
+        let mut lines = text.lines();
+        let header = lines.next().unwrap_or_default();
+        let version = match header.split_once(' ') {
+            Some((MAGIC, version)) => parse::<u32>(version)?,
+            _ => return Err(SaveError::Corrupt("not a save file".to_string())),
+        };
+        let lines = migrate(version, lines.map(str::to_string).collect())?;
+
+        let mut save = SaveData {
+            seed: 0,
+            clock: 0,
+            play_time: 0.0,
+            last_played: 0,
+            player_position: WorldPos::default(),
+            player_health: config::PLAYER_MAX_HEALTH,
+            discovered: Vec::new(),
+            visited: Vec::new(),
+            chunks: Vec::new(),
+            explored: Vec::new(),
+            entities: Vec::new(),
+            markers: Vec::new(),
+        };
+        let mut has_seed = false;
+
+        for line in &lines {
+            let mut fields = line.split_whitespace();
+            let values: Vec<&str> = fields.clone().skip(1).collect();
+            match fields.next() {
+                Some("seed") => {
+                    save.seed = parse(field(&values, 0)?)?;
+                    has_seed = true;
+                }
+                Some("clock") => save.clock = parse(field(&values, 0)?)?,
+                Some("meta") => {
+                    save.play_time = parse(field(&values, 0)?)?;
+                    save.last_played = parse(field(&values, 1)?)?;
+                }
+                Some("player") => {
+                    save.player_position = parse_position(&values, 0)?;
+                    save.player_health = parse(field(&values, 2)?)?;
+                }
+                Some("spellbook") => {
+                    save.discovered = values.iter().map(|word| word.to_string()).collect();
+                }
+                Some("visited") => {
+                    for pair in values.chunks(2) {
+                        save.visited.push(ChunkPos {
+                            x: parse(field(pair, 0)?)?,
+                            y: parse(field(pair, 1)?)?,
+                        });
+                    }
+                }
+                Some("markers") => {
+                    for pair in values.chunks(2) {
+                        save.markers.push(parse_position(pair, 0)?);
+                    }
+                }
+                Some("chunk") => {
+                    let pos = ChunkPos {
+                        x: parse(field(&values, 0)?)?,
+                        y: parse(field(&values, 1)?)?,
+                    };
+                    let tiles = values[2..]
+                        .iter()
+                        .map(|tile| parse::<TileId>(tile))
+                        .collect::<Result<Vec<_>, _>>()?;
+                    let chunk = Chunk::from_tiles(pos, &tiles).ok_or_else(|| {
+                        SaveError::Corrupt(format!("chunk {:?} has {} tiles", pos, tiles.len()))
+                    })?;
+                    save.chunks.push(chunk);
+                }
+                Some("explored") => {
+                    let pos = ChunkPos {
+                        x: parse(field(&values, 0)?)?,
+                        y: parse(field(&values, 1)?)?,
+                    };
+                    let explored = Explored::from_hex(field(&values, 2)?).ok_or_else(|| {
+                        SaveError::Corrupt(format!("chunk {:?} has an unreadable fog", pos))
+                    })?;
+                    save.explored.push((pos, explored));
+                }
+                Some("entity") => save.entities.push(parse_entity(&values)?),
+                Some(other) => {
+                    return Err(SaveError::Corrupt(format!("unknown record '{}'", other)));
+                }
+                None => {}
+            }
+        }
+
+        if !has_seed {
+            return Err(SaveError::Corrupt("missing world seed".to_string()));
+        }
+        Ok(save)
+    }
+}
+
+/// Upgrades the lines of an older save to the current layout.
+/// Each old version gets an arm rewriting its lines into the next version's layout.
+fn migrate(version: u32, mut lines: Vec<String>) -> Result<Vec<String>, SaveError> {
+    match version {
+        SAVE_VERSION => Ok(lines),
+        // Version 1 didn't track play time or when the save was made
+        1 => {
+            lines.push("meta 0 0".to_string());
+            migrate(2, lines)
+        }
+        // Version 2 had no player health, the wizard wakes up unhurt
+        2 => {
+            for line in lines.iter_mut().filter(|line| line.starts_with("player ")) {
+                *line += &format!(" {}", config::PLAYER_MAX_HEALTH);
+            }
+            migrate(3, lines)
+        }
+        // Version 3 had no map markers, the map starts out unmarked
+        3 => migrate(4, lines),
+        // Version 4 had no fog of war, everything already visited counts as explored
+        4 => {
+            let all = Explored::all().to_hex();
+            let mut explored = Vec::new();
+            for line in lines.iter().filter(|line| line.starts_with("visited")) {
+                let values: Vec<&str> = line.split_whitespace().skip(1).collect();
+                for pair in values.chunks(2) {
+                    let (x, y) = (field(pair, 0)?, field(pair, 1)?);
+                    explored.push(format!("explored {} {} {}", x, y, all));
+                }
+            }
+            lines.extend(explored);
+            migrate(5, lines)
+        }
+        // Version 5 had no world clock, the time of day followed the play time
+        5 => {
+            let mut play_time = 0.0;
+            for line in lines.iter().filter(|line| line.starts_with("meta ")) {
+                let values: Vec<&str> = line.split_whitespace().skip(1).collect();
+                play_time = parse::<f64>(field(&values, 0)?)?;
+            }
+            let start = WorldClock::new().ticks();
+            let ticks = start + (play_time * config::TICKS_PER_SECOND as f64) as u64;
+            lines.push(format!("clock {}", ticks));
+            migrate(6, lines)
+        }
+        // Version 6 chunks only had ground, the layers above it start out empty
+        6 => {
+            let empty = format!(" {}", tile::EMPTY).repeat((Layer::COUNT - 1) * config::CHUNK_AREA);
+            for line in lines.iter_mut().filter(|line| line.starts_with("chunk ")) {
+                *line += &empty;
+            }
+            migrate(7, lines)
+        }
+        _ => Err(SaveError::UnsupportedVersion(version)),
+    }
+}
+
+/// What the title screen shows about a save slot
+pub struct SlotSummary {
+    pub seed: u64,
+    pub play_time: f64,
+    pub spells_discovered: usize,
+    pub last_played: u64,
+}
+
+pub fn slot_path(slot: usize) -> PathBuf {
+    Path::new(config::SAVE_DIR).join(format!("slot{}.sav", slot + 1))
+}
+
+/// Summary of a save slot, None if the slot is empty
+pub fn read_slot_summary(slot: usize) -> Option<Result<SlotSummary, SaveError>> {
+    let path = slot_path(slot);
+    if !path.exists() {
+        return None;
+    }
+
+    Some(SaveData::read(&path).map(|save| SlotSummary {
+        seed: save.seed,
+        play_time: save.play_time,
+        spells_discovered: save.discovered.len(),
+        last_played: save.last_played,
+    }))
+}
+
+pub fn delete_slot(slot: usize) -> Result<(), SaveError> {
+    fs::remove_file(slot_path(slot))?;
+    Ok(())
+}
+
+pub fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_secs())
+}
+
+fn parse_entity(values: &[&str]) -> Result<Entity, SaveError> {
+    let number = |index| parse::<f32>(field(values, index)?);
+    let health = match field(values, 7)? {
+        "-" => None,
+        _ => Some(Health {
+            current: number(7)?,
+            max: number(8)?,
+        }),
+    };
+    let ai = match field(values, 9)? {
+        "wander" => Some(Ai::Wander {
+            direction: Vec2::ZERO,
+            frames_left: 0,
+        }),
+        _ => None,
+    };
+
+    Ok(Entity {
+        id: EntityId::default(),
+        position: parse_position(values, 0)?,
+        sprite: Sprite {
+            color: Color::new(number(2)?, number(3)?, number(4)?, number(5)?),
+            size: number(6)?,
+        },
+        health,
+        // Wandering creatures are the only animated entities so far
+        animator: ai.map(|_| Animator::new(&animation::CREATURE_SHEET)),
+        ai,
+        projectile: None,
+    })
+}
+
+/// Positions are written as pixels from the world origin. f64 keeps them precise
+/// however far out they are, and saves from before positions had chunks still read.
+pub fn format_position(pos: WorldPos) -> String {
+    let pixels = pos.pixels();
+    format!("{} {}", pixels.x, pixels.y)
+}
+
+pub fn parse_position(values: &[&str], index: usize) -> Result<WorldPos, SaveError> {
+    let x = parse::<f64>(field(values, index)?)?;
+    let y = parse::<f64>(field(values, index + 1)?)?;
+    Ok(WorldPos::from_pixels(DVec2::new(x, y)))
+}
+
+pub fn field<'a>(values: &[&'a str], index: usize) -> Result<&'a str, SaveError> {
+    values
+        .get(index)
+        .copied()
+        .ok_or_else(|| SaveError::Corrupt("record is missing fields".to_string()))
+}
+
+pub fn parse<T: FromStr>(value: &str) -> Result<T, SaveError> {
+    value
+        .parse()
+        .map_err(|_| SaveError::Corrupt(format!("invalid value '{}'", value)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::spell::Spellbook;
+    use crate::types::{LocalTilePos, TilePos};
+
+    /// Fog of a chunk where only the top row has been seen
+    fn top_row() -> Explored {
+        let mut explored = Explored::default();
+        for x in 0..config::CHUNK_SIZE {
+            explored.explore(LocalTilePos { x, y: 0 });
+        }
+        explored
+    }
+
+    /// A save as the game wrote it at an older version. Every version writes the
+    /// same game: the wizard hurt and somewhere west, one sandy chunk changed,
+    /// two chunks visited, one creature around.
+    fn fixture(version: u32) -> String {
+        let mut lines = vec![format!("{} {}", MAGIC, version), "seed 42".to_string()];
+        if version >= 2 {
+            lines.push("meta 12.5 1700000000".to_string());
+        }
+        if version >= 6 {
+            lines.push("clock 9000".to_string());
+        }
+        lines.push(match version {
+            1 | 2 => "player -100.5 64".to_string(),
+            _ => "player -100.5 64 80".to_string(),
+        });
+        lines.push("spellbook bolt fire water".to_string());
+        lines.push("visited 0 0 -1 2".to_string());
+        if version >= 4 {
+            lines.push("markers 32 -96".to_string());
+        }
+        let layers = if version >= 7 { Layer::COUNT } else { 1 };
+        let mut chunk = "chunk -1 2".to_string();
+        chunk += &format!(" {}", tile::SAND).repeat(config::CHUNK_AREA);
+        chunk += &format!(" {}", tile::EMPTY).repeat((layers - 1) * config::CHUNK_AREA);
+        lines.push(chunk);
+        if version >= 5 {
+            lines.push(format!("explored -1 2 {}", top_row().to_hex()));
+        }
+        lines.push("entity 10 20 0.5 0.25 0 1 24 30 40 wander".to_string());
+        lines.join("\n")
+    }
+
+    #[test]
+    fn every_old_version_migrates_to_the_current_layout() {
+        for version in 1..=SAVE_VERSION {
+            let save = SaveData::from_text(&fixture(version))
+                .unwrap_or_else(|err| panic!("version {}: {}", version, err));
+            let at = |what: &str| format!("{} of version {}", what, version);
+
+            assert_eq!(save.seed, 42, "{}", at("seed"));
+            assert_eq!(
+                save.discovered,
+                ["bolt", "fire", "water"],
+                "{}",
+                at("spellbook")
+            );
+            assert_eq!(
+                save.visited,
+                [ChunkPos { x: 0, y: 0 }, ChunkPos { x: -1, y: 2 }],
+                "{}",
+                at("visited chunks")
+            );
+            assert_eq!(
+                save.player_position.pixels(),
+                DVec2::new(-100.5, 64.0),
+                "{}",
+                at("player position")
+            );
+            let health = if version < 3 {
+                config::PLAYER_MAX_HEALTH
+            } else {
+                80.0
+            };
+            assert_eq!(save.player_health, health, "{}", at("health"));
+            let play_time = if version < 2 { 0.0 } else { 12.5 };
+            assert_eq!(save.play_time, play_time, "{}", at("play time"));
+            let markers = if version < 4 { 0 } else { 1 };
+            assert_eq!(save.markers.len(), markers, "{}", at("markers"));
+            let clock = if version < 6 {
+                WorldClock::new().ticks() + (play_time * config::TICKS_PER_SECOND as f64) as u64
+            } else {
+                9000
+            };
+            assert_eq!(save.clock, clock, "{}", at("clock"));
+            assert_eq!(save.entities.len(), 1, "{}", at("entities"));
+            assert!(save.entities[0].ai.is_some());
+        }
+    }
+
+    #[test]
+    fn saves_from_before_fog_count_every_visited_chunk_as_explored() {
+        let save = SaveData::from_text(&fixture(4)).unwrap();
+        assert_eq!(
+            save.explored,
+            [
+                (ChunkPos { x: 0, y: 0 }, Explored::all()),
+                (ChunkPos { x: -1, y: 2 }, Explored::all()),
+            ]
+        );
+        // Fog saved since then is kept as it was
+        for version in 5..=SAVE_VERSION {
+            let save = SaveData::from_text(&fixture(version)).unwrap();
+            assert_eq!(save.explored, [(ChunkPos { x: -1, y: 2 }, top_row())]);
+        }
+    }
+
+    #[test]
+    fn ground_only_chunks_get_empty_layers_above() {
+        for version in 1..=SAVE_VERSION {
+            let save = SaveData::from_text(&fixture(version)).unwrap();
+            let [chunk] = save.chunks.as_slice() else {
+                panic!("version {} has {} chunks", version, save.chunks.len());
+            };
+            assert_eq!(chunk.pos, ChunkPos { x: -1, y: 2 });
+            for local in LocalTilePos::all() {
+                assert_eq!(chunk.get(Layer::Ground, local), tile::SAND);
+                for &layer in &Layer::ALL[1..] {
+                    assert_eq!(chunk.get(layer, local), tile::EMPTY);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn unknown_versions_and_other_files_are_refused() {
+        let future = fixture(SAVE_VERSION).replacen(
+            &format!("{} {}", MAGIC, SAVE_VERSION),
+            &format!("{} {}", MAGIC, SAVE_VERSION + 1),
+            1,
+        );
+        assert!(matches!(
+            SaveData::from_text(&future),
+            Err(SaveError::UnsupportedVersion(_))
+        ));
+        assert!(matches!(
+            SaveData::from_text("hello world"),
+            Err(SaveError::Corrupt(_))
+        ));
+        let truncated = fixture(SAVE_VERSION).replace("chunk -1 2 3 3", "chunk -1 2 3");
+        assert!(matches!(
+            SaveData::from_text(&truncated),
+            Err(SaveError::Corrupt(_))
+        ));
+    }
+
+    #[test]
+    fn a_played_game_survives_a_round_trip() {
+        let mut world = World::with_textures(9, None);
+        world.stream_chunks(ChunkPos::default());
+        world.set_tile(Layer::Ground, TilePos { x: 3, y: -2 }, tile::ASH);
+        world.set_tile(Layer::Overlay, TilePos { x: -20, y: 5 }, tile::FROST);
+        world.look_around(TilePos { x: 0, y: 0 }, 6);
+        let mut player = Player::new();
+        player.set_position(TilePos { x: -7, y: 11 }.world_center() + Vec2::new(0.25, -3.5));
+        player.health.current = 55.5;
+        let mut spellbook = Spellbook::new();
+        spellbook.discover("lightning");
+        let markers = [TilePos {
+            x: 1_000_000,
+            y: -5,
+        }
+        .world_center()];
+
+        let save = SaveData::capture(&world, &player, &spellbook, &markers, 321.25);
+        let text = save.to_text();
+        let loaded = SaveData::from_text(&text).unwrap();
+        // Writing what was read gives back the same file
+        assert_eq!(loaded.to_text(), text);
+
+        assert_eq!(loaded.seed, 9);
+        assert_eq!(loaded.clock, world.clock().ticks());
+        assert_eq!(loaded.play_time, 321.25);
+        assert_eq!(loaded.player_position, player.get_position());
+        assert_eq!(loaded.player_health, 55.5);
+        assert_eq!(loaded.discovered, ["bolt", "fire", "lightning"]);
+        assert_eq!(loaded.markers, markers);
+        assert_eq!(loaded.visited.len(), save.visited.len());
+        assert_eq!(loaded.entities.len(), save.entities.len());
+        let tiles = |chunks: &[Chunk]| -> Vec<(ChunkPos, Vec<TileId>)> {
+            chunks
+                .iter()
+                .map(|chunk| (chunk.pos, chunk.tiles().collect()))
+                .collect()
+        };
+        assert_eq!(tiles(&loaded.chunks), tiles(&save.chunks));
+        assert_eq!(loaded.chunks.len(), 2);
+        assert_eq!(loaded.explored, save.explored);
+        assert!(!loaded.explored.is_empty());
+    }
+}
@@ -0,0 +1,319 @@
+// shape.rs
+// spatial shapes of spells, rasterized to the tiles they affect
+use crate::types::TilePos;
+use macroquad::prelude::Vec2;
+
+/// Area a spell covers, relative to the tile it originates from
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Shape {
+    /// Straight path of `length` tiles in a direction
+    Bolt { direction: Vec2, length: i32 },
+    /// Wedge opening in a direction, `half_angle` in radians
+    Cone {
+        direction: Vec2,
+        range: i32,
+        half_angle: f32,
+    },
+    /// Filled disc around the origin
+    Circle { radius: i32 },
+    /// Straight path from the origin to a target tile
+    Line { to: TilePos },
+    /// Segment perpendicular to a direction, `distance` tiles ahead of the origin
+    Wall {
+        direction: Vec2,
+        distance: i32,
+        half_width: i32,
+    },
+}
+
+impl Shape {
+    /// Every tile covered by the shape when cast from `origin`, without duplicates
+    pub fn rasterize(&self, origin: TilePos) -> Vec<TilePos> {
+        match *self {
+            Shape::Bolt { direction, length } => line(
+                wide(origin),
+                offset_along(wide(origin), direction, length as f32),
+            ),
+            Shape::Cone {
+                direction,
+                range,
+                half_angle,
+            } => cone(origin, direction, range, half_angle),
+            Shape::Circle { radius } => circle(origin, radius),
+            Shape::Line { to } => line(wide(origin), wide(to)),
+            Shape::Wall {
+                direction,
+                distance,
+                half_width,
+            } => {
+                let center = offset_along(wide(origin), direction, distance as f32);
+                let across = direction.perp();
+                line(
+                    offset_along(center, across, -half_width as f32),
+                    offset_along(center, across, half_width as f32),
+                )
+            }
+        }
+    }
+}
+
+/// Column and row of a tile in i64, so points past the edge of the grid and
+/// spans near the i32 limits can't overflow
+fn wide(tile: TilePos) -> (i64, i64) {
+    (tile.x as i64, tile.y as i64)
+}
+
+/// Point `distance` tiles away from `from` in `direction`, maybe off the grid
+fn offset_along(from: (i64, i64), direction: Vec2, distance: f32) -> (i64, i64) {
+    let offset = direction.normalize_or_zero() * distance;
+    (
+        from.0 + offset.x.round() as i64,
+        from.1 + offset.y.round() as i64,
+    )
+}
+
+/// Bresenham line including both endpoints, leaving out the tiles past the
+/// edge of the `i32` grid
+fn line((x0, y0): (i64, i64), (x1, y1): (i64, i64)) -> Vec<TilePos> {
+    let dx = (x1 - x0).abs();
+    let dy = -(y1 - y0).abs();
+    let step_x = if x0 < x1 { 1 } else { -1 };
+    let step_y = if y0 < y1 { 1 } else { -1 };
+
+    let mut tiles = Vec::with_capacity((dx.max(-dy) + 1) as usize);
+    let (mut x, mut y) = (x0, y0);
+    let mut error = dx + dy;
+    loop {
+        if let (Ok(x), Ok(y)) = (i32::try_from(x), i32::try_from(y)) {
+            tiles.push(TilePos { x, y });
+        }
+        if x == x1 && y == y1 {
+            break;
+        }
+        let doubled = 2 * error;
+        if doubled >= dy {
+            error += dy;
+            x += step_x;
+        }
+        if doubled <= dx {
+            error += dx;
+            y += step_y;
+        }
+    }
+    tiles
+}
+
+/// Filled disc, leaving out the tiles past the edge of the `i32` grid
+fn circle(center: TilePos, radius: i32) -> Vec<TilePos> {
+    // The extra `radius` rounds the disc out so small circles aren't diamonds
+    let limit = radius * radius + radius;
+    let mut tiles = Vec::new();
+    for dy in -radius..=radius {
+        for dx in -radius..=radius {
+            if dx * dx + dy * dy <= limit
+                && let Some(tile) = center.offset(dx, dy)
+            {
+                tiles.push(tile);
+            }
+        }
+    }
+    tiles
+}
+
+fn cone(origin: TilePos, direction: Vec2, range: i32, half_angle: f32) -> Vec<TilePos> {
+    let direction = direction.normalize_or_zero();
+    if direction == Vec2::ZERO {
+        return Vec::new();
+    }
+
+    let min_cos = half_angle.cos();
+    circle(origin, range)
+        .into_iter()
+        .filter(|tile| {
+            let offset = Vec2::new((tile.x - origin.x) as f32, (tile.y - origin.y) as f32);
+            // The caster's own tile is not part of the cone
+            offset != Vec2::ZERO && offset.normalize().dot(direction) >= min_cos
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config;
+    use std::collections::HashSet;
+
+    fn tile(x: i32, y: i32) -> TilePos {
+        TilePos { x, y }
+    }
+
+    fn assert_unique(tiles: &[TilePos]) {
+        let unique: HashSet<_> = tiles.iter().collect();
+        assert_eq!(unique.len(), tiles.len(), "duplicate tiles in {:?}", tiles);
+    }
+
+    fn assert_connected(tiles: &[TilePos]) {
+        for pair in tiles.windows(2) {
+            let step = ((pair[1].x - pair[0].x).abs(), (pair[1].y - pair[0].y).abs());
+            assert!(step.0 <= 1 && step.1 <= 1, "gap between {:?}", pair);
+        }
+    }
+
+    #[test]
+    fn line_includes_both_endpoints() {
+        let tiles = Shape::Line { to: tile(-7, 3) }.rasterize(tile(2, -4));
+        assert_eq!(tiles.first(), Some(&tile(2, -4)));
+        assert_eq!(tiles.last(), Some(&tile(-7, 3)));
+        assert_eq!(tiles.len(), 10);
+        assert_unique(&tiles);
+        assert_connected(&tiles);
+    }
+
+    #[test]
+    fn line_to_itself_is_one_tile() {
+        let tiles = Shape::Line { to: tile(-3, -3) }.rasterize(tile(-3, -3));
+        assert_eq!(tiles, vec![tile(-3, -3)]);
+    }
+
+    #[test]
+    fn line_crosses_chunk_edges_without_gaps() {
+        // Start on the last tile of the origin chunk and run west through negative chunks
+        let from = tile(config::CHUNK_SIZE - 1, 1);
+        let to = tile(-config::CHUNK_SIZE - 1, -2);
+        let tiles = Shape::Line { to }.rasterize(from);
+        assert_connected(&tiles);
+        assert_unique(&tiles);
+
+        let chunk_xs: HashSet<i32> = tiles.iter().map(|t| t.to_chunk_local().0.x).collect();
+        assert_eq!(chunk_xs, HashSet::from([-2, -1, 0]));
+    }
+
+    #[test]
+    fn line_near_i32_limits_does_not_overflow() {
+        let tiles = Shape::Line {
+            to: tile(i32::MAX, i32::MIN + 2),
+        }
+        .rasterize(tile(i32::MAX - 2, i32::MIN));
+        assert_eq!(tiles.len(), 3);
+        assert_connected(&tiles);
+    }
+
+    #[test]
+    fn circle_is_symmetric_around_negative_origin() {
+        let center = tile(-17, -33);
+        let tiles = Shape::Circle { radius: 3 }.rasterize(center);
+        assert_unique(&tiles);
+        let set: HashSet<_> = tiles.iter().copied().collect();
+        for t in &tiles {
+            let (dx, dy) = (t.x - center.x, t.y - center.y);
+            assert!(set.contains(&tile(center.x - dx, center.y + dy)));
+            assert!(set.contains(&tile(center.x + dx, center.y - dy)));
+            assert!(set.contains(&tile(center.x + dy, center.y + dx)));
+        }
+        assert!(set.contains(&center));
+        assert!(set.contains(&tile(center.x + 3, center.y)));
+        assert!(!set.contains(&tile(center.x + 3, center.y + 3)));
+    }
+
+    #[test]
+    fn circle_of_radius_zero_is_the_origin() {
+        assert_eq!(
+            Shape::Circle { radius: 0 }.rasterize(tile(5, -5)),
+            vec![tile(5, -5)]
+        );
+    }
+
+    #[test]
+    fn cone_only_covers_tiles_ahead() {
+        let origin = tile(-1, -1);
+        let tiles = Shape::Cone {
+            direction: Vec2::new(-1.0, 0.0),
+            range: 5,
+            half_angle: std::f32::consts::FRAC_PI_4,
+        }
+        .rasterize(origin);
+        assert!(!tiles.is_empty());
+        assert_unique(&tiles);
+        assert!(!tiles.contains(&origin));
+        for t in &tiles {
+            assert!(t.x < origin.x, "{:?} is not ahead of the caster", t);
+            assert!((t.y - origin.y).abs() <= origin.x - t.x);
+        }
+        assert!(tiles.contains(&tile(-6, -1)));
+    }
+
+    #[test]
+    fn cone_without_direction_is_empty() {
+        let cone = Shape::Cone {
+            direction: Vec2::ZERO,
+            range: 4,
+            half_angle: 1.0,
+        };
+        assert!(cone.rasterize(tile(0, 0)).is_empty());
+    }
+
+    #[test]
+    fn bolt_follows_direction_for_its_length() {
+        let tiles = Shape::Bolt {
+            direction: Vec2::new(0.0, -2.0),
+            length: 6,
+        }
+        .rasterize(tile(3, 2));
+        let expected: Vec<_> = (0..=6).map(|i| tile(3, 2 - i)).collect();
+        assert_eq!(tiles, expected);
+    }
+
+    #[test]
+    fn wall_is_perpendicular_to_direction() {
+        let tiles = Shape::Wall {
+            direction: Vec2::new(1.0, 0.0),
+            distance: 3,
+            half_width: 2,
+        }
+        .rasterize(tile(-10, 4));
+        assert_eq!(tiles.len(), 5);
+        assert_unique(&tiles);
+        assert!(tiles.iter().all(|t| t.x == -7));
+        let ys: HashSet<i32> = tiles.iter().map(|t| t.y).collect();
+        assert_eq!(ys, HashSet::from([2, 3, 4, 5, 6]));
+    }
+
+    #[test]
+    fn shapes_past_the_edge_of_the_grid_are_cut_off() {
+        let corner = tile(i32::MAX - 1, i32::MIN + 1);
+        // The same disc around the middle of the grid, moved to the corner
+        let expected: Vec<TilePos> = Shape::Circle { radius: 3 }
+            .rasterize(tile(0, 0))
+            .into_iter()
+            .filter(|t| t.x <= 1 && t.y >= -1)
+            .map(|t| tile(corner.x + t.x, corner.y + t.y))
+            .collect();
+        let circle = Shape::Circle { radius: 3 }.rasterize(corner);
+        assert_eq!(circle, expected);
+        assert!(circle.contains(&tile(i32::MAX, i32::MIN)));
+
+        let bolt = Shape::Bolt {
+            direction: Vec2::new(1.0, 0.0),
+            length: 5,
+        }
+        .rasterize(corner);
+        assert_eq!(bolt, vec![corner, tile(i32::MAX, i32::MIN + 1)]);
+
+        // Entirely off the grid
+        let wall = Shape::Wall {
+            direction: Vec2::new(0.0, -1.0),
+            distance: 3,
+            half_width: 2,
+        }
+        .rasterize(corner);
+        assert!(wall.is_empty());
+
+        let cone = Shape::Cone {
+            direction: Vec2::new(1.0, -1.0),
+            range: 4,
+            half_angle: 0.5,
+        }
+        .rasterize(corner);
+        assert_eq!(cone, vec![tile(i32::MAX, i32::MIN)]);
+    }
+}
@@ -0,0 +1,239 @@
+// simulation.rs
+// cellular automaton letting tiles react to their neighbors over time
+use crate::config;
+use crate::hash;
+use crate::tile::{Layer, TILES, TileDef, TileId, Trigger, tile_def};
+use crate::types::TilePos;
+use crate::world::World;
+
+// Salt of the flow roll, reactions use their layer and index instead
+const FLOW_SALT: u64 = 0xff;
+
+/// Steps tile reactions every few game ticks, slower than everything else
+pub struct CellularAutomaton {
+    tick: u64,             // Generations stepped so far
+    ticks_until_step: u32, // Game ticks left before the next generation
+}
+
+impl CellularAutomaton {
+    pub fn new() -> Self {
+        CellularAutomaton {
+            tick: 0,
+            ticks_until_step: 0,
+        }
+    }
+
+    /// Called once per game tick, steps a generation when one is due
+    pub fn update(&mut self, world: &mut World) {
+        if self.ticks_until_step == 0 {
+            self.step(world);
+            self.ticks_until_step =
+                (config::TICKS_PER_SECOND / config::CELLULAR_TICKS_PER_SECOND) as u32;
+        }
+        self.ticks_until_step -= 1;
+    }
+
+    /// One generation: every tile looks at the current state of its neighbors,
+    /// then all changes are applied together so the scan order doesn't matter
+    fn step(&mut self, world: &mut World) {
+        let mut changes = Vec::new();
+        // Tiles lower than this are the only ones a liquid can run into
+        let flood_height = TILES
+            .iter()
+            .filter(|def| def.flow > 0.0)
+            .map(|def| def.height)
+            .max()
+            .unwrap_or(0);
+        let can_change = |layer: Layer, def: &TileDef| {
+            !def.reactions.is_empty()
+                || (layer == Layer::Ground && !def.solid && def.height < flood_height)
+        };
+
+        for chunk_pos in world.simulated_chunks() {
+            let Some(chunk) = world.chunk(chunk_pos) else {
+                continue;
+            };
+            let raining = world.weather(chunk_pos).is_wet();
+            for layer in Layer::ALL {
+                // Most tiles never react, and the layers above the ground are mostly empty
+                let grid = chunk.layer(layer);
+                if !grid
+                    .palette()
+                    .iter()
+                    .any(|&tile| can_change(layer, tile_def(tile)))
+                {
+                    continue;
+                }
+                for (local, tile) in grid.iter() {
+                    let def = tile_def(tile);
+                    if !can_change(layer, def) {
+                        continue;
+                    }
+                    let pos = chunk_pos.tile(local);
+                    if let Some(next) = self.react(world, layer, pos, def, raining) {
+                        changes.push((layer, pos, next));
+                    }
+                }
+            }
+        }
+
+        for (layer, pos, tile) in changes {
+            world.set_tile(layer, pos, tile);
+        }
+        self.tick += 1;
+    }
+
+    /// What the tile of a layer at `pos`, of type `def`, turns into this tick, if
+    /// anything. Neighbors are looked for in the same layer.
+    fn react(
+        &self,
+        world: &World,
+        layer: Layer,
+        pos: TilePos,
+        def: &TileDef,
+        raining: bool,
+    ) -> Option<TileId> {
+        // Neighbors may live in the adjacent chunk, unloaded ones are ignored
+        let mut neighbors = [None; 4];
+        for (slot, neighbor) in neighbors.iter_mut().zip(pos.neighbors()) {
+            *slot = world.get_tile(layer, neighbor);
+        }
+        let ground = world.get_tile(Layer::Ground, pos);
+
+        // A liquid sitting higher runs in, whatever the tile would do otherwise
+        if layer == Layer::Ground
+            && !def.solid
+            && let Some((liquid, flow)) = neighbors.iter().flatten().find_map(|&tile| {
+                let neighbor = tile_def(tile);
+                (neighbor.flow > 0.0 && neighbor.height > def.height)
+                    .then_some((tile, neighbor.flow))
+            })
+            && roll(pos, self.tick, FLOW_SALT) < flow
+        {
+            return Some(liquid);
+        }
+
+        for (index, reaction) in def.reactions.iter().enumerate() {
+            let triggered = match reaction.trigger {
+                Trigger::Always => true,
+                Trigger::Rain => raining,
+                Trigger::Neighbor(tile) => neighbors.contains(&Some(tile)),
+                Trigger::Below(tile) => layer != Layer::Ground && ground == Some(tile),
+            };
+            // Each layer rolls its own dice
+            let salt = (layer as u64) << 8 | index as u64;
+            if triggered && roll(pos, self.tick, salt) < reaction.chance {
+                return Some(reaction.becomes);
+            }
+        }
+        None
+    }
+}
+
+/// Deterministic pseudo random number in [0, 1) for a tile, tick and reaction salt
+fn roll(pos: TilePos, tick: u64, salt: u64) -> f32 {
+    hash::unit(hash::hash2(tick, pos.x, pos.y, salt))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tile;
+    use crate::types::{ChunkPos, TileRect};
+
+    /// A world around the origin with a patch of bare sand across the chunk
+    /// corner at tile (0, 0). Sand never reacts to anything on its own.
+    fn sandbox(seed: u64) -> World {
+        let mut world = World::with_textures(seed, None);
+        world.stream_chunks(ChunkPos::default());
+        let patch = TileRect {
+            min: TilePos { x: -8, y: -8 },
+            max: TilePos { x: 8, y: 8 },
+        };
+        for pos in patch.iter() {
+            world.set_tile(Layer::Ground, pos, tile::SAND);
+            for &layer in &Layer::ALL[1..] {
+                world.set_tile(layer, pos, tile::EMPTY);
+            }
+        }
+        world
+    }
+
+    fn run(world: &mut World, generations: usize) {
+        let mut automaton = CellularAutomaton::new();
+        for _ in 0..generations {
+            automaton.step(world);
+        }
+    }
+
+    fn ground(world: &World, x: i32, y: i32) -> TileId {
+        world.get_tile(Layer::Ground, TilePos { x, y }).unwrap()
+    }
+
+    #[test]
+    fn water_runs_into_lower_tiles_only() {
+        let mut world = sandbox(3);
+        world.set_tile(Layer::Ground, TilePos { x: 0, y: 0 }, tile::WATER);
+        // Ash lies lower than water, stone higher, sand as high
+        world.set_tile(Layer::Ground, TilePos { x: 1, y: 0 }, tile::ASH);
+        world.set_tile(Layer::Ground, TilePos { x: 2, y: 0 }, tile::ASH);
+        world.set_tile(Layer::Ground, TilePos { x: 0, y: 1 }, tile::STONE);
+        run(&mut world, 100);
+
+        assert_eq!(ground(&world, 1, 0), tile::WATER);
+        // Water that ran in keeps running
+        assert_eq!(ground(&world, 2, 0), tile::WATER);
+        assert_eq!(ground(&world, 0, 1), tile::STONE);
+        assert_eq!(ground(&world, -1, 0), tile::SAND);
+        assert_eq!(ground(&world, 0, -1), tile::SAND);
+    }
+
+    #[test]
+    fn neighbors_across_a_chunk_border_react() {
+        // Tiles left of x = 0 are in chunk (-1, 0), tiles above y = 0 in chunk (0, -1)
+        let mut world = sandbox(3);
+        world.set_tile(Layer::Ground, TilePos { x: -1, y: 1 }, tile::WATER);
+        world.set_tile(Layer::Ground, TilePos { x: 0, y: 1 }, tile::ASH);
+        world.set_tile(Layer::Ground, TilePos { x: 3, y: -1 }, tile::FIRE);
+        world.set_tile(Layer::Ground, TilePos { x: 3, y: 0 }, tile::ICE);
+        run(&mut world, 100);
+
+        assert_eq!(ground(&world, 0, 1), tile::WATER);
+        assert_eq!(ground(&world, 3, 0), tile::WATER);
+    }
+
+    #[test]
+    fn fire_spreads_over_grass_and_burns_out() {
+        let mut world = sandbox(3);
+        for x in 0..4 {
+            world.set_tile(Layer::Ground, TilePos { x, y: 3 }, tile::GRASS);
+        }
+        world.set_tile(Layer::Ground, TilePos { x: -1, y: 3 }, tile::FIRE);
+
+        let mut automaton = CellularAutomaton::new();
+        let mut caught_fire = [false; 4];
+        for _ in 0..200 {
+            automaton.step(&mut world);
+            for (x, caught) in caught_fire.iter_mut().enumerate() {
+                *caught |= ground(&world, x as i32, 3) == tile::FIRE;
+            }
+        }
+        assert_eq!(caught_fire, [true; 4]);
+        for x in -1..4 {
+            assert_ne!(ground(&world, x, 3), tile::FIRE);
+        }
+        // Sand doesn't burn
+        assert_eq!(ground(&world, -2, 3), tile::SAND);
+        assert_eq!(ground(&world, 0, 4), tile::SAND);
+    }
+
+    #[test]
+    fn tiles_above_react_to_the_ground_below() {
+        let mut world = sandbox(3);
+        let pos = TilePos { x: 2, y: 2 };
+        world.set_tile(Layer::Decoration, pos, tile::FLOWERS);
+        world.set_tile(Layer::Ground, pos, tile::WATER);
+        run(&mut world, 1);
+        assert_eq!(world.get_tile(Layer::Decoration, pos), Some(tile::EMPTY));
+    }
+}
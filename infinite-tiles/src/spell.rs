@@ -0,0 +1,246 @@
+// spell.rs
+use crate::clock::DayPhase;
+use std::collections::BTreeSet;
+
+/// Grammatical role a word plays inside an incantation
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum WordCategory {
+    Element,  // What the spell is made of (fire, water, ...)
+    Verb,     // What the spell does (bolt, burst, ...)
+    Modifier, // How strongly or widely it does it
+}
+
+/// A single word of the spell language
+#[derive(Debug, PartialEq, Eq)]
+pub struct Word {
+    pub text: &'static str,
+    pub category: WordCategory,
+}
+
+impl Word {
+    const fn new(text: &'static str, category: WordCategory) -> Self {
+        Word { text, category }
+    }
+}
+
+/// Every word that can be discovered by experimentation
+pub const VOCABULARY: &[Word] = &[
+    // Elements
+    Word::new("fire", WordCategory::Element),
+    Word::new("water", WordCategory::Element),
+    Word::new("ice", WordCategory::Element),
+    Word::new("earth", WordCategory::Element),
+    Word::new("lightning", WordCategory::Element),
+    Word::new("light", WordCategory::Element),
+    // Verbs
+    Word::new("bolt", WordCategory::Verb),
+    Word::new("burst", WordCategory::Verb),
+    Word::new("cone", WordCategory::Verb),
+    Word::new("line", WordCategory::Verb),
+    Word::new("wall", WordCategory::Verb),
+    Word::new("reveal", WordCategory::Verb),
+    // Modifiers
+    Word::new("greater", WordCategory::Modifier),
+    Word::new("lesser", WordCategory::Modifier),
+    Word::new("swift", WordCategory::Modifier),
+    Word::new("wide", WordCategory::Modifier),
+];
+
+/// Words every new wizard starts out knowing
+pub const STARTING_WORDS: &[&str] = &["fire", "bolt"];
+
+/// Looks up a vocabulary word by its exact text
+pub fn find_word(text: &str) -> Option<&'static Word> {
+    VOCABULARY.iter().find(|word| word.text == text)
+}
+
+/// Substance a spell is made of
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Element {
+    Fire,
+    Water,
+    Ice,
+    Earth,
+    Lightning,
+    Light,
+}
+
+impl Element {
+    pub const ALL: [Element; 6] = [
+        Element::Fire,
+        Element::Water,
+        Element::Ice,
+        Element::Earth,
+        Element::Lightning,
+        Element::Light,
+    ];
+
+    fn from_word(text: &str) -> Option<Self> {
+        match text {
+            "fire" => Some(Element::Fire),
+            "water" => Some(Element::Water),
+            "ice" => Some(Element::Ice),
+            "earth" => Some(Element::Earth),
+            "lightning" => Some(Element::Lightning),
+            "light" => Some(Element::Light),
+            _ => None,
+        }
+    }
+}
+
+/// Form a spell takes when cast
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Verb {
+    Bolt,
+    Burst,
+    Cone,
+    Line,
+    Wall,
+    Reveal,
+}
+
+impl Verb {
+    fn from_word(text: &str) -> Option<Self> {
+        match text {
+            "bolt" => Some(Verb::Bolt),
+            "burst" => Some(Verb::Burst),
+            "cone" => Some(Verb::Cone),
+            "line" => Some(Verb::Line),
+            "wall" => Some(Verb::Wall),
+            "reveal" => Some(Verb::Reveal),
+            _ => None,
+        }
+    }
+
+    /// Parts of the day the verb answers in, None if it works any time
+    pub fn phases(self) -> Option<&'static [DayPhase]> {
+        match self {
+            // The hidden only shows itself when the sun is down or just rising
+            Verb::Reveal => Some(&[DayPhase::Night, DayPhase::Dawn]),
+            _ => None,
+        }
+    }
+}
+
+/// How closely a typed word matched the vocabulary word it was resolved to
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum MatchQuality {
+    Exact,   // Typed correctly
+    Weak,    // Near miss: the spell works but at reduced strength
+    Chaotic, // Far miss: the spell works but unpredictably
+}
+
+impl MatchQuality {
+    /// Strength multiplier contributed by a word of this quality
+    pub fn potency(self) -> f32 {
+        match self {
+            MatchQuality::Exact => 1.0,
+            MatchQuality::Weak => 0.8,
+            MatchQuality::Chaotic => 0.6,
+        }
+    }
+}
+
+/// A resolved word inside a cast spell
+#[derive(Debug)]
+pub struct SpellWord {
+    pub word: &'static Word,
+    pub quality: MatchQuality,
+}
+
+/// A spell assembled from the recognized words of an incantation
+#[derive(Debug, Default)]
+pub struct Spell {
+    pub words: Vec<SpellWord>,
+}
+
+impl Spell {
+    /// Combined strength multiplier of all words
+    pub fn potency(&self) -> f32 {
+        self.words.iter().map(|w| w.quality.potency()).product()
+    }
+
+    /// First element named in the incantation
+    pub fn element(&self) -> Option<Element> {
+        self.words
+            .iter()
+            .find_map(|w| Element::from_word(w.word.text))
+    }
+
+    /// First verb named in the incantation
+    pub fn verb(&self) -> Option<Verb> {
+        self.words.iter().find_map(|w| Verb::from_word(w.word.text))
+    }
+
+    /// Whether a modifier word appears in the incantation
+    pub fn has_modifier(&self, text: &str) -> bool {
+        self.words
+            .iter()
+            .any(|w| w.word.category == WordCategory::Modifier && w.word.text == text)
+    }
+
+    /// A single chaotic word makes the whole spell unpredictable
+    pub fn is_chaotic(&self) -> bool {
+        self.words
+            .iter()
+            .any(|w| w.quality == MatchQuality::Chaotic)
+    }
+
+    pub fn describe(&self) -> String {
+        let words: Vec<&str> = self.words.iter().map(|w| w.word.text).collect();
+        let mut description = words.join(" ");
+        if self.is_chaotic() {
+            description.push_str(" (chaotic)");
+        } else if self.potency() < 1.0 {
+            description.push_str(" (weakened)");
+        }
+        description
+    }
+}
+
+/// Words the wizard has discovered so far
+pub struct Spellbook {
+    discovered: BTreeSet<&'static str>,
+}
+
+impl Spellbook {
+    pub fn new() -> Self {
+        let mut spellbook = Spellbook {
+            discovered: BTreeSet::new(),
+        };
+        for text in STARTING_WORDS {
+            spellbook.discover(text);
+        }
+        spellbook
+    }
+
+    /// Records a word as discovered, returns true if it was new
+    pub fn discover(&mut self, text: &str) -> bool {
+        match find_word(text) {
+            Some(word) => self.discovered.insert(word.text),
+            None => false,
+        }
+    }
+
+    pub fn is_discovered(&self, text: &str) -> bool {
+        self.discovered.contains(text)
+    }
+
+    /// Discovered words in alphabetical order
+    pub fn words(&self) -> impl Iterator<Item = &'static str> + '_ {
+        self.discovered.iter().copied()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn potency_drops_with_match_quality() {
+        let exact = MatchQuality::Exact.potency();
+        let weak = MatchQuality::Weak.potency();
+        let chaotic = MatchQuality::Chaotic.potency();
+        assert!(exact > weak && weak > chaotic);
+    }
+}
@@ -0,0 +1,345 @@
+// state.rs
+// which screen owns the frame, and the transitions between them
+use crate::config;
+use crate::game::Game;
+use crate::gamepad::Gamepad;
+use crate::input::{Action, InputFrame, InputMap};
+use crate::menu::{self, ControlsMenu, MenuAction, TitleMenu};
+use crate::replay::{self, Replay};
+use macroquad::prelude::*;
+use std::collections::VecDeque;
+use std::path::Path;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum State {
+    Title,
+    Playing,   // Walking around, the world runs
+    Paused,    // World frozen under the pause menu
+    Controls,  // Rebinding keys, opened from the pause menu
+    Spellbook, // World frozen under the list of discovered words
+    Console,   // The world runs but the keyboard belongs to the incantation
+    GameOver,  // The wizard died, the world is frozen
+    Map,       // World frozen under the full-screen map
+}
+
+/// Routes input to the current state and runs the enter/exit hooks on transitions
+pub struct StateMachine {
+    state: State,
+    title: TitleMenu,
+    controls: ControlsMenu,
+    input: InputMap,
+    gamepad: Gamepad,
+    game: Option<Game>,
+    recording: Option<Replay>, // Every tick of the current game, if it was started fresh
+    playback: VecDeque<InputFrame>, // Recorded ticks still to be played instead of live input
+    playback_input: InputMap,  // Controls the playback was recorded with
+    pending: InputFrame,       // Live input the next tick hasn't seen yet
+    lag: f32,                  // Seconds the world is behind the clock, less than a tick
+}
+
+impl StateMachine {
+    /// Starts on the title screen, or straight into a replay given as `--replay <file>`
+    pub async fn new() -> Self {
+        let mut machine = StateMachine {
+            state: State::Title,
+            title: TitleMenu::new(),
+            controls: ControlsMenu::new(),
+            input: load_input_map(),
+            gamepad: Gamepad::new(),
+            game: None,
+            recording: None,
+            playback: VecDeque::new(),
+            playback_input: InputMap::new(),
+            pending: InputFrame::default(),
+            lag: 0.0,
+        };
+
+        let args: Vec<String> = std::env::args().collect();
+        if let [_, flag, path] = args.as_slice()
+            && flag == "--replay"
+        {
+            match Replay::read(Path::new(path)) {
+                Ok(replay) => {
+                    machine.game = Some(Game::new(None, replay.seed).await);
+                    machine.playback = replay.frames.into();
+                    machine.playback_input = replay.input;
+                    machine.state = State::Playing;
+                }
+                Err(err) => eprintln!("failed to read replay {}: {}", path, err),
+            }
+        }
+        machine
+    }
+
+    /// Handles input, advances the game if the state allows it, and draws
+    pub async fn frame(&mut self) {
+        self.gamepad.poll();
+        let next = match self.state {
+            State::Title => self.update_title().await,
+            State::Controls => self
+                .controls
+                .update(&mut self.input, self.gamepad.stick())
+                .then_some(State::Paused),
+            _ => self.update_in_game().await,
+        };
+
+        self.draw();
+
+        if let Some(next) = next {
+            self.transition(next);
+        }
+    }
+
+    fn transition(&mut self, next: State) {
+        if next == self.state {
+            return;
+        }
+        self.on_exit(self.state);
+        self.state = next;
+        self.on_enter(next);
+    }
+
+    fn on_enter(&mut self, state: State) {
+        match state {
+            State::Title => {
+                // Leaving a game drops it, saving is up to whoever left
+                self.game = None;
+                self.recording = None;
+                self.playback.clear();
+                self.pending = InputFrame::default();
+                self.lag = 0.0;
+                self.title.refresh();
+            }
+            State::Controls => self.controls = ControlsMenu::new(),
+            State::Map => {
+                if let Some(game) = &mut self.game {
+                    game.open_map();
+                }
+            }
+            State::Playing
+            | State::Paused
+            | State::Spellbook
+            | State::Console
+            | State::GameOver => {}
+        }
+    }
+
+    fn on_exit(&mut self, state: State) {
+        match state {
+            // Dying mid-sentence leaves nothing half typed
+            State::Console => {
+                if let Some(game) = &mut self.game {
+                    game.close_console();
+                }
+            }
+            State::Controls => {
+                if let Err(err) = self.input.save(Path::new(config::INPUT_CONFIG_PATH)) {
+                    eprintln!("failed to save controls: {}", err);
+                }
+                // A recording plays back through the controls it started with
+                if self
+                    .recording
+                    .as_ref()
+                    .is_some_and(|recording| recording.input != self.input)
+                {
+                    eprintln!("controls changed, the rest of this game isn't recorded");
+                    self.recording = None;
+                }
+            }
+            State::Title
+            | State::Playing
+            | State::Paused
+            | State::Spellbook
+            | State::GameOver
+            | State::Map => {}
+        }
+    }
+
+    async fn update_title(&mut self) -> Option<State> {
+        match self.title.update()? {
+            MenuAction::NewGame(slot) => {
+                let seed = (miniquad::date::now() * 1000.0) as u64;
+                let game = Game::new(Some(slot), seed).await;
+                // Claim the slot right away so it shows up on the title screen
+                save_or_report(&game);
+                self.game = Some(game);
+                // Only fresh games can be replayed, a loaded one depends on its save
+                self.recording = Some(Replay::new(seed, self.input.clone()));
+                Some(State::Playing)
+            }
+            MenuAction::Continue(slot) => match Game::load(slot).await {
+                Ok(game) => {
+                    self.game = Some(game);
+                    Some(State::Playing)
+                }
+                Err(err) => {
+                    eprintln!("failed to load slot {}: {}", slot + 1, err);
+                    None
+                }
+            },
+        }
+    }
+
+    /// Input handling for the states that have a game running underneath
+    async fn update_in_game(&mut self) -> Option<State> {
+        let Some(game) = &mut self.game else {
+            // Every other state needs a game, fall back to the title if it's gone
+            return Some(State::Title);
+        };
+        let live = game.capture_input(self.gamepad.stick());
+        let input = &self.input;
+
+        match self.state {
+            State::Playing | State::Console => {
+                if self.state == State::Playing {
+                    if input.is_pressed(Action::Cancel, &live) {
+                        return Some(State::Paused);
+                    }
+                    if input.is_pressed(Action::OpenSpellbook, &live) {
+                        return Some(State::Spellbook);
+                    }
+                    if input.is_pressed(Action::OpenMap, &live) {
+                        return Some(State::Map);
+                    }
+                    if input.is_pressed(Action::QuickSave, &live) {
+                        save_or_report(game);
+                    }
+                    if input.is_pressed(Action::KeepReplay, &live) {
+                        keep_recording(self.recording.as_ref());
+                    }
+                    if input.is_pressed(Action::QuickLoad, &live)
+                        && let Some(slot) = game.slot()
+                    {
+                        match Game::load(slot).await {
+                            Ok(loaded) => *game = loaded,
+                            Err(err) => eprintln!("failed to load: {}", err),
+                        }
+                        // The reloaded game no longer follows from the recorded seed
+                        self.recording = None;
+                    }
+                }
+
+                // The world runs at a fixed rate however fast frames are drawn,
+                // input from frames between two ticks goes to the next one
+                let step = 1.0 / config::TICKS_PER_SECOND;
+                // A replay doesn't listen, live input would only pile up until it ends
+                if self.playback.is_empty() {
+                    self.pending.merge(live);
+                }
+                self.lag =
+                    (self.lag + get_frame_time()).min(config::MAX_TICKS_PER_FRAME as f32 * step);
+                while self.lag >= step && !game.is_over() {
+                    self.lag -= step;
+                    // A replay feeds its recorded ticks until it runs out
+                    let replaying = !self.playback.is_empty();
+                    let (frame, input) = match self.playback.pop_front() {
+                        Some(frame) => (frame, &self.playback_input),
+                        None => {
+                            let held = self.pending.held();
+                            (std::mem::replace(&mut self.pending, held), input)
+                        }
+                    };
+                    if let Some(recording) = &mut self.recording {
+                        recording.record(frame.clone());
+                    }
+                    game.tick(input, &frame);
+                    if replaying && self.playback.is_empty() {
+                        eprintln!("replay finished: {:?}", game.snapshot());
+                    }
+                }
+                game.update_effects(get_frame_time());
+                game.load_textures().await;
+
+                Some(if game.is_over() {
+                    State::GameOver
+                } else if game.is_typing() {
+                    State::Console
+                } else {
+                    State::Playing
+                })
+            }
+            State::Paused => {
+                if input.is_pressed(Action::Cancel, &live) {
+                    Some(State::Playing)
+                } else if input.is_pressed(Action::OpenControls, &live) {
+                    Some(State::Controls)
+                } else if input.is_pressed(Action::SaveGame, &live) {
+                    save_or_report(game);
+                    None
+                } else if input.is_pressed(Action::QuitToTitle, &live) {
+                    save_or_report(game);
+                    Some(State::Title)
+                } else {
+                    None
+                }
+            }
+            State::Spellbook => (input.is_pressed(Action::Cancel, &live)
+                || input.is_pressed(Action::OpenSpellbook, &live))
+            .then_some(State::Playing),
+            State::Map => {
+                if input.is_pressed(Action::Cancel, &live)
+                    || input.is_pressed(Action::OpenMap, &live)
+                {
+                    Some(State::Playing)
+                } else {
+                    game.update_map(input, &live);
+                    None
+                }
+            }
+            State::GameOver => input
+                .is_pressed(Action::Dismiss, &live)
+                .then_some(State::Title),
+            State::Title | State::Controls => None,
+        }
+    }
+
+    fn draw(&self) {
+        let Some(game) = &self.game else {
+            self.title.draw();
+            return;
+        };
+
+        // The map covers the whole screen
+        if self.state == State::Map {
+            game.draw_map(&self.input);
+            return;
+        }
+
+        // The world stays on screen under every overlay
+        game.draw(&self.input);
+        match self.state {
+            State::Paused => menu::draw_pause_overlay(&self.input),
+            State::Controls => self.controls.draw(&self.input),
+            State::Spellbook => game.draw_spellbook(&self.input),
+            State::GameOver => menu::draw_game_over_overlay(&self.input),
+            State::Title | State::Playing | State::Console | State::Map => {}
+        }
+    }
+}
+
+fn save_or_report(game: &Game) {
+    if let Err(err) = game.save() {
+        eprintln!("failed to save: {}", err);
+    }
+}
+
+/// Writes the recording so far, e.g. right after running into a bug
+fn keep_recording(recording: Option<&Replay>) {
+    let Some(recording) = recording else {
+        eprintln!("nothing to keep, only games started from the title screen are recorded");
+        return;
+    };
+    let path = replay::new_replay_path();
+    match recording.write(&path) {
+        Ok(()) => eprintln!("replay written to {}", path.display()),
+        Err(err) => eprintln!("failed to write replay: {}", err),
+    }
+}
+
+/// Falls back to the default controls if the config can't be read
+fn load_input_map() -> InputMap {
+    InputMap::load(Path::new(config::INPUT_CONFIG_PATH)).unwrap_or_else(|err| {
+        eprintln!("failed to read controls, using the defaults: {}", err);
+        InputMap::new()
+    })
+}
@@ -1,14 +1,22 @@
+use crate::animation::{self, SpriteSheet};
+use crate::autotile;
+use crate::clock::WorldClock;
 use crate::config;
+use crate::tile::{TILES, TileId, tile_def};
 use crate::types::LoadPriority;
 use macroquad::prelude::*;
-use std::collections::{HashMap, VecDeque};
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::sync::Arc;
 
+/// Number of procedural placeholder textures, indexed by `TileDef::texture` and
+/// the frames of `TileDef::animation`
+const PROCEDURAL_TEXTURE_COUNT: u32 = 15;
+
 /// Represents the current storage location of a texture
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub enum TextureLocation {
-    GPU,     // Texture is loaded in GPU memory (VRAM)
-    RAM,     // Texture is in system RAM but not GPU
+    Gpu,     // Texture is loaded in GPU memory (VRAM)
+    Ram,     // Texture is in system RAM but not GPU
     Storage, // Texture is on disk/storage only
 }
 
@@ -30,8 +38,12 @@ pub struct TextureManager {
     priority_queue: VecDeque<String>,
     // Metadata about all known textures
     metadata: HashMap<String, TextureMetadata>,
+    // Textures that stay on the GPU for good, never queued for eviction
+    pinned: HashSet<String>,
     // Procedurally generated textures for testing/placeholder
     procedural_textures: Vec<Arc<Texture2D>>,
+    // Edges tiles spill onto their neighbors, by tile and edge mask
+    transition_textures: HashMap<(TileId, u8), Arc<Texture2D>>,
     // Current memory usage
     gpu_memory_used: usize,
     ram_memory_used: usize,
@@ -45,27 +57,46 @@ impl TextureManager {
             ram_cache: HashMap::with_capacity(config::MAX_RAM_TEXTURES),
             priority_queue: VecDeque::new(),
             metadata: HashMap::new(),
+            pinned: HashSet::new(),
             procedural_textures: Vec::new(),
+            transition_textures: HashMap::new(),
             gpu_memory_used: 0,
             ram_memory_used: 0,
         };
 
         // Generate our procedural textures
-        for i in 0..4 {
+        for i in 0..PROCEDURAL_TEXTURE_COUNT {
             let texture = manager.generate_procedural_texture(i).await;
             // Estimate size (64x64 RGBA = 16384 bytes)
+            let tile_size = config::TILE_SIZE as u32;
             manager.update_metadata(
                 &format!("procedural_{}", i),
                 TextureMetadata {
-                    location: TextureLocation::GPU,
+                    location: TextureLocation::Gpu,
                     last_used: get_time(),
                     loading: false,
-                    size: 16384,
+                    size: Self::estimate_texture_size(tile_size, tile_size, 4),
                 },
             );
             manager.procedural_textures.push(Arc::new(texture));
         }
 
+        // Every edge of every tile that spills onto another
+        for (id, def) in TILES.iter().enumerate() {
+            let spills = TILES
+                .iter()
+                .any(|other| other.blend > 0 && other.blend < def.blend);
+            if !spills {
+                continue;
+            }
+            for mask in 1..16 {
+                let texture = Self::generate_transition_texture(id as TileId, mask);
+                manager
+                    .transition_textures
+                    .insert((id as TileId, mask), Arc::new(texture));
+            }
+        }
+
         manager
     }
 
@@ -80,18 +111,23 @@ impl TextureManager {
     }
 
     async fn generate_procedural_texture(&self, id: u32) -> Texture2D {
+        Texture2D::from_image(&Self::procedural_image(id))
+    }
+
+    fn procedural_image(id: u32) -> Image {
         let tile_size: u32 = config::TILE_SIZE
             .try_into()
             .expect("TILE_SIZE should fit into u32");
 
         let tile_size_u16 = u16::try_from(tile_size).expect("Tile size should fit in u16");
         let mut image = Image::gen_image_color(tile_size_u16, tile_size_u16, WHITE);
-        match id % 4 {
-            0 => {
-                // Checkerboard pattern
+        match id % PROCEDURAL_TEXTURE_COUNT {
+            0 | 7..=9 => {
+                // Checkerboard pattern, drifting sideways over the water frames
+                let shift = if id == 0 { 0 } else { (id - 6) * 4 };
                 for y in 0..tile_size {
                     for x in 0..tile_size {
-                        if (x / 8 + y / 8) % 2 == 0 {
+                        if ((x + shift) / 8 + y / 8) % 2 == 0 {
                             image.set_pixel(x, y, BLUE);
                         }
                     }
@@ -137,19 +173,220 @@ impl TextureManager {
                     }
                 }
             }
+            4 | 10 | 11 => {
+                // Flickering flames pattern, the tongues move along over the fire frames
+                let phase = if id == 4 { 0.0 } else { (id - 9) as f32 * 2.0 };
+                image = Image::gen_image_color(tile_size_u16, tile_size_u16, ORANGE);
+                for y in 0..tile_size {
+                    for x in 0..tile_size {
+                        let flame = ((x as f32 / 6.0 + phase).sin() * 8.0 + 16.0) as u32;
+                        if y % 32 < flame {
+                            image.set_pixel(x, y, RED);
+                        }
+                    }
+                }
+            }
+            5 => {
+                // Speckled ash pattern
+                image = Image::gen_image_color(tile_size_u16, tile_size_u16, DARKGRAY);
+                for y in 0..tile_size {
+                    for x in 0..tile_size {
+                        if (x * 7 + y * 13) % 11 == 0 {
+                            image.set_pixel(x, y, GRAY);
+                        }
+                    }
+                }
+            }
+            6 => {
+                // Cracked ice pattern
+                image = Image::gen_image_color(tile_size_u16, tile_size_u16, SKYBLUE);
+                for y in 0..tile_size {
+                    for x in 0..tile_size {
+                        if x == y || x + y == tile_size / 2 || (x + 2 * y) % 48 == 0 {
+                            image.set_pixel(x, y, WHITE);
+                        }
+                    }
+                }
+            }
+            12 => {
+                // Scattered flowers, clear so the ground shows through
+                image = Image::gen_image_color(tile_size_u16, tile_size_u16, BLANK);
+                let petals = [PINK, YELLOW, VIOLET];
+                for y in 0..tile_size {
+                    for x in 0..tile_size {
+                        let (cell_x, cell_y) = (x / 16, y / 16);
+                        let (center_x, center_y) = (cell_x * 16 + 8, cell_y * 16 + 8);
+                        let dx = x.abs_diff(center_x + (cell_y * 5) % 6);
+                        let dy = y.abs_diff(center_y + (cell_x * 3) % 6);
+                        if dx + dy < 3 {
+                            let petal = petals[((cell_x + cell_y * 2) % 3) as usize];
+                            image.set_pixel(x, y, petal);
+                        }
+                    }
+                }
+            }
+            13 => {
+                // Tree, a trunk under a round crown
+                image = Image::gen_image_color(tile_size_u16, tile_size_u16, BLANK);
+                let size = tile_size as f32;
+                for y in 0..tile_size {
+                    for x in 0..tile_size {
+                        let (fx, fy) = (x as f32 / size, y as f32 / size);
+                        let crown = (fx - 0.5).powi(2) + (fy - 0.4).powi(2);
+                        if crown < 0.1 {
+                            let shade = if crown < 0.03 && fx < 0.5 {
+                                GREEN
+                            } else {
+                                DARKGREEN
+                            };
+                            image.set_pixel(x, y, shade);
+                        } else if (fx - 0.5).abs() < 0.07 && fy > 0.6 && fy < 0.95 {
+                            image.set_pixel(x, y, BROWN);
+                        }
+                    }
+                }
+            }
+            14 => {
+                // Frost, a pale film with crystals running through it
+                image = Image::gen_image_color(
+                    tile_size_u16,
+                    tile_size_u16,
+                    Color::new(0.85, 0.95, 1.0, 0.4),
+                );
+                for y in 0..tile_size {
+                    for x in 0..tile_size {
+                        if (x + y) % 16 == 0 || x.abs_diff(y) % 24 == 0 {
+                            image.set_pixel(x, y, Color::new(1.0, 1.0, 1.0, 0.8));
+                        }
+                    }
+                }
+            }
             _ => unreachable!(),
         }
 
+        image
+    }
+
+    /// Draws the edge a tile spills over the sides of its neighbor in `mask`, a
+    /// wavy band cut out of the tile's own texture with the rest left transparent
+    fn generate_transition_texture(tile: TileId, mask: u8) -> Texture2D {
+        let source = Self::procedural_image(tile_def(tile).texture);
+        let tile_size = config::TILE_SIZE as u32;
+        let mut image = Image::gen_image_color(tile_size as u16, tile_size as u16, BLANK);
+        let last = tile_size - 1;
+        for y in 0..tile_size {
+            for x in 0..tile_size {
+                // Distance into the tile from each side, and how far along that side
+                let sides = [
+                    (autotile::NORTH, y, x),
+                    (autotile::EAST, last - x, y),
+                    (autotile::SOUTH, last - y, x),
+                    (autotile::WEST, x, y),
+                ];
+                let covered = sides.iter().any(|&(bit, depth, along)| {
+                    let reach = tile_size as f32 / 5.0 + (along as f32 * 0.4).sin() * 4.0;
+                    mask & bit != 0 && (depth as f32) < reach
+                });
+                if covered {
+                    image.set_pixel(x, y, source.get_pixel(x, y));
+                }
+            }
+        }
         Texture2D::from_image(&image)
     }
 
-    /// Gets a texture for a specific world position
-    pub fn get_texture(&self, x: i32, y: i32) -> Arc<Texture2D> {
-        // For testing, we'll use procedural textures based on position
-        let index = ((x.abs() + y.abs()) % 4) as usize;
+    /// Draws every frame of a sprite sheet, a placeholder until there are real assets.
+    /// Shapes are laid out in fractions of a frame so any frame size works.
+    fn generate_sprite_sheet(sheet: &SpriteSheet) -> Texture2D {
+        let size = sheet.frame_size;
+        let mut image = Image::gen_image_color(
+            (sheet.columns() * size) as u16,
+            (sheet.clips.len() as u32 * size) as u16,
+            BLANK,
+        );
+        let wizard = sheet.texture == animation::WIZARD_SHEET.texture;
+
+        for (row, clip) in sheet.clips.iter().enumerate() {
+            for frame in 0..clip.frames {
+                let mut frame_image = FrameImage {
+                    image: &mut image,
+                    origin: (frame * size, row as u32 * size),
+                    size,
+                };
+                let walking = clip.name.starts_with("walk");
+                let casting = clip.name == "cast";
+                // Feet swing back and forth, the body bobs on every other frame
+                let step = if walking {
+                    [-1.0, 0.0, 1.0, 0.0][frame as usize % 4]
+                } else {
+                    0.0
+                };
+                let bob = if frame % 2 == 0 { 0.0 } else { 0.03 };
+                // Which way the face looks, none when walking away
+                let eyes: &[f32] = match clip.name {
+                    "walk_up" => &[],
+                    "walk_left" => &[-0.08],
+                    "walk_right" => &[0.08],
+                    _ => &[-0.06, 0.06],
+                };
+
+                if wizard {
+                    frame_image.fill_rect(0.36 + step * 0.05, 0.8, 0.1, 0.15, DARKBROWN);
+                    frame_image.fill_rect(0.54 - step * 0.05, 0.8, 0.1, 0.15, DARKBROWN);
+                    frame_image.fill_rect(0.3, 0.45 + bob, 0.4, 0.4, BLUE);
+                    frame_image.fill_ellipse(0.5, 0.36 + bob, 0.14, 0.14, BEIGE);
+                    // Pointed hat, narrowing towards the tip
+                    for i in 0..8 {
+                        let t = i as f32 / 8.0;
+                        let half = 0.2 * (1.0 - t);
+                        frame_image.fill_rect(
+                            0.5 - half,
+                            0.26 - t * 0.22 + bob,
+                            half * 2.0,
+                            0.03,
+                            DARKBLUE,
+                        );
+                    }
+                    for eye in eyes {
+                        frame_image.fill_rect(0.48 + eye, 0.38 + bob, 0.04, 0.04, BLACK);
+                    }
+                    if casting {
+                        // Staff raised, its tip glowing brighter every frame
+                        frame_image.fill_rect(0.76, 0.12, 0.04, 0.68, BROWN);
+                        let glow = 0.04 + 0.03 * frame as f32;
+                        frame_image.fill_ellipse(0.78, 0.12, glow, glow, YELLOW);
+                    } else {
+                        frame_image.fill_rect(0.76, 0.35 + bob, 0.04, 0.6, BROWN);
+                    }
+                } else {
+                    let squash = if walking && frame % 2 == 0 { 0.04 } else { 0.0 };
+                    let color = if casting { RED } else { MAROON };
+                    frame_image.fill_ellipse(0.5, 0.6 + bob, 0.36 + squash, 0.3 - squash, color);
+                    for eye in eyes {
+                        frame_image.fill_ellipse(0.5 + eye * 1.5, 0.52 + bob, 0.06, 0.06, WHITE);
+                    }
+                }
+            }
+        }
+
+        let texture = Texture2D::from_image(&image);
+        texture.set_filter(FilterMode::Nearest);
+        texture
+    }
+
+    /// Gets the texture a tile type is drawn with at a moment in the world
+    pub fn get_tile_texture(&self, tile: TileId, clock: WorldClock) -> Arc<Texture2D> {
+        // For testing, every tile type maps onto one of the procedural textures
+        let index = tile_def(tile).texture_at(clock) as usize % self.procedural_textures.len();
         Arc::clone(&self.procedural_textures[index])
     }
 
+    /// Gets the edge a tile draws over the sides of a neighbor in `mask`, None for
+    /// tiles that don't spill onto others
+    pub fn get_transition_texture(&self, tile: TileId, mask: u8) -> Option<Arc<Texture2D>> {
+        self.transition_textures.get(&(tile, mask)).cloned()
+    }
+
     /// Ensures a texture is loaded with the appropriate priority
     pub async fn ensure_loaded(
         &mut self,
@@ -184,7 +421,7 @@ impl TextureManager {
 
             // Update metadata
             if let Some(metadata) = self.metadata.get_mut(id) {
-                metadata.location = TextureLocation::GPU;
+                metadata.location = TextureLocation::Gpu;
                 metadata.loading = false;
             }
 
@@ -209,7 +446,7 @@ impl TextureManager {
 
                     // Update metadata after loading
                     if let Some(metadata) = self.metadata.get_mut(id) {
-                        metadata.location = TextureLocation::GPU;
+                        metadata.location = TextureLocation::Gpu;
                         metadata.loading = false;
                     }
 
@@ -225,9 +462,25 @@ impl TextureManager {
             None
         }
     }
+
+    /// A texture that is already on the GPU, nothing is loaded
+    pub fn loaded(&self, id: &str) -> Option<&Texture2D> {
+        self.gpu_cache.get(id).map(Arc::as_ref)
+    }
+
+    /// Keeps a texture on the GPU for good once it is loaded, e.g. sprite sheets
+    /// that are needed wherever the wizard goes
+    pub fn pin(&mut self, id: &str) {
+        self.priority_queue.retain(|queued_id| queued_id != id);
+        self.pinned.insert(id.to_string());
+    }
+
     /// Updates the priority of a texture in the queue
     fn update_priority(&mut self, id: &str, high_priority: bool) {
         self.priority_queue.retain(|queued_id| queued_id != id);
+        if self.pinned.contains(id) {
+            return;
+        }
         if high_priority {
             self.priority_queue.push_front(id.to_string());
         } else {
@@ -241,11 +494,11 @@ impl TextureManager {
             if let Some(texture) = self.gpu_cache.remove(&id) {
                 // Get texture data and convert to bytes
                 let image = Arc::as_ref(&texture).get_texture_data();
-                let bytes: Vec<u8> = image.bytes.into();
+                let bytes: Vec<u8> = image.bytes;
 
                 // Update metadata
                 if let Some(metadata) = self.metadata.get_mut(&id) {
-                    metadata.location = TextureLocation::RAM;
+                    metadata.location = TextureLocation::Ram;
                     self.ram_memory_used += metadata.size;
                     self.gpu_memory_used -= metadata.size;
                 }
@@ -267,10 +520,27 @@ impl TextureManager {
     }
 
     /// Loads a texture from storage (simulated for now)
-    async fn load_from_storage(&mut self, _id: &str) -> Option<Texture2D> {
+    async fn load_from_storage(&mut self, id: &str) -> Option<Texture2D> {
         // Simulate loading delay
         next_frame().await;
 
+        if let Some(sheet) = animation::sheet_by_texture(id) {
+            let texture = Self::generate_sprite_sheet(sheet);
+            let size =
+                Self::estimate_texture_size(texture.width() as u32, texture.height() as u32, 4);
+            self.update_metadata(
+                id,
+                TextureMetadata {
+                    location: TextureLocation::Storage,
+                    last_used: get_time(),
+                    loading: true,
+                    size,
+                },
+            );
+            self.gpu_memory_used += size;
+            return Some(texture);
+        }
+
         // For now, return a dummy texture
         // In a real implementation, this would load from disk
         Some(self.generate_procedural_texture(0).await)
@@ -280,26 +550,40 @@ impl TextureManager {
     pub fn get_memory_stats(&self) -> (usize, usize) {
         (self.gpu_memory_used, self.ram_memory_used)
     }
+}
 
-    /// Cleans up unused textures
-    pub fn cleanup_unused(&mut self) {
-        let current_time = get_time();
-        let timeout = 30.0; // 30 seconds unused = cleanup
+/// One frame of a sprite sheet being drawn, positions and sizes are fractions of the frame
+struct FrameImage<'a> {
+    image: &'a mut Image,
+    origin: (u32, u32), // Top-left pixel of the frame in the sheet
+    size: u32,
+}
 
-        self.gpu_cache.retain(|id, _| {
-            if let Some(metadata) = self.metadata.get(id) {
-                current_time - metadata.last_used < timeout
-            } else {
-                false
-            }
+impl FrameImage<'_> {
+    fn fill_rect(&mut self, x: f32, y: f32, width: f32, height: f32, color: Color) {
+        self.fill(color, |px, py| {
+            px >= x && px < x + width && py >= y && py < y + height
         });
+    }
 
-        self.ram_cache.retain(|id, _| {
-            if let Some(metadata) = self.metadata.get(id) {
-                current_time - metadata.last_used < timeout
-            } else {
-                false
-            }
+    fn fill_ellipse(&mut self, x: f32, y: f32, radius_x: f32, radius_y: f32, color: Color) {
+        self.fill(color, |px, py| {
+            let dx = (px - x) / radius_x;
+            let dy = (py - y) / radius_y;
+            dx * dx + dy * dy <= 1.0
         });
     }
+
+    /// Paints the pixels whose centers `inside` accepts
+    fn fill(&mut self, color: Color, inside: impl Fn(f32, f32) -> bool) {
+        let size = self.size as f32;
+        for y in 0..self.size {
+            for x in 0..self.size {
+                if inside((x as f32 + 0.5) / size, (y as f32 + 0.5) / size) {
+                    self.image
+                        .set_pixel(self.origin.0 + x, self.origin.1 + y, color);
+                }
+            }
+        }
+    }
 }
@@ -0,0 +1,286 @@
+// tile.rs
+// registry of tile types and their properties
+use crate::clock::WorldClock;
+use crate::config;
+use crate::particles::{self, EmitterId};
+use macroquad::prelude::*;
+
+/// Index into the tile registry, this is what chunks store
+pub type TileId = u32;
+
+pub const GRASS: TileId = 0;
+pub const WATER: TileId = 1;
+pub const STONE: TileId = 2;
+pub const SAND: TileId = 3;
+pub const FIRE: TileId = 4;
+pub const ASH: TileId = 5;
+pub const ICE: TileId = 6;
+pub const EMPTY: TileId = 7; // Nothing, what the layers above the ground mostly hold
+pub const FLOWERS: TileId = 8;
+pub const TREE: TileId = 9;
+pub const FROST: TileId = 10;
+
+/// Chunks keep a grid of tiles per layer, drawn in this order
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Layer {
+    Ground,     // Terrain, every tile has some
+    Decoration, // Small things lying on the ground, e.g. flowers
+    Object,     // Things standing on the ground, e.g. trees
+    Overlay,    // Left on top by spells, e.g. frost
+}
+
+impl Layer {
+    pub const COUNT: usize = 4;
+    pub const ALL: [Layer; Layer::COUNT] = [
+        Layer::Ground,
+        Layer::Decoration,
+        Layer::Object,
+        Layer::Overlay,
+    ];
+}
+
+/// What has to be around a tile for one of its reactions to happen
+#[derive(Clone, Copy)]
+pub enum Trigger {
+    /// Happens on its own
+    Always,
+    /// One of the four neighbors is this tile
+    Neighbor(TileId),
+    /// It is raining on the tile's region
+    Rain,
+    /// The ground under a tile of an upper layer is this tile
+    Below(TileId),
+}
+
+/// A tile turning into another tile of its layer during the cellular simulation
+pub struct Reaction {
+    pub trigger: Trigger,
+    pub becomes: TileId,
+    pub chance: f32, // Per simulation tick, once triggered
+}
+
+/// Textures a tile cycles through, e.g. rippling water
+pub struct TileAnimation {
+    pub frames: &'static [u32], // Procedural texture indices, in order
+    pub frame_seconds: f32,
+}
+
+/// Properties shared by every tile of one type
+pub struct TileDef {
+    pub name: &'static str,
+    pub solid: bool,                      // Blocks movement and projectiles
+    pub move_cost: f32,                   // Effort to walk across, 1 for open ground and never less
+    pub texture: u32,                     // Procedural texture index
+    pub animation: Option<TileAnimation>, // Replaces `texture` while the tile is drawn
+    pub height: u8,                       // Liquids flow from higher to lower tiles
+    pub flow: f32,                        // Liquids: chance per tick to run into a lower neighbor
+    pub flammable: bool,                  // Fire spells set it alight
+    pub damage: f32,                      // Hurts the wizard standing on it, per second
+    pub blend: u8,                        // Edges spill onto lower blends, 0 for hard edges
+    pub map_color: Color,                 // Pixel color on the minimap and world map
+    pub glow: i32,                        // Radius in tiles of the light it gives off, 0 for none
+    pub particles: Option<EmitterId>,     // Thrown off while the tile is on screen
+    pub reactions: &'static [Reaction],   // Checked in order, first success wins
+}
+
+impl TileDef {
+    const DEFAULT: TileDef = TileDef {
+        name: "",
+        solid: false,
+        move_cost: 1.0,
+        texture: 0,
+        animation: None,
+        height: 1,
+        flow: 0.0,
+        flammable: false,
+        damage: 0.0,
+        blend: 0,
+        map_color: MAGENTA,
+        glow: 0,
+        particles: None,
+        reactions: &[],
+    };
+
+    /// Texture to draw the tile with at a moment in the world. Frames follow the
+    /// world clock, so every tile of a type is on the same frame.
+    pub fn texture_at(&self, clock: WorldClock) -> u32 {
+        let Some(animation) = &self.animation else {
+            return self.texture;
+        };
+        let frame_ticks = ((animation.frame_seconds * config::TICKS_PER_SECOND) as u64).max(1);
+        let frame = clock.ticks() / frame_ticks % animation.frames.len() as u64;
+        animation.frames[frame as usize]
+    }
+}
+
+/// All tile types, indexed by TileId
+pub const TILES: &[TileDef] = &[
+    TileDef {
+        name: "grass",
+        texture: 1,
+        blend: 3,
+        map_color: DARKGREEN,
+        height: 2,
+        flammable: true,
+        reactions: &[Reaction {
+            trigger: Trigger::Neighbor(FIRE),
+            becomes: FIRE,
+            chance: 0.25,
+        }],
+        ..TileDef::DEFAULT
+    },
+    TileDef {
+        name: "water",
+        move_cost: 4.0,
+        texture: 0,
+        animation: Some(TileAnimation {
+            frames: &[0, 7, 8, 9],
+            frame_seconds: 0.25,
+        }),
+        blend: 1,
+        map_color: BLUE,
+        flow: 0.2,
+        ..TileDef::DEFAULT
+    },
+    TileDef {
+        name: "stone",
+        solid: true,
+        texture: 2,
+        map_color: GRAY,
+        height: 3,
+        ..TileDef::DEFAULT
+    },
+    TileDef {
+        name: "sand",
+        move_cost: 1.5,
+        texture: 3,
+        blend: 2,
+        map_color: BEIGE,
+        ..TileDef::DEFAULT
+    },
+    TileDef {
+        name: "fire",
+        move_cost: 6.0,
+        texture: 4,
+        animation: Some(TileAnimation {
+            frames: &[4, 10, 11],
+            frame_seconds: 0.12,
+        }),
+        map_color: ORANGE,
+        glow: 4,
+        particles: Some(particles::EMBERS),
+        height: 2,
+        damage: 20.0,
+        reactions: &[
+            Reaction {
+                trigger: Trigger::Neighbor(WATER),
+                becomes: ASH,
+                chance: 1.0,
+            },
+            Reaction {
+                trigger: Trigger::Rain,
+                becomes: ASH,
+                chance: 0.3,
+            },
+            Reaction {
+                trigger: Trigger::Always,
+                becomes: ASH,
+                chance: 0.08,
+            },
+        ],
+        ..TileDef::DEFAULT
+    },
+    TileDef {
+        name: "ash",
+        move_cost: 1.2,
+        texture: 5,
+        map_color: DARKGRAY,
+        // Burnt ground sits low, nearby water pools into it
+        height: 0,
+        reactions: &[Reaction {
+            trigger: Trigger::Always,
+            becomes: GRASS,
+            chance: 0.002,
+        }],
+        ..TileDef::DEFAULT
+    },
+    TileDef {
+        name: "ice",
+        move_cost: 2.0,
+        texture: 6,
+        map_color: SKYBLUE,
+        reactions: &[Reaction {
+            trigger: Trigger::Neighbor(FIRE),
+            becomes: WATER,
+            chance: 0.5,
+        }],
+        ..TileDef::DEFAULT
+    },
+    TileDef {
+        name: "empty",
+        map_color: BLANK,
+        ..TileDef::DEFAULT
+    },
+    TileDef {
+        name: "flowers",
+        texture: 12,
+        // Too small to see from afar, the map shows the ground
+        map_color: BLANK,
+        flammable: true,
+        reactions: &[
+            Reaction {
+                trigger: Trigger::Below(FIRE),
+                becomes: EMPTY,
+                chance: 1.0,
+            },
+            Reaction {
+                trigger: Trigger::Below(WATER),
+                becomes: EMPTY,
+                chance: 1.0,
+            },
+        ],
+        ..TileDef::DEFAULT
+    },
+    TileDef {
+        name: "tree",
+        solid: true,
+        texture: 13,
+        map_color: Color::new(0.0, 0.3, 0.1, 1.0),
+        flammable: true,
+        reactions: &[Reaction {
+            trigger: Trigger::Below(FIRE),
+            becomes: EMPTY,
+            chance: 0.1,
+        }],
+        ..TileDef::DEFAULT
+    },
+    TileDef {
+        name: "frost",
+        move_cost: 1.5,
+        texture: 14,
+        map_color: Color::new(0.9, 0.95, 1.0, 1.0),
+        reactions: &[
+            Reaction {
+                trigger: Trigger::Below(FIRE),
+                becomes: EMPTY,
+                chance: 1.0,
+            },
+            Reaction {
+                trigger: Trigger::Rain,
+                becomes: EMPTY,
+                chance: 0.3,
+            },
+            Reaction {
+                trigger: Trigger::Always,
+                becomes: EMPTY,
+                chance: 0.01,
+            },
+        ],
+        ..TileDef::DEFAULT
+    },
+];
+
+/// Looks up the definition of a tile, unknown ids fall back to the first tile
+pub fn tile_def(id: TileId) -> &'static TileDef {
+    TILES.get(id as usize).unwrap_or(&TILES[0])
+}
@@ -0,0 +1,225 @@
+// tokenizer.rs
+// splits the incantation buffer into classified words while it is being typed
+use crate::fuzzy::{FuzzyMatcher, WordMatch};
+use crate::spell::{Spellbook, WordCategory};
+
+/// Highlighting class of a token
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum TokenCategory {
+    Element,
+    Verb,
+    Modifier,
+    Unknown,
+}
+
+/// A word of the incantation buffer, as a byte range into the source
+#[derive(Clone, Copy, Debug)]
+pub struct Token {
+    pub start: usize,
+    pub end: usize,
+    pub word_match: WordMatch,
+}
+
+impl Token {
+    pub fn category(&self) -> TokenCategory {
+        match self.word_match {
+            WordMatch::Known(word, _) => match word.category {
+                WordCategory::Element => TokenCategory::Element,
+                WordCategory::Verb => TokenCategory::Verb,
+                WordCategory::Modifier => TokenCategory::Modifier,
+            },
+            WordMatch::Unknown => TokenCategory::Unknown,
+        }
+    }
+}
+
+/// Incremental tokenizer: only the tokens touched by an edit are rescanned,
+/// so typing at the end of a long incantation re-matches a single word.
+pub struct Tokenizer {
+    source: String,
+    tokens: Vec<Token>,
+}
+
+impl Tokenizer {
+    pub fn new() -> Self {
+        Tokenizer {
+            source: String::new(),
+            tokens: Vec::new(),
+        }
+    }
+
+    /// Brings the tokens in line with the new buffer contents
+    pub fn update(&mut self, input: &str, matcher: &FuzzyMatcher) {
+        if input == self.source {
+            return;
+        }
+
+        // A token survives if the character right after it is unchanged,
+        // otherwise the edit may have extended or split it
+        let unchanged = common_prefix_len(&self.source, input);
+        let keep = self
+            .tokens
+            .iter()
+            .take_while(|token| token.end < unchanged)
+            .count();
+        self.tokens.truncate(keep);
+
+        let resume = self.tokens.last().map_or(0, |token| token.end);
+        self.scan(input, resume, matcher);
+
+        self.source.clear();
+        self.source.push_str(input);
+    }
+
+    fn scan(&mut self, input: &str, from: usize, matcher: &FuzzyMatcher) {
+        let mut start = None;
+        for (i, c) in input[from..].char_indices() {
+            let i = from + i;
+            match (c.is_whitespace(), start) {
+                (false, None) => start = Some(i),
+                (true, Some(s)) => {
+                    self.push_token(input, s, i, matcher);
+                    start = None;
+                }
+                _ => {}
+            }
+        }
+        if let Some(s) = start {
+            self.push_token(input, s, input.len(), matcher);
+        }
+    }
+
+    fn push_token(&mut self, input: &str, start: usize, end: usize, matcher: &FuzzyMatcher) {
+        self.tokens.push(Token {
+            start,
+            end,
+            word_match: matcher.resolve(&input[start..end]),
+        });
+    }
+
+    pub fn tokens(&self) -> &[Token] {
+        &self.tokens
+    }
+
+    pub fn text(&self, token: &Token) -> &str {
+        &self.source[token.start..token.end]
+    }
+
+    /// The word still being typed, if the buffer doesn't end in whitespace
+    pub fn partial(&self) -> Option<&str> {
+        self.tokens
+            .last()
+            .filter(|token| token.end == self.source.len())
+            .map(|token| self.text(token))
+    }
+
+    /// Remaining characters of the first discovered word extending the partial word
+    pub fn completion(&self, spellbook: &Spellbook) -> Option<&'static str> {
+        let partial = self.partial()?;
+        spellbook
+            .words()
+            .find(|word| word.len() > partial.len() && word.starts_with(partial))
+            .map(|word| &word[partial.len()..])
+    }
+}
+
+fn common_prefix_len(a: &str, b: &str) -> usize {
+    a.char_indices()
+        .zip(b.chars())
+        .find(|&((_, ca), cb)| ca != cb)
+        .map_or(a.len().min(b.len()), |((i, _), _)| i)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn words(tokenizer: &Tokenizer) -> Vec<(&str, TokenCategory)> {
+        tokenizer
+            .tokens()
+            .iter()
+            .map(|token| (tokenizer.text(token), token.category()))
+            .collect()
+    }
+
+    /// Feeds every buffer in turn, checking each time that the incremental tokens
+    /// match tokenizing the buffer from scratch
+    fn type_out(buffers: &[&str]) -> Tokenizer {
+        let matcher = FuzzyMatcher::new();
+        let mut tokenizer = Tokenizer::new();
+        for buffer in buffers {
+            tokenizer.update(buffer, &matcher);
+            let mut fresh = Tokenizer::new();
+            fresh.update(buffer, &matcher);
+            let spans = |t: &Tokenizer| -> Vec<(usize, usize, WordMatch)> {
+                t.tokens()
+                    .iter()
+                    .map(|token| (token.start, token.end, token.word_match))
+                    .collect()
+            };
+            assert_eq!(spans(&tokenizer), spans(&fresh), "after {:?}", buffer);
+        }
+        tokenizer
+    }
+
+    #[test]
+    fn typing_at_the_end_extends_the_last_word() {
+        let tokenizer = type_out(&["f", "fi", "fir", "fire", "fire ", "fire b", "fire bolt"]);
+        assert_eq!(
+            words(&tokenizer),
+            [
+                ("fire", TokenCategory::Element),
+                ("bolt", TokenCategory::Verb)
+            ]
+        );
+        assert_eq!(tokenizer.partial(), Some("bolt"));
+    }
+
+    #[test]
+    fn edits_in_the_middle_rescan_the_words_after_them() {
+        let tokenizer = type_out(&["fire bolt wide", "fire blt wide", "ice blt wide"]);
+        assert_eq!(
+            words(&tokenizer),
+            [
+                ("ice", TokenCategory::Element),
+                // A near miss still reads as the word it misses
+                ("blt", TokenCategory::Verb),
+                ("wide", TokenCategory::Modifier),
+            ]
+        );
+    }
+
+    #[test]
+    fn deleting_shortens_or_removes_words() {
+        let tokenizer = type_out(&["fire bolt", "fire bol", "fire ", "fire", "", "water"]);
+        assert_eq!(words(&tokenizer), [("water", TokenCategory::Element)]);
+
+        let tokenizer = type_out(&["greater fire bolt", "greater bolt"]);
+        assert_eq!(
+            words(&tokenizer),
+            [
+                ("greater", TokenCategory::Modifier),
+                ("bolt", TokenCategory::Verb)
+            ]
+        );
+    }
+
+    #[test]
+    fn spaces_split_and_join_words() {
+        let tokenizer = type_out(&["firebolt", "fire bolt", "firebolt"]);
+        assert_eq!(words(&tokenizer), [("firebolt", TokenCategory::Unknown)]);
+
+        let tokenizer = type_out(&["fire  bolt", "fire bolt", "fire bo lt"]);
+        assert_eq!(
+            words(&tokenizer),
+            [
+                ("fire", TokenCategory::Element),
+                ("bo", TokenCategory::Unknown),
+                ("lt", TokenCategory::Unknown),
+            ]
+        );
+        assert_eq!(tokenizer.partial(), Some("lt"));
+        let tokenizer = type_out(&["fire bolt", "fire bolt "]);
+        assert_eq!(tokenizer.partial(), None);
+    }
+}
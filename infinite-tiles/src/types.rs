@@ -1,5 +1,7 @@
+use crate::config;
 use macroquad::prelude::*;
 use std::hash::Hash;
+use std::ops::{Add, AddAssign, Sub};
 
 #[derive(PartialEq, Clone, Copy)]
 pub enum LoadPriority {
@@ -8,7 +10,7 @@ pub enum LoadPriority {
     Cache,
 }
 
-#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+#[derive(Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Default, Debug)]
 pub struct ChunkPos {
     pub x: i32,
     pub y: i32,
@@ -20,20 +22,561 @@ pub struct TilePos {
     pub y: i32,
 }
 
+/// Tiles from `min` to `max`, both corners included. Empty when `min` is past
+/// `max` on either axis.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct TileRect {
+    pub min: TilePos,
+    pub max: TilePos,
+}
+
+/// A point in the world, as the chunk it lies in and the offset in pixels from
+/// that chunk's top-left corner. A single f32 position would lose whole pixels a
+/// few million tiles out, the offset never outgrows a chunk so it stays precise
+/// however far the wizard walks.
+#[derive(Clone, Copy, PartialEq, Default, Debug)]
+pub struct WorldPos {
+    pub chunk: ChunkPos,
+    pub offset: Vec2, // In 0..CHUNK_PIXELS on both axes
+}
+
+/// Column and row of a tile inside its chunk, both in 0..CHUNK_SIZE
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct LocalTilePos {
+    pub x: i32,
+    pub y: i32,
+}
+
 impl ChunkPos {
-    pub fn from_world_pos(x: f32, y: f32) -> Self {
-        ChunkPos {
-            x: (x / (crate::config::CHUNK_SIZE * crate::config::TILE_SIZE) as f32).floor() as i32,
-            y: (y / (crate::config::CHUNK_SIZE * crate::config::TILE_SIZE) as f32).floor() as i32,
+    /// Top-left tile of this chunk
+    pub fn origin_tile(self) -> TilePos {
+        TilePos {
+            x: self.x * config::CHUNK_SIZE,
+            y: self.y * config::CHUNK_SIZE,
+        }
+    }
+
+    /// Tile at a column and row of this chunk
+    pub fn tile(self, local: LocalTilePos) -> TilePos {
+        let origin = self.origin_tile();
+        TilePos {
+            x: origin.x + local.x,
+            y: origin.y + local.y,
         }
     }
+
+    /// Every tile of this chunk
+    pub fn tile_rect(self) -> TileRect {
+        let last = config::CHUNK_SIZE - 1;
+        TileRect {
+            min: self.origin_tile(),
+            max: self.tile(LocalTilePos { x: last, y: last }),
+        }
+    }
+
+    /// Top-left corner of the chunk
+    pub fn world_pos(self) -> WorldPos {
+        WorldPos {
+            chunk: self,
+            offset: Vec2::ZERO,
+        }
+    }
+
+    /// Area the chunk covers, as its top-left corner and its size in pixels
+    pub fn world_rect(self) -> (WorldPos, Vec2) {
+        (self.world_pos(), Vec2::splat(config::CHUNK_PIXELS))
+    }
+
+    /// Chunk `dx` columns and `dy` rows away, None if any of its tiles would be
+    /// past the edge of the `i32` grid
+    pub fn offset(self, dx: i32, dy: i32) -> Option<ChunkPos> {
+        let fits = |chunk: i32| {
+            chunk
+                .checked_mul(config::CHUNK_SIZE)
+                .and_then(|origin| origin.checked_add(config::CHUNK_SIZE - 1))
+                .is_some()
+        };
+        let chunk = ChunkPos {
+            x: self.x.checked_add(dx)?,
+            y: self.y.checked_add(dy)?,
+        };
+        (fits(chunk.x) && fits(chunk.y)).then_some(chunk)
+    }
 }
 
 impl TilePos {
-    pub fn from_world_pos(x: f32, y: f32) -> Self {
-        TilePos {
-            x: (x / crate::config::TILE_SIZE as f32).floor() as i32,
-            y: (y / crate::config::TILE_SIZE as f32).floor() as i32,
+    /// Top-left corner of the tile
+    pub fn world_pos(self) -> WorldPos {
+        let (chunk, local) = self.to_chunk_local();
+        WorldPos {
+            chunk,
+            offset: Vec2::new(local.x as f32, local.y as f32) * config::TILE_SIZE as f32,
+        }
+    }
+
+    /// Area the tile covers, as its top-left corner and its size in pixels
+    pub fn world_rect(self) -> (WorldPos, Vec2) {
+        (self.world_pos(), Vec2::splat(config::TILE_SIZE as f32))
+    }
+
+    /// Middle of the tile
+    pub fn world_center(self) -> WorldPos {
+        let (top_left, size) = self.world_rect();
+        top_left + size / 2.0
+    }
+
+    /// Tile `dx` columns and `dy` rows away, None past the edge of the `i32` grid
+    pub fn offset(self, dx: i32, dy: i32) -> Option<TilePos> {
+        Some(TilePos {
+            x: self.x.checked_add(dx)?,
+            y: self.y.checked_add(dy)?,
+        })
+    }
+
+    /// The four tiles sharing a side with this one, north, east, south then west.
+    /// Tiles on the edge of the `i32` grid have fewer.
+    pub fn neighbors(self) -> impl Iterator<Item = TilePos> {
+        [(0, -1), (1, 0), (0, 1), (-1, 0)]
+            .into_iter()
+            .filter_map(move |(dx, dy)| self.offset(dx, dy))
+    }
+
+    /// Straight-line distance in tiles between the tile centers
+    pub fn distance(self, other: TilePos) -> f32 {
+        let dx = (other.x as f64) - (self.x as f64);
+        let dy = (other.y as f64) - (self.y as f64);
+        dx.hypot(dy) as f32
+    }
+
+    /// Chunk containing this tile, and the tile's column and row inside it
+    pub fn to_chunk_local(self) -> (ChunkPos, LocalTilePos) {
+        let size = config::CHUNK_SIZE;
+        (
+            ChunkPos {
+                x: self.x.div_euclid(size),
+                y: self.y.div_euclid(size),
+            },
+            LocalTilePos {
+                x: self.x.rem_euclid(size),
+                y: self.y.rem_euclid(size),
+            },
+        )
+    }
+}
+
+impl TileRect {
+    /// Tiles touched by the area from `start` to `end`
+    pub fn covering(start: WorldPos, end: WorldPos) -> Self {
+        TileRect {
+            min: start.tile(),
+            max: end.tile(),
         }
     }
+
+    /// Grown by `by` tiles on every side, stopping at the edge of the `i32` grid
+    pub fn expand(self, by: i32) -> Self {
+        TileRect {
+            min: TilePos {
+                x: self.min.x.saturating_sub(by),
+                y: self.min.y.saturating_sub(by),
+            },
+            max: TilePos {
+                x: self.max.x.saturating_add(by),
+                y: self.max.y.saturating_add(by),
+            },
+        }
+    }
+
+    /// Every tile in the rect, row by row
+    pub fn iter(self) -> impl Iterator<Item = TilePos> {
+        (self.min.y..=self.max.y)
+            .flat_map(move |y| (self.min.x..=self.max.x).map(move |x| TilePos { x, y }))
+    }
+}
+
+impl WorldPos {
+    /// Moves whole chunks out of `offset` until it lies inside `chunk`
+    pub fn new(chunk: ChunkPos, offset: Vec2) -> Self {
+        let carry = (offset / config::CHUNK_PIXELS).floor();
+        let mut pos = WorldPos {
+            chunk: ChunkPos {
+                x: chunk.x.saturating_add(carry.x as i32),
+                y: chunk.y.saturating_add(carry.y as i32),
+            },
+            offset: offset - carry * config::CHUNK_PIXELS,
+        };
+        // A hair below zero rounds up to a whole chunk, which belongs to the next one
+        if pos.offset.x >= config::CHUNK_PIXELS {
+            pos.offset.x = 0.0;
+            pos.chunk.x = pos.chunk.x.saturating_add(1);
+        }
+        if pos.offset.y >= config::CHUNK_PIXELS {
+            pos.offset.y = 0.0;
+            pos.chunk.y = pos.chunk.y.saturating_add(1);
+        }
+        pos
+    }
+
+    /// From pixels counted from the world origin, e.g. as saves store positions.
+    /// f64 holds them to a tiny fraction of a pixel even at the edge of the `i32` grid.
+    pub fn from_pixels(pixels: DVec2) -> Self {
+        let chunk = (pixels / config::CHUNK_PIXELS as f64).floor();
+        let offset = pixels - chunk * config::CHUNK_PIXELS as f64;
+        WorldPos::new(
+            ChunkPos {
+                x: chunk.x as i32,
+                y: chunk.y as i32,
+            },
+            offset.as_vec2(),
+        )
+    }
+
+    /// Pixels counted from the world origin
+    pub fn pixels(self) -> DVec2 {
+        let chunk = DVec2::new(self.chunk.x as f64, self.chunk.y as f64);
+        chunk * config::CHUNK_PIXELS as f64 + self.offset.as_dvec2()
+    }
+
+    /// Tile the point lies on
+    pub fn tile(self) -> TilePos {
+        let last = config::CHUNK_SIZE - 1;
+        let local = (self.offset / config::TILE_SIZE as f32).floor();
+        self.chunk.tile(LocalTilePos {
+            x: (local.x as i32).clamp(0, last),
+            y: (local.y as i32).clamp(0, last),
+        })
+    }
+}
+
+impl Add<Vec2> for WorldPos {
+    type Output = WorldPos;
+
+    fn add(self, delta: Vec2) -> WorldPos {
+        WorldPos::new(self.chunk, self.offset + delta)
+    }
+}
+
+impl AddAssign<Vec2> for WorldPos {
+    fn add_assign(&mut self, delta: Vec2) {
+        *self = *self + delta;
+    }
+}
+
+impl Sub<Vec2> for WorldPos {
+    type Output = WorldPos;
+
+    fn sub(self, delta: Vec2) -> WorldPos {
+        WorldPos::new(self.chunk, self.offset - delta)
+    }
+}
+
+/// Pixels from `other` to `self`, precise as long as the two are close
+impl Sub for WorldPos {
+    type Output = Vec2;
+
+    fn sub(self, other: WorldPos) -> Vec2 {
+        let chunks = Vec2::new(
+            (self.chunk.x as i64 - other.chunk.x as i64) as f32,
+            (self.chunk.y as i64 - other.chunk.y as i64) as f32,
+        );
+        chunks * config::CHUNK_PIXELS + (self.offset - other.offset)
+    }
+}
+
+impl LocalTilePos {
+    /// Position in a chunk's flat, row-major tile arrays
+    pub fn index(self) -> usize {
+        (self.y * config::CHUNK_SIZE + self.x) as usize
+    }
+
+    pub fn from_index(index: usize) -> Self {
+        let size = config::CHUNK_SIZE as usize;
+        LocalTilePos {
+            x: (index % size) as i32,
+            y: (index / size) as i32,
+        }
+    }
+
+    /// Every tile of a chunk, row by row
+    pub fn all() -> impl Iterator<Item = LocalTilePos> {
+        (0..config::CHUNK_AREA).map(Self::from_index)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hash;
+    use config::CHUNK_SIZE;
+
+    /// Values where conversions tend to go wrong: zero, chunk borders on both
+    /// sides of it and the `i32` limits
+    const EDGES: [i32; 11] = [
+        i32::MIN,
+        i32::MIN + 1,
+        -CHUNK_SIZE - 1,
+        -CHUNK_SIZE,
+        -1,
+        0,
+        1,
+        CHUNK_SIZE - 1,
+        CHUNK_SIZE,
+        i32::MAX - 1,
+        i32::MAX,
+    ];
+
+    /// Every pair of edge values, then pseudo-random tiles spread over the whole grid
+    fn sample_tiles() -> Vec<TilePos> {
+        let edges = EDGES
+            .iter()
+            .flat_map(|&y| EDGES.iter().map(move |&x| TilePos { x, y }));
+        let random = (0..2000).map(|i| TilePos {
+            x: hash::hash1(7, i, 1) as i32,
+            y: hash::hash1(7, i, 2) as i32,
+        });
+        edges.chain(random).collect()
+    }
+
+    #[test]
+    fn chunk_and_local_position_give_back_the_tile() {
+        for tile in sample_tiles() {
+            let (chunk, local) = tile.to_chunk_local();
+            assert!((0..CHUNK_SIZE).contains(&local.x), "{:?}", tile);
+            assert!((0..CHUNK_SIZE).contains(&local.y), "{:?}", tile);
+            assert_eq!(chunk.tile(local), tile);
+            assert_eq!(LocalTilePos::from_index(local.index()), local);
+        }
+    }
+
+    #[test]
+    fn negative_tiles_round_toward_negative_chunks() {
+        let (chunk, local) = TilePos {
+            x: -1,
+            y: -CHUNK_SIZE,
+        }
+        .to_chunk_local();
+        assert_eq!(chunk, ChunkPos { x: -1, y: -1 });
+        assert_eq!(
+            local,
+            LocalTilePos {
+                x: CHUNK_SIZE - 1,
+                y: 0
+            }
+        );
+
+        let (chunk, _) = TilePos {
+            x: i32::MIN,
+            y: i32::MAX,
+        }
+        .to_chunk_local();
+        assert_eq!(chunk.origin_tile().x, i32::MIN);
+        assert_eq!(chunk.tile_rect().max.y, i32::MAX);
+    }
+
+    #[test]
+    fn chunk_tile_rect_holds_exactly_its_tiles() {
+        for tile in sample_tiles() {
+            let (chunk, _) = tile.to_chunk_local();
+            let rect = chunk.tile_rect();
+            assert!(rect.min.x <= tile.x && tile.x <= rect.max.x);
+            assert!(rect.min.y <= tile.y && tile.y <= rect.max.y);
+
+            let tiles: Vec<TilePos> = rect.iter().collect();
+            assert_eq!(tiles.len(), config::CHUNK_AREA);
+            assert!(tiles.iter().all(|t| t.to_chunk_local().0 == chunk));
+            let locals: Vec<LocalTilePos> = LocalTilePos::all().collect();
+            assert_eq!(
+                tiles,
+                locals.iter().map(|&l| chunk.tile(l)).collect::<Vec<_>>()
+            );
+        }
+    }
+
+    #[test]
+    fn world_positions_map_back_to_their_tile_and_chunk() {
+        for tile in sample_tiles() {
+            let (chunk, _) = tile.to_chunk_local();
+            for pos in [tile.world_pos(), tile.world_center()] {
+                assert_eq!(pos.tile(), tile);
+                assert_eq!(pos.chunk, chunk);
+            }
+            assert_eq!(tile.world_center() - tile.world_pos(), Vec2::splat(32.0));
+        }
+    }
+
+    #[test]
+    fn tile_rects_tile_their_chunk_rect() {
+        for tile in sample_tiles() {
+            let (chunk, local) = tile.to_chunk_local();
+            let (chunk_corner, chunk_size) = chunk.world_rect();
+            let (corner, size) = tile.world_rect();
+            assert_eq!(size, Vec2::splat(config::TILE_SIZE as f32));
+            assert_eq!(chunk_size, Vec2::splat(config::CHUNK_PIXELS));
+
+            // The tile sits at its local column and row, and ends inside the chunk
+            let inside = corner - chunk_corner;
+            assert_eq!(inside, Vec2::new(local.x as f32, local.y as f32) * size);
+            assert!((inside + size).cmple(chunk_size).all(), "{:?}", tile);
+            assert_eq!((corner + size / 2.0).tile(), tile);
+        }
+    }
+
+    #[test]
+    fn pixels_round_trip_to_well_under_a_pixel() {
+        for tile in sample_tiles() {
+            let pos = tile.world_center() + Vec2::new(0.37, -0.11);
+            let back = WorldPos::from_pixels(pos.pixels());
+            assert_eq!(back.chunk, pos.chunk);
+            assert!(
+                (back.offset - pos.offset).abs().max_element() < 1e-3,
+                "{:?}",
+                pos
+            );
+        }
+    }
+
+    #[test]
+    fn offsets_stay_inside_their_chunk() {
+        let size = config::CHUNK_PIXELS;
+        let deltas = [
+            Vec2::ZERO,
+            Vec2::splat(-1e-6),
+            Vec2::splat(size),
+            Vec2::new(-size, size - 1e-3),
+            Vec2::new(3.5 * size, -2.25 * size),
+        ];
+        for tile in sample_tiles().into_iter().step_by(7) {
+            let start = tile.world_pos();
+            for delta in deltas {
+                let pos = start + delta;
+                assert!((0.0..size).contains(&pos.offset.x), "{:?}", pos);
+                assert!((0.0..size).contains(&pos.offset.y), "{:?}", pos);
+                // Rounded only as much as the delta itself is
+                assert!(((pos - start) - delta).abs().max_element() < 1e-3);
+            }
+        }
+    }
+
+    #[test]
+    fn walking_millions_of_tiles_out_is_as_precise_as_at_home() {
+        // As plain f32 pixels this far out positions come in steps of 32, a step
+        // of the wizard wouldn't move them at all
+        let start = TilePos {
+            x: 5_000_000,
+            y: -3_000_000,
+        }
+        .world_center();
+        let step = Vec2::new(config::PLAYER_SPEED, 0.0);
+        let mut pos = start;
+        for _ in 0..400 {
+            pos += step;
+        }
+        assert_eq!(pos - start, step * 400.0);
+        assert_eq!(pos.chunk, start.chunk.offset(1, 0).unwrap());
+        for _ in 0..400 {
+            pos += -step;
+        }
+        assert_eq!(pos, start);
+    }
+
+    #[test]
+    fn neighbors_are_one_step_away_and_symmetric() {
+        for tile in sample_tiles() {
+            let neighbors: Vec<TilePos> = tile.neighbors().collect();
+            let on_x_limit = tile.x == i32::MIN || tile.x == i32::MAX;
+            let on_y_limit = tile.y == i32::MIN || tile.y == i32::MAX;
+            assert_eq!(
+                neighbors.len(),
+                4 - on_x_limit as usize - on_y_limit as usize
+            );
+            for neighbor in neighbors {
+                assert_eq!(tile.distance(neighbor), 1.0);
+                assert!(neighbor.neighbors().any(|back| back == tile));
+            }
+        }
+    }
+
+    #[test]
+    fn chunk_offsets_stop_at_the_last_whole_chunk() {
+        for tile in sample_tiles() {
+            let (chunk, _) = tile.to_chunk_local();
+            for (dx, dy) in [(0, -1), (1, 0), (0, 1), (-1, 0)] {
+                let past_edge = |tile: i32, step: i32| {
+                    (tile == i32::MIN && step < 0) || (tile == i32::MAX && step > 0)
+                };
+                let Some(neighbor) = chunk.offset(dx, dy) else {
+                    let edge = chunk.tile_rect();
+                    assert!(
+                        past_edge(edge.min.x, dx)
+                            || past_edge(edge.max.x, dx)
+                            || past_edge(edge.min.y, dy)
+                            || past_edge(edge.max.y, dy)
+                    );
+                    continue;
+                };
+                assert_eq!(neighbor.offset(-dx, -dy), Some(chunk));
+                assert!(neighbor.tile_rect().iter().next().is_some());
+            }
+        }
+    }
+
+    #[test]
+    fn distance_is_a_metric_even_across_the_whole_grid() {
+        let tiles = sample_tiles();
+        for pair in tiles.windows(3) {
+            let (a, b, c) = (pair[0], pair[1], pair[2]);
+            assert_eq!(a.distance(a), 0.0);
+            assert_eq!(a.distance(b), b.distance(a));
+            assert!(a.distance(b).is_finite());
+            // Rounded to f32, so allow for the last bit
+            assert!(a.distance(c) <= (a.distance(b) + b.distance(c)) * (1.0 + f32::EPSILON));
+        }
+        let corner = TilePos { x: i32::MIN, y: 0 }.distance(TilePos { x: i32::MAX, y: 0 });
+        assert_eq!(corner, u32::MAX as f32);
+    }
+
+    #[test]
+    fn rect_iteration_is_row_major_and_stops_at_the_limits() {
+        let rect = TileRect {
+            min: TilePos { x: -2, y: -1 },
+            max: TilePos { x: 0, y: 0 },
+        };
+        let tiles: Vec<(i32, i32)> = rect.iter().map(|t| (t.x, t.y)).collect();
+        assert_eq!(
+            tiles,
+            [(-2, -1), (-1, -1), (0, -1), (-2, 0), (-1, 0), (0, 0)]
+        );
+
+        let corner = TileRect {
+            min: TilePos {
+                x: i32::MAX - 1,
+                y: i32::MAX - 1,
+            },
+            max: TilePos {
+                x: i32::MAX,
+                y: i32::MAX,
+            },
+        };
+        assert_eq!(corner.iter().count(), 4);
+        assert_eq!(corner.expand(5).max, corner.max);
+        assert_eq!(corner.expand(5).iter().count(), 49);
+
+        let backwards = TileRect {
+            min: TilePos { x: 1, y: 0 },
+            max: TilePos { x: 0, y: 0 },
+        };
+        assert_eq!(backwards.iter().count(), 0);
+    }
+
+    #[test]
+    fn covering_a_world_area_includes_partly_covered_tiles() {
+        let size = config::TILE_SIZE as f32;
+        let origin = WorldPos::default();
+        let rect = TileRect::covering(
+            origin + Vec2::new(-0.5, 0.0),
+            origin + Vec2::new(size, size * 2.0 - 1.0),
+        );
+        assert_eq!(rect.min, TilePos { x: -1, y: 0 });
+        assert_eq!(rect.max, TilePos { x: 1, y: 1 });
+    }
 }
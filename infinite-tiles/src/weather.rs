@@ -0,0 +1,204 @@
+// weather.rs
+// regional weather worked out from the seed and the world clock, and how it looks
+use crate::biome::{self, Biome};
+use crate::camera::Camera;
+use crate::clock::{DayPhase, WorldClock};
+use crate::config;
+use crate::hash;
+use crate::types::ChunkPos;
+use macroquad::prelude::*;
+
+const WEATHER_SALT: u64 = 0x51;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Weather {
+    Clear,
+    Rain,
+    Snow,
+    Fog,
+    Storm,
+}
+
+impl Weather {
+    pub fn name(self) -> &'static str {
+        match self {
+            Weather::Clear => "clear",
+            Weather::Rain => "rain",
+            Weather::Snow => "snow",
+            Weather::Fog => "fog",
+            Weather::Storm => "storm",
+        }
+    }
+
+    /// Water falling from the sky, it puts out fires
+    pub fn is_wet(self) -> bool {
+        matches!(self, Weather::Rain | Weather::Storm)
+    }
+}
+
+/// Weather regions are squares of chunks that share a sky
+fn region(chunk: ChunkPos) -> (i32, i32) {
+    let size = config::WEATHER_REGION_CHUNKS;
+    (chunk.x.div_euclid(size), chunk.y.div_euclid(size))
+}
+
+/// The weather over a chunk. It changes every weather period and only depends on
+/// the seed and the clock, so replays see the same skies. A sky falls differently
+/// on each biome under it, e.g. as snow over the tundra.
+pub fn weather_at(seed: u64, chunk: ChunkPos, clock: WorldClock) -> Weather {
+    let (x, y) = region(chunk);
+    let period_ticks = (config::WEATHER_PERIOD_SECONDS * config::TICKS_PER_SECOND) as u64;
+    let period = clock.ticks() / period_ticks;
+    let roll = hash::unit(hash::hash2(seed ^ hash::mix(period), x, y, WEATHER_SALT));
+
+    // Mist gathers in the early morning wherever it isn't dry
+    let biome = biome::biome_at(seed, chunk);
+    if clock.phase() == DayPhase::Dawn && biome != Biome::Desert && roll < 0.3 {
+        return Weather::Fog;
+    }
+    match biome {
+        Biome::Meadow => match roll {
+            r if r < 0.55 => Weather::Clear,
+            r if r < 0.8 => Weather::Rain,
+            r if r < 0.9 => Weather::Fog,
+            _ => Weather::Storm,
+        },
+        Biome::Tundra => match roll {
+            r if r < 0.5 => Weather::Clear,
+            r if r < 0.85 => Weather::Snow,
+            _ => Weather::Fog,
+        },
+        Biome::Desert => match roll {
+            r if r < 0.85 => Weather::Clear,
+            _ => Weather::Storm,
+        },
+    }
+}
+
+/// Rain, snow or mist over the whole view. Only the looks depend on real time,
+/// the drops are placed by index so nothing has to be kept between frames.
+pub fn draw_weather(weather: Weather, camera: &Camera, time: f32) {
+    let size = camera.viewport_size;
+    // Drops drift with the world a little so walking through rain feels like it,
+    // in f64 since far from the origin f32 would only move them in steps
+    let scroll = camera.position.pixels() * 0.5;
+    let place = |index: u64, speed: Vec2| {
+        let start = Vec2::new(
+            hash::unit(hash::hash1(index, 0, WEATHER_SALT)),
+            hash::unit(hash::hash1(index, 1, WEATHER_SALT)),
+        ) * size;
+        let pos = (start + speed * time).as_dvec2() - scroll;
+        let size = size.as_dvec2();
+        Vec2::new(
+            pos.x.rem_euclid(size.x) as f32,
+            pos.y.rem_euclid(size.y) as f32,
+        )
+    };
+
+    match weather {
+        Weather::Clear => {}
+        Weather::Rain | Weather::Storm => {
+            let (drops, speed) = if weather == Weather::Storm {
+                (config::WEATHER_PARTICLES * 2, Vec2::new(-220.0, 900.0))
+            } else {
+                (config::WEATHER_PARTICLES, Vec2::new(-60.0, 700.0))
+            };
+            let streak = speed.normalize() * 14.0;
+            let color = Color::new(0.6, 0.7, 1.0, 0.6);
+            for index in 0..drops as u64 {
+                let pos = place(index, speed);
+                draw_line(pos.x, pos.y, pos.x + streak.x, pos.y + streak.y, 1.5, color);
+            }
+            // Lightning lights up the whole sky for a moment every few seconds
+            if weather == Weather::Storm && (time * 0.4).fract() < 0.02 {
+                draw_rectangle(0.0, 0.0, size.x, size.y, Color::new(1.0, 1.0, 0.9, 0.5));
+            }
+        }
+        Weather::Snow => {
+            for index in 0..config::WEATHER_PARTICLES as u64 {
+                let sway = (time * 1.5 + index as f32).sin() * 20.0;
+                let pos = place(index, Vec2::new(0.0, 60.0)) + Vec2::new(sway, 0.0);
+                draw_circle(pos.x, pos.y, 2.0, Color::new(1.0, 1.0, 1.0, 0.8));
+            }
+        }
+        Weather::Fog => {
+            draw_rectangle(0.0, 0.0, size.x, size.y, Color::new(0.8, 0.8, 0.85, 0.35));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn period_ticks() -> u64 {
+        (config::WEATHER_PERIOD_SECONDS * config::TICKS_PER_SECOND) as u64
+    }
+
+    /// Chunks on both sides of the axes, so negative regions are covered
+    fn chunks() -> impl Iterator<Item = ChunkPos> {
+        (-40..40).flat_map(|y| (-40..40).map(move |x| ChunkPos { x, y }))
+    }
+
+    #[test]
+    fn weather_only_depends_on_the_seed_and_the_clock() {
+        for ticks in [0, 1, 12_345, 9_999_999] {
+            let clock = WorldClock::from_ticks(ticks);
+            for chunk in chunks() {
+                assert_eq!(weather_at(3, chunk, clock), weather_at(3, chunk, clock));
+            }
+        }
+        // It holds for a whole period, then may turn
+        let start = WorldClock::from_ticks(period_ticks() * 40);
+        let end = WorldClock::from_ticks(period_ticks() * 41 - 1);
+        let next = WorldClock::from_ticks(period_ticks() * 41);
+        assert_eq!(start.phase(), end.phase());
+        for chunk in chunks() {
+            assert_eq!(weather_at(3, chunk, start), weather_at(3, chunk, end));
+        }
+        assert!(chunks().any(|chunk| weather_at(3, chunk, end) != weather_at(3, chunk, next)));
+    }
+
+    #[test]
+    fn regions_split_at_multiples_of_their_size_on_both_sides_of_zero() {
+        let size = config::WEATHER_REGION_CHUNKS;
+        let at = |x, y| region(ChunkPos { x, y });
+        assert_eq!(at(0, 0), (0, 0));
+        assert_eq!(at(size - 1, size - 1), (0, 0));
+        assert_eq!(at(-1, -1), (-1, -1));
+        assert_eq!(at(-size, -size), (-1, -1));
+        assert_eq!(at(-size - 1, size), (-2, 1));
+    }
+
+    #[test]
+    fn a_region_shares_its_sky_over_the_same_biome() {
+        let seed = 3;
+        for period in 0..50 {
+            let clock = WorldClock::from_ticks(period * period_ticks());
+            let mut skies = std::collections::HashMap::new();
+            for chunk in chunks() {
+                let key = (region(chunk), biome::biome_at(seed, chunk));
+                let weather = weather_at(seed, chunk, clock);
+                assert_eq!(*skies.entry(key).or_insert(weather), weather, "{:?}", chunk);
+            }
+        }
+    }
+
+    #[test]
+    fn each_biome_keeps_to_its_own_weather() {
+        let seed = 3;
+        for period in 0..100 {
+            let clock = WorldClock::from_ticks(period * period_ticks());
+            for chunk in chunks().step_by(7) {
+                let weather = weather_at(seed, chunk, clock);
+                match biome::biome_at(seed, chunk) {
+                    Biome::Meadow => assert_ne!(weather, Weather::Snow),
+                    Biome::Tundra => assert!(!weather.is_wet(), "{:?}", weather),
+                    Biome::Desert => {
+                        assert!(matches!(weather, Weather::Clear | Weather::Storm))
+                    }
+                }
+            }
+        }
+    }
+}
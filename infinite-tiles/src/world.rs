@@ -1,145 +1,1312 @@
 // world.rs
+use crate::animation::{self, Animator, SpriteSheet};
+use crate::autotile;
+use crate::biome;
 use crate::camera::Camera;
+use crate::casting::{AreaEffect, CastPlan, element_color, element_glow, element_residue};
+use crate::clock::{DayPhase, WorldClock};
 use crate::config;
+use crate::entity::{Ai, Entity, EntityId, EntityStore, Health, Impact, Projectile, Sprite};
+use crate::fog::{self, Explored, FOG_COLOR};
+use crate::grid::TileGrid;
+use crate::hash;
+use crate::lighting::{LightMap, PointLight};
+use crate::pathfinding::{Path, PathCache, Planner};
+use crate::raycast::{self, RayHit};
+use crate::shape::Shape;
 use crate::texture::TextureManager;
-use crate::types::{ChunkPos, LoadPriority};
+use crate::tile::{self, Layer, TileId};
+use crate::types::{ChunkPos, LoadPriority, LocalTilePos, TilePos, TileRect, WorldPos};
+use crate::weather::{self, Weather};
 use macroquad::prelude::*;
-use std::collections::HashMap;
+use std::borrow::Cow;
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
 use std::sync::Arc;
 
+// Salts keeping the different uses of the seed independent
+const TERRAIN_SALT: u64 = 0x10;
+const GROWTH_SALT: u64 = 0x11;
+const CREATURE_SALT: u64 = 0x20;
+const ENTITY_SALT: u64 = 0x30;
+
+#[derive(Clone)]
 pub struct Chunk {
     pub pos: ChunkPos,
-    tiles: Vec<Vec<u32>>, // Stores tile IDs
+    layers: [TileGrid; Layer::COUNT], // In `Layer::ALL` order
+    modified: bool,                   // Differs from what the seed generates
+    explored: Explored,               // Tiles the wizard has seen
+    revision: u32,                    // Bumped on every change, lets renderers cache the chunk
+    edges: [u8; config::CHUNK_AREA],  // Autotile masks of the ground, worked out on load
 }
 
 impl Chunk {
-    fn new(pos: ChunkPos) -> Self {
-        let mut tiles = Vec::with_capacity(config::CHUNK_SIZE as usize);
-        for _ in 0..config::CHUNK_SIZE {
-            let row = (0..config::CHUNK_SIZE)
-                .map(|_| rand::gen_range::<u32>(0, 4))
-                .collect();
-            tiles.push(row);
+    /// Generates the chunk's terrain and what grows on it, the same seed always
+    /// gives the same tiles
+    fn generate(pos: ChunkPos, seed: u64) -> Self {
+        let biome = biome::biome_at(seed, pos);
+        let ground = TileGrid::from_fn(|local| {
+            let tile = pos.tile(local);
+            let roll = hash::hash2(seed, tile.x, tile.y, TERRAIN_SALT);
+            biome.ground(hash::unit(roll))
+        });
+        // Meadows grow flowers and the odd tree
+        let mut growth = [(tile::EMPTY, tile::EMPTY); config::CHUNK_AREA];
+        for (local, _) in ground.iter().filter(|&(_, tile)| tile == tile::GRASS) {
+            let tile = pos.tile(local);
+            let roll = hash::unit(hash::hash2(seed, tile.x, tile.y, GROWTH_SALT));
+            if roll < config::TREE_CHANCE {
+                growth[local.index()] = (tile::TREE, tile::EMPTY);
+            } else if roll < config::TREE_CHANCE + config::FLOWERS_CHANCE {
+                growth[local.index()] = (tile::EMPTY, tile::FLOWERS);
+            }
+        }
+        let decoration = TileGrid::from_fn(|local| growth[local.index()].1);
+        let object = TileGrid::from_fn(|local| growth[local.index()].0);
+
+        Chunk {
+            pos,
+            layers: [ground, decoration, object, TileGrid::filled(tile::EMPTY)],
+            modified: false,
+            explored: Explored::default(),
+            revision: 0,
+            edges: [0; config::CHUNK_AREA],
+        }
+    }
+
+    /// Rebuilds a modified chunk from the tiles of every layer, one layer after
+    /// the other in `Layer::ALL` order and each in row-major order
+    pub fn from_tiles(pos: ChunkPos, tiles: &[TileId]) -> Option<Self> {
+        if tiles.len() != Layer::COUNT * config::CHUNK_AREA {
+            return None;
+        }
+
+        let mut layers = tiles.chunks(config::CHUNK_AREA);
+        Some(Chunk {
+            pos,
+            layers: std::array::from_fn(|_| {
+                let layer = layers.next().unwrap_or_default();
+                TileGrid::from_fn(|local| layer[local.index()])
+            }),
+            modified: true,
+            explored: Explored::default(),
+            revision: 0,
+            edges: [0; config::CHUNK_AREA],
+        })
+    }
+
+    /// Tile of a layer at a column and row inside this chunk
+    pub fn get(&self, layer: Layer, pos: LocalTilePos) -> TileId {
+        self.layers[layer as usize].get(pos)
+    }
+
+    pub fn set(&mut self, layer: Layer, pos: LocalTilePos, tile: TileId) {
+        if self.layers[layer as usize].set(pos, tile) {
+            self.modified = true;
+            self.revision = self.revision.wrapping_add(1);
         }
+    }
+
+    /// All tiles of one layer
+    pub fn layer(&self, layer: Layer) -> &TileGrid {
+        &self.layers[layer as usize]
+    }
+
+    /// Whether anything on any layer of a tile blocks the way, e.g. a tree on grass
+    pub fn is_solid(&self, pos: LocalTilePos) -> bool {
+        Layer::ALL
+            .iter()
+            .any(|&layer| tile::tile_def(self.get(layer, pos)).solid)
+    }
 
-        Chunk { pos, tiles }
+    /// Effort to walk across a tile, the slowest layer wins. None if it's solid.
+    pub fn move_cost(&self, pos: LocalTilePos) -> Option<f32> {
+        Layer::ALL.iter().try_fold(1.0f32, |cost, &layer| {
+            let def = tile::tile_def(self.get(layer, pos));
+            (!def.solid).then(|| cost.max(def.move_cost))
+        })
+    }
+
+    /// Sides of a tile its neighbors spill an edge over, see `autotile`
+    pub fn edge_mask(&self, pos: LocalTilePos) -> u8 {
+        self.edges[pos.index()]
+    }
+
+    pub fn is_explored(&self, pos: LocalTilePos) -> bool {
+        self.explored.is_explored(pos)
+    }
+
+    fn explore(&mut self, pos: LocalTilePos) {
+        if self.explored.explore(pos) {
+            self.revision = self.revision.wrapping_add(1);
+        }
+    }
+
+    /// All tiles, layer after layer and each in row-major order
+    pub fn tiles(&self) -> impl Iterator<Item = TileId> + '_ {
+        self.layers
+            .iter()
+            .flat_map(|layer| layer.iter().map(|(_, tile)| tile))
     }
 }
 
+/// A query needed tiles of a chunk that isn't loaded, so it has no answer
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Unloaded(pub ChunkPos);
+
 pub struct World {
+    seed: u64,
     chunks: HashMap<ChunkPos, Chunk>,
-    texture_manager: TextureManager,
+    // Modified chunks that were streamed out, generating them again would lose them
+    stored_chunks: HashMap<ChunkPos, Chunk>,
+    // Fog of unmodified chunks that were streamed out, their tiles are generated again
+    stored_fog: HashMap<ChunkPos, Explored>,
+    // Last revision of unmodified chunks that were streamed out, they carry on
+    // from it when generated again so a revision never stands for two states
+    stored_revisions: HashMap<ChunkPos, u32>,
+    // Chunks generated at least once, their creatures have already been spawned
+    visited: HashSet<ChunkPos>,
+    texture_manager: Option<TextureManager>, // None when running headless
+    textures_stale: bool,                    // The center moved since textures were loaded
+    pub entities: EntityStore,
+    center_chunk: ChunkPos,
+    clock: WorldClock,
+    paths: RefCell<PathCache>, // Searching only needs to borrow the world
 }
 
 impl World {
-    pub async fn new() -> Self {
+    pub async fn new(seed: u64) -> Self {
+        World::with_textures(seed, Some(TextureManager::new().await))
+    }
+
+    /// A world without textures can't be drawn, but simulates without a window,
+    /// e.g. to replay recorded input in tests
+    pub fn with_textures(seed: u64, texture_manager: Option<TextureManager>) -> Self {
         World {
+            seed,
             chunks: HashMap::new(),
-            texture_manager: TextureManager::new().await,
+            stored_chunks: HashMap::new(),
+            stored_fog: HashMap::new(),
+            stored_revisions: HashMap::new(),
+            visited: HashSet::new(),
+            texture_manager,
+            textures_stale: true,
+            entities: EntityStore::new(),
+            center_chunk: ChunkPos { x: 0, y: 0 },
+            clock: WorldClock::new(),
+            paths: RefCell::new(PathCache::new()),
+        }
+    }
+
+    /// Recreates a saved world. Nothing is loaded until the next `stream_chunks`.
+    pub async fn restore(
+        seed: u64,
+        clock: WorldClock,
+        visited: impl IntoIterator<Item = ChunkPos>,
+        modified_chunks: impl IntoIterator<Item = Chunk>,
+        explored: impl IntoIterator<Item = (ChunkPos, Explored)>,
+        entities: impl IntoIterator<Item = Entity>,
+    ) -> Self {
+        let mut world = World::new(seed).await;
+        world.clock = clock;
+        world.visited.extend(visited);
+        for chunk in modified_chunks {
+            world.stored_chunks.insert(chunk.pos, chunk);
+        }
+        for (pos, explored) in explored {
+            match world.stored_chunks.get_mut(&pos) {
+                Some(chunk) => chunk.explored = explored,
+                None => {
+                    world.stored_fog.insert(pos, explored);
+                }
+            }
+        }
+        for entity in entities {
+            world.entities.insert(entity);
         }
+        world
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    pub fn clock(&self) -> WorldClock {
+        self.clock
+    }
+
+    pub fn advance_clock(&mut self) {
+        self.clock.advance();
+    }
+
+    pub fn weather(&self, chunk: ChunkPos) -> Weather {
+        weather::weather_at(self.seed, chunk, self.clock)
+    }
+
+    pub fn visited_chunks(&self) -> impl Iterator<Item = ChunkPos> + '_ {
+        self.visited.iter().copied()
+    }
+
+    /// Loaded and stored chunks that differ from their generated terrain
+    pub fn modified_chunks(&self) -> impl Iterator<Item = &Chunk> {
+        self.chunks
+            .values()
+            .chain(self.stored_chunks.values())
+            .filter(|chunk| chunk.modified)
+    }
+
+    /// Fog of every chunk with at least one tile seen, loaded or not
+    pub fn explored_chunks(&self) -> impl Iterator<Item = (ChunkPos, Explored)> + '_ {
+        self.chunks
+            .values()
+            .chain(self.stored_chunks.values())
+            .map(|chunk| (chunk.pos, chunk.explored))
+            .chain(
+                self.stored_fog
+                    .iter()
+                    .map(|(&pos, &explored)| (pos, explored)),
+            )
+            .filter(|(_, explored)| !explored.is_empty())
+    }
+
+    pub fn center_chunk(&self) -> ChunkPos {
+        self.center_chunk
     }
 
-    pub async fn update(&mut self, center_chunk: ChunkPos) {
-        // Calculate chunk loading ranges
+    pub fn loaded_chunks(&self) -> impl Iterator<Item = ChunkPos> + '_ {
+        self.chunks.keys().copied()
+    }
+
+    pub fn is_visited(&self, pos: ChunkPos) -> bool {
+        self.visited.contains(&pos)
+    }
+
+    /// Revision of a visited chunk, whether it is loaded or not. It only ever
+    /// goes up, streaming a chunk out and in again doesn't start it over.
+    pub fn chunk_revision(&self, pos: ChunkPos) -> Option<u32> {
+        match self
+            .chunks
+            .get(&pos)
+            .or_else(|| self.stored_chunks.get(&pos))
+        {
+            Some(chunk) => Some(chunk.revision),
+            None => self
+                .is_visited(pos)
+                .then(|| self.stored_revisions.get(&pos).copied().unwrap_or(0)),
+        }
+    }
+
+    /// A loaded chunk
+    pub fn chunk(&self, pos: ChunkPos) -> Option<&Chunk> {
+        self.chunks.get(&pos)
+    }
+
+    /// Tiles of a visited chunk for the map, unloaded unmodified chunks are generated again
+    pub fn map_chunk(&self, pos: ChunkPos) -> Option<Cow<'_, Chunk>> {
+        match self
+            .chunks
+            .get(&pos)
+            .or_else(|| self.stored_chunks.get(&pos))
+        {
+            Some(chunk) => Some(Cow::Borrowed(chunk)),
+            None => self
+                .is_visited(pos)
+                .then(|| Cow::Owned(self.regenerate(pos))),
+        }
+    }
+
+    /// Loads the chunks around `center_chunk` and unloads the ones too far away
+    pub fn stream_chunks(&mut self, center_chunk: ChunkPos) {
+        self.center_chunk = center_chunk;
+        self.textures_stale = true;
+
+        // Track which chunks we want to keep
+        let radius = config::CACHE_CHUNKS_RADIUS;
+        let mut chunks_to_keep = Vec::new();
+        let mut loaded = Vec::new();
+
+        for dy in -radius..=radius {
+            for dx in -radius..=radius {
+                let chunk_pos = ChunkPos {
+                    x: center_chunk.x + dx,
+                    y: center_chunk.y + dy,
+                };
+
+                chunks_to_keep.push(chunk_pos);
+
+                // Bring back a stored chunk or generate it if it doesn't exist
+                if !self.chunks.contains_key(&chunk_pos) {
+                    let chunk = match self.stored_chunks.remove(&chunk_pos) {
+                        Some(chunk) => chunk,
+                        None => {
+                            let chunk = self.regenerate(chunk_pos);
+                            self.stored_fog.remove(&chunk_pos);
+                            self.stored_revisions.remove(&chunk_pos);
+                            chunk
+                        }
+                    };
+                    self.chunks.insert(chunk_pos, chunk);
+                    self.paths.get_mut().invalidate(chunk_pos);
+                    loaded.push(chunk_pos);
+                    self.entities.load_chunk(chunk_pos);
+                    if self.visited.insert(chunk_pos) {
+                        self.populate_chunk(chunk_pos);
+                    }
+                }
+            }
+        }
+
+        // New chunks need their edges, and their neighbors' edges facing them
+        for chunk_pos in loaded {
+            self.refresh_edges(chunk_pos.tile_rect().expand(1).iter());
+        }
+
+        // Remove chunks that are too far away, their entities go dormant
+        let far_away: Vec<ChunkPos> = self
+            .chunks
+            .keys()
+            .filter(|pos| !chunks_to_keep.contains(pos))
+            .copied()
+            .collect();
+        for pos in far_away {
+            self.entities.unload_chunk(pos);
+            self.paths.get_mut().invalidate(pos);
+            // Unmodified chunks come back the same from the seed, only their fog
+            // and revision are kept
+            match self.chunks.remove(&pos) {
+                Some(chunk) if chunk.modified => {
+                    self.stored_chunks.insert(pos, chunk);
+                }
+                Some(chunk) => {
+                    if !chunk.explored.is_empty() {
+                        self.stored_fog.insert(pos, chunk.explored);
+                    }
+                    self.stored_revisions.insert(pos, chunk.revision);
+                }
+                None => {}
+            }
+        }
+        self.entities
+            .forget_far_creatures(center_chunk, config::CREATURE_FORGET_RADIUS);
+    }
+
+    /// An unmodified chunk as it was when streamed out, with the tiles seen so far
+    /// and the revision it had then
+    fn regenerate(&self, pos: ChunkPos) -> Chunk {
+        let mut chunk = Chunk::generate(pos, self.seed);
+        if let Some(&explored) = self.stored_fog.get(&pos) {
+            chunk.explored = explored;
+        }
+        if let Some(&revision) = self.stored_revisions.get(&pos) {
+            chunk.revision = revision;
+        }
+        chunk
+    }
+
+    /// Ensures chunk textures around the center are loaded with the appropriate priority.
+    /// Only does work after the center moved.
+    pub async fn load_textures(&mut self) {
+        let Some(texture_manager) = &mut self.texture_manager else {
+            return;
+        };
+        // Sprite sheets are needed wherever the wizard goes, they stay pinned once loaded
+        for sheet in animation::SHEETS {
+            if texture_manager.loaded(sheet.texture).is_none()
+                && texture_manager
+                    .ensure_loaded(sheet.texture, LoadPriority::Immediate)
+                    .await
+                    .is_some()
+            {
+                texture_manager.pin(sheet.texture);
+            }
+        }
+        if !self.textures_stale {
+            return;
+        }
+        self.textures_stale = false;
+
         let ranges = [
             (config::VISIBLE_CHUNKS_RADIUS, LoadPriority::Immediate),
             (config::PRELOAD_CHUNKS_RADIUS, LoadPriority::Preload),
             (config::CACHE_CHUNKS_RADIUS, LoadPriority::Cache),
         ];
-
-        // Track which chunks we want to keep
-        let mut chunks_to_keep = Vec::new();
-
-        // Load or update chunks in each range
-        for &(radius, priority) in &ranges {
+        for (radius, priority) in ranges {
             for dy in -radius..=radius {
                 for dx in -radius..=radius {
-                    let chunk_pos = ChunkPos {
-                        x: center_chunk.x + dx,
-                        y: center_chunk.y + dy,
+                    let chunk_id = format!(
+                        "chunk_{}_{}",
+                        self.center_chunk.x + dx,
+                        self.center_chunk.y + dy
+                    );
+                    texture_manager.ensure_loaded(&chunk_id, priority).await;
+                }
+            }
+        }
+    }
+
+    /// Spawns the creatures living in a newly generated chunk
+    fn populate_chunk(&mut self, chunk_pos: ChunkPos) {
+        let roll = |salt| hash::unit(hash::hash2(self.seed, chunk_pos.x, chunk_pos.y, salt));
+        // More creatures are out at night
+        let chance = match self.clock.phase() {
+            DayPhase::Night => config::CREATURE_NIGHT_SPAWN_CHANCE,
+            DayPhase::Dawn | DayPhase::Day | DayPhase::Dusk => config::CREATURE_SPAWN_CHANCE,
+        };
+        if roll(CREATURE_SALT) >= chance {
+            return;
+        }
+
+        // Somewhere the whole body fits inside the chunk, and not inside a wall
+        let sprite = Sprite {
+            color: MAROON,
+            size: 24.0,
+        };
+        let (corner, size) = chunk_pos.world_rect();
+        let room = size - Vec2::splat(sprite.size);
+        let spot = Vec2::new(roll(CREATURE_SALT + 1), roll(CREATURE_SALT + 2));
+        let position = corner + Vec2::splat(sprite.size / 2.0) + spot * room;
+        if self
+            .solid_tiles_in(sprite.covers(position))
+            .is_ok_and(|solid| !solid.is_empty())
+        {
+            return;
+        }
+        let creature = self.entities.spawn(position, sprite);
+        creature.health = Some(Health {
+            current: 10.0,
+            max: 10.0,
+        });
+        creature.ai = Some(Ai::Wander {
+            direction: Vec2::ZERO,
+            frames_left: 0,
+        });
+        creature.animator = Some(Animator::new(&animation::CREATURE_SHEET));
+    }
+
+    /// Loaded chunks close enough to the player to run the cellular simulation.
+    /// The preload radius is inside the cache radius, so their neighbors are loaded too.
+    pub fn simulated_chunks(&self) -> Vec<ChunkPos> {
+        let radius = config::PRELOAD_CHUNKS_RADIUS;
+        self.chunks
+            .keys()
+            .filter(|pos| {
+                (pos.x - self.center_chunk.x).abs() <= radius
+                    && (pos.y - self.center_chunk.y).abs() <= radius
+            })
+            .copied()
+            .collect()
+    }
+
+    /// Tile of a layer at a position, None if its chunk isn't loaded
+    pub fn get_tile(&self, layer: Layer, pos: TilePos) -> Option<TileId> {
+        let (chunk_pos, local) = pos.to_chunk_local();
+        self.chunks
+            .get(&chunk_pos)
+            .map(|chunk| chunk.get(layer, local))
+    }
+
+    /// Replaces a tile of a layer, returns false if its chunk isn't loaded
+    pub fn set_tile(&mut self, layer: Layer, pos: TilePos, tile: TileId) -> bool {
+        let (chunk_pos, local) = pos.to_chunk_local();
+        let Some(chunk) = self.chunks.get_mut(&chunk_pos) else {
+            return false;
+        };
+        if chunk.get(layer, local) != tile {
+            let walked = chunk.move_cost(local);
+            chunk.set(layer, local, tile);
+            // Burning, freezing or blasting a tile can open or close paths
+            if chunk.move_cost(local) != walked {
+                self.paths.get_mut().invalidate(chunk_pos);
+            }
+            if layer != Layer::Ground {
+                return true;
+            }
+            // Only the tile and the neighbors it spills onto can change their edges
+            self.refresh_edges(std::iter::once(pos).chain(pos.neighbors()));
+        }
+        true
+    }
+
+    /// Works out the edge masks of tiles again, tiles of unloaded chunks are skipped
+    fn refresh_edges(&mut self, tiles: impl IntoIterator<Item = TilePos>) {
+        let masks: Vec<(TilePos, u8)> = tiles
+            .into_iter()
+            .map(|pos| {
+                let mask = autotile::edge_mask(pos, |pos| self.get_tile(Layer::Ground, pos));
+                (pos, mask)
+            })
+            .collect();
+        for (pos, mask) in masks {
+            let (chunk_pos, local) = pos.to_chunk_local();
+            if let Some(chunk) = self.chunks.get_mut(&chunk_pos) {
+                chunk.edges[local.index()] = mask;
+            }
+        }
+    }
+
+    /// Whether the wizard has seen a tile, unloaded tiles count as unseen
+    fn is_explored(chunks: &HashMap<ChunkPos, Chunk>, pos: TilePos) -> bool {
+        let (chunk_pos, local) = pos.to_chunk_local();
+        chunks
+            .get(&chunk_pos)
+            .is_some_and(|chunk| chunk.is_explored(local))
+    }
+
+    /// Marks tiles as seen, tiles of unloaded chunks are skipped
+    fn explore(&mut self, tiles: impl IntoIterator<Item = TilePos>) {
+        for pos in tiles {
+            let (chunk_pos, local) = pos.to_chunk_local();
+            if let Some(chunk) = self.chunks.get_mut(&chunk_pos) {
+                chunk.explore(local);
+            }
+        }
+    }
+
+    /// Explores everything in sight of a tile
+    pub fn look_around(&mut self, from: TilePos, radius: i32) {
+        let chunks = &self.chunks;
+        let visible = fog::visible_tiles(from, radius, |pos| Self::is_solid(chunks, pos));
+        self.explore(visible);
+    }
+
+    /// Unloaded tiles count as solid so nothing wanders off the loaded world
+    fn is_solid(chunks: &HashMap<ChunkPos, Chunk>, pos: TilePos) -> bool {
+        let (chunk_pos, local) = pos.to_chunk_local();
+        chunks
+            .get(&chunk_pos)
+            .is_none_or(|chunk| chunk.is_solid(local))
+    }
+
+    fn loaded_chunk(chunks: &HashMap<ChunkPos, Chunk>, pos: ChunkPos) -> Result<&Chunk, Unloaded> {
+        chunks.get(&pos).ok_or(Unloaded(pos))
+    }
+
+    /// Tile of a layer under a point in the world
+    pub fn tile_at(&self, layer: Layer, pos: WorldPos) -> Result<TileId, Unloaded> {
+        let (chunk_pos, local) = pos.tile().to_chunk_local();
+        Ok(Self::loaded_chunk(&self.chunks, chunk_pos)?.get(layer, local))
+    }
+
+    /// Solid tiles of any layer inside a rect, row by row. Every chunk the rect
+    /// overlaps has to be loaded, a partial answer would pass for a clear area.
+    pub fn solid_tiles_in(&self, rect: TileRect) -> Result<Vec<TilePos>, Unloaded> {
+        let (first, _) = rect.min.to_chunk_local();
+        let (last, _) = rect.max.to_chunk_local();
+        for y in first.y..=last.y {
+            for x in first.x..=last.x {
+                Self::loaded_chunk(&self.chunks, ChunkPos { x, y })?;
+            }
+        }
+        Ok(rect
+            .iter()
+            .filter(|&pos| {
+                let (chunk_pos, local) = pos.to_chunk_local();
+                self.chunks[&chunk_pos].is_solid(local)
+            })
+            .collect())
+    }
+
+    /// First solid tile on the straight line from `from` to `to`, see `raycast::raycast`.
+    /// A line running into an unloaded chunk before it hits anything has no answer.
+    pub fn raycast(&self, from: WorldPos, to: WorldPos) -> Result<Option<RayHit>, Unloaded> {
+        raycast::raycast(from, to, |pos| {
+            let (chunk_pos, local) = pos.to_chunk_local();
+            Ok(Self::loaded_chunk(&self.chunks, chunk_pos)?.is_solid(local))
+        })
+    }
+
+    /// Closest tile of a type on a layer within `radius` tiles of `from`, None if
+    /// there is none. Tiles at the same distance are picked in ring order. The
+    /// search only fails if it needs an unloaded chunk before the answer is certain.
+    pub fn nearest_tile(
+        &self,
+        layer: Layer,
+        tile: TileId,
+        from: TilePos,
+        radius: i32,
+    ) -> Result<Option<TilePos>, Unloaded> {
+        let radius_squared = i64::from(radius) * i64::from(radius);
+        let mut best: Option<(i64, TilePos)> = None;
+        // Rings of growing size around `from`, a ring can't hold anything closer
+        // than its own radius
+        for ring in 0..=radius {
+            if best.is_some_and(|(distance, _)| i64::from(ring) * i64::from(ring) > distance) {
+                break;
+            }
+            for dy in -ring..=ring {
+                let step = if dy.abs() == ring { 1 } else { 2 * ring };
+                for dx in (-ring..=ring).step_by(step.max(1) as usize) {
+                    let distance = i64::from(dx) * i64::from(dx) + i64::from(dy) * i64::from(dy);
+                    if distance > radius_squared
+                        || best.is_some_and(|(closest, _)| distance >= closest)
+                    {
+                        continue;
+                    }
+                    let Some(pos) = from.offset(dx, dy) else {
+                        continue;
                     };
+                    let (chunk_pos, local) = pos.to_chunk_local();
+                    if Self::loaded_chunk(&self.chunks, chunk_pos)?.get(layer, local) == tile {
+                        best = Some((distance, pos));
+                    }
+                }
+            }
+        }
+        Ok(best.map(|(_, pos)| pos))
+    }
+
+    /// Unloaded tiles can't be walked on, paths stay inside the loaded world
+    fn move_cost(chunks: &HashMap<ChunkPos, Chunk>, pos: TilePos) -> Option<f32> {
+        let (chunk_pos, local) = pos.to_chunk_local();
+        chunks.get(&chunk_pos)?.move_cost(local)
+    }
+
+    /// Path over walkable tiles between two tiles, see `pathfinding`. Paths are
+    /// cached until a tile in a chunk the search looked at is walked differently.
+    pub fn find_path(&self, from: TilePos, to: TilePos, planner: Planner) -> Option<Arc<Path>> {
+        let chunks = &self.chunks;
+        self.paths
+            .borrow_mut()
+            .find(from, to, planner, |pos| Self::move_cost(chunks, pos))
+    }
+
+    /// Simulates entities of loaded chunks and sets off projectiles that hit something.
+    /// Returns the projectiles that went off.
+    pub fn update_entities(&mut self, tick: u64, wizard: TilePos) -> Vec<Impact> {
+        self.approach(wizard);
+        self.flee_fire();
+
+        // Entities look at the world while they move, so they are out of it meanwhile
+        let mut entities = std::mem::replace(&mut self.entities, EntityStore::new());
+        let seed = hash::hash1(self.seed, tick, ENTITY_SALT);
+        let impacts = entities.update(
+            |rect| !matches!(self.solid_tiles_in(rect), Ok(solid) if solid.is_empty()),
+            |from, to| self.raycast(from, to),
+            seed,
+        );
+        self.entities = entities;
+
+        for impact in &impacts {
+            let tiles = Shape::Circle {
+                radius: impact.radius,
+            }
+            .rasterize(impact.tile);
+            self.apply_area(&tiles, impact.effect);
+        }
+        impacts
+    }
+
+    /// Sends wandering creatures near the wizard along a path up to them, one tile
+    /// at a time. Fire nearby still scares them off, see `flee_fire`.
+    fn approach(&mut self, wizard: TilePos) {
+        let mut approaching = HashMap::new();
+        for entity in self.entities.iter() {
+            let tile = entity.tile_pos();
+            if !matches!(entity.ai, Some(Ai::Wander { .. }))
+                || tile.distance(wizard) > config::CREATURE_NOTICE_RADIUS as f32
+            {
+                continue;
+            }
+            let Some(path) = self.find_path(tile, wizard, Planner::Tiles) else {
+                continue;
+            };
+            // Standing next to them already. Otherwise it heads for the next tile
+            // while it overlaps no other, so it never cuts a blocked corner.
+            let target = match path.tiles[..] {
+                [] | [_] | [_, _] => None,
+                [_, next, ..] => {
+                    let body = entity.sprite.covers(entity.position);
+                    let inside = body
+                        .iter()
+                        .all(|covered| covered == tile || covered == next);
+                    Some(if inside { next } else { tile })
+                }
+            };
+            let direction = target.map_or(Vec2::ZERO, |target| {
+                (target.world_center() - entity.position).normalize_or_zero()
+            });
+            approaching.insert(entity.id, direction);
+        }
+        for entity in self.entities.iter_mut() {
+            if let Some(&direction) = approaching.get(&entity.id) {
+                entity.ai = Some(Ai::Wander {
+                    direction,
+                    frames_left: 1,
+                });
+            }
+        }
+    }
+
+    /// Turns wandering creatures away from the closest fire burning near them
+    fn flee_fire(&mut self) {
+        let mut fleeing = HashMap::new();
+        for entity in self.entities.iter() {
+            if let Some(Ai::Wander { .. }) = entity.ai
+                && let Ok(Some(fire)) = self.nearest_tile(
+                    Layer::Ground,
+                    tile::FIRE,
+                    entity.tile_pos(),
+                    config::CREATURE_FEAR_RADIUS,
+                )
+            {
+                let away = entity.position - fire.world_center();
+                fleeing.insert(entity.id, away.normalize_or(Vec2::X));
+            }
+        }
+        for entity in self.entities.iter_mut() {
+            if let Some(&direction) = fleeing.get(&entity.id) {
+                // Checked again next tick, so the creature stops once it's clear
+                entity.ai = Some(Ai::Wander {
+                    direction,
+                    frames_left: 1,
+                });
+            }
+        }
+    }
 
-                    chunks_to_keep.push(chunk_pos);
+    /// Releases a planned spell from the caster's position, returns the projectile
+    /// it launched if there is one
+    pub fn cast(&mut self, plan: CastPlan, caster: WorldPos) -> Option<EntityId> {
+        match plan {
+            CastPlan::Projectile {
+                velocity,
+                frames,
+                blast_radius,
+                effect,
+            } => {
+                let bolt = self.entities.spawn(
+                    caster,
+                    Sprite {
+                        color: element_color(effect.element),
+                        size: 12.0,
+                    },
+                );
+                bolt.projectile = Some(Projectile {
+                    velocity,
+                    frames_left: frames,
+                    blast_radius,
+                    effect,
+                });
+                return Some(bolt.id);
+            }
+            CastPlan::Area {
+                origin,
+                shape,
+                effect,
+            } => self.apply_area(&shape.rasterize(origin), effect),
+            CastPlan::Reveal { center, radius } => {
+                self.explore((Shape::Circle { radius }).rasterize(center));
+            }
+        }
+        None
+    }
+
+    /// Damages creatures standing on the affected tiles and leaves elemental residue
+    fn apply_area(&mut self, tiles: &[TilePos], effect: AreaEffect) {
+        for entity in self.entities.iter_mut() {
+            let hit = tiles.contains(&entity.tile_pos());
+            if let Some(health) = &mut entity.health
+                && hit
+            {
+                health.current -= effect.damage;
+            }
+        }
 
-                    // Create chunk if it doesn't exist
-                    if !self.chunks.contains_key(&chunk_pos) {
-                        self.chunks.insert(chunk_pos, Chunk::new(chunk_pos));
+        if let Some(element) = effect.element {
+            for &pos in tiles {
+                for layer in Layer::ALL {
+                    if let Some(residue) = self
+                        .get_tile(layer, pos)
+                        .and_then(|tile| element_residue(element, layer, tile))
+                    {
+                        self.set_tile(layer, pos, residue);
                     }
+                }
+            }
+        }
+    }
 
-                    // Ensure textures are loaded with appropriate priority
-                    let chunk_id = format!("chunk_{}_{}", chunk_pos.x, chunk_pos.y);
-                    self.texture_manager
-                        .ensure_loaded(&chunk_id, priority)
-                        .await;
+    /// Light falling on the tiles in view, from the sky, glowing tiles and spells in
+    /// flight, plus `extra` lights like the wizard's staff
+    pub fn light_map(&self, camera: &Camera, ambient: Color, extra: &[PointLight]) -> LightMap {
+        let (view_start, view_end) = camera.get_visible_range();
+        let area = TileRect::covering(view_start, view_end + Vec2::splat(config::TILE_SIZE as f32));
+
+        // Glowing tiles just out of view still light the edge of the screen
+        let mut lights = extra.to_vec();
+        for pos in area.expand(config::MAX_LIGHT_RADIUS).iter() {
+            if let Some(def) = self.get_tile(Layer::Ground, pos).map(tile::tile_def)
+                && def.glow > 0
+            {
+                lights.push(PointLight {
+                    tile: pos,
+                    radius: def.glow,
+                    color: def.map_color,
+                });
+            }
+        }
+        for entity in self.entities.iter() {
+            if let Some(projectile) = &entity.projectile {
+                let element = projectile.effect.element;
+                let radius = element_glow(element);
+                if radius > 0 {
+                    lights.push(PointLight {
+                        tile: entity.tile_pos(),
+                        radius,
+                        color: element_color(element),
+                    });
                 }
             }
         }
 
-        // Remove chunks that are too far away
-        self.chunks.retain(|pos, _| chunks_to_keep.contains(pos));
+        // Too many lights cost frames, keep the ones closest to the middle of the view
+        let (min, max) = (area.min, area.max);
+        let center = Vec2::new((min.x + max.x) as f32, (min.y + max.y) as f32) / 2.0;
+        let distance = |light: &PointLight| {
+            center.distance_squared(Vec2::new(light.tile.x as f32, light.tile.y as f32))
+        };
+        lights.sort_by(|a, b| distance(a).total_cmp(&distance(b)));
+        lights.truncate(config::MAX_LIGHTS);
+
+        let chunks = &self.chunks;
+        LightMap::build(area, ambient, &lights, |pos| Self::is_solid(chunks, pos))
     }
 
-    pub fn draw(&self, camera: &Camera) {
+    /// Draws the tiles in view with the color `tint` gives each of them
+    pub fn draw(&self, camera: &Camera, tint: impl Fn(TilePos) -> Color) {
+        let Some(texture_manager) = &self.texture_manager else {
+            return;
+        };
         let (view_start, view_end) = camera.get_visible_range();
 
         // Convert view range to chunk coordinates
-        let start_chunk = ChunkPos::from_world_pos(view_start.x, view_start.y);
-        let end_chunk = ChunkPos::from_world_pos(view_end.x, view_end.y);
+        let (start_chunk, end_chunk) = (view_start.chunk, view_end.chunk);
 
-        // Draw visible chunks
-        for chunk_y in start_chunk.y..=end_chunk.y {
-            for chunk_x in start_chunk.x..=end_chunk.x {
-                let chunk_pos = ChunkPos {
-                    x: chunk_x,
-                    y: chunk_y,
-                };
+        // Draw visible chunks a layer at a time, so things standing on one chunk
+        // are never covered by the ground of the next
+        for layer in Layer::ALL {
+            for chunk_y in start_chunk.y..=end_chunk.y {
+                for chunk_x in start_chunk.x..=end_chunk.x {
+                    let chunk_pos = ChunkPos {
+                        x: chunk_x,
+                        y: chunk_y,
+                    };
 
-                if let Some(chunk) = self.chunks.get(&chunk_pos) {
-                    self.draw_chunk(chunk, camera);
+                    if let Some(chunk) = self.chunks.get(&chunk_pos) {
+                        self.draw_chunk(chunk, layer, camera, texture_manager, &tint);
+                    }
                 }
             }
         }
+
+        // Creatures out in the dark stay hidden
+        self.entities.draw(
+            camera,
+            |pos| Self::is_explored(&self.chunks, pos),
+            &tint,
+            |sheet| self.sprite_sheet(sheet),
+        );
     }
 
-    fn draw_chunk(&self, chunk: &Chunk, camera: &Camera) {
-        let chunk_world_x = chunk.pos.x * config::CHUNK_SIZE * config::TILE_SIZE;
-        let chunk_world_y = chunk.pos.y * config::CHUNK_SIZE * config::TILE_SIZE;
+    /// Texture of a sprite sheet, None until it is loaded or when running headless
+    pub fn sprite_sheet(&self, sheet: &SpriteSheet) -> Option<&Texture2D> {
+        self.texture_manager.as_ref()?.loaded(sheet.texture)
+    }
 
-        for tile_y in 0..config::CHUNK_SIZE {
-            for tile_x in 0..config::CHUNK_SIZE {
-                let world_x = chunk_world_x + tile_x * config::TILE_SIZE;
-                let world_y = chunk_world_y + tile_y * config::TILE_SIZE;
+    fn draw_chunk(
+        &self,
+        chunk: &Chunk,
+        layer: Layer,
+        camera: &Camera,
+        texture_manager: &TextureManager,
+        tint: &impl Fn(TilePos) -> Color,
+    ) {
+        // Layers holding nothing but empty tiles only matter for the fog, which the
+        // ground pass already drew
+        if layer != Layer::Ground && chunk.layer(layer).palette() == [tile::EMPTY] {
+            return;
+        }
 
-                let screen_pos = camera.world_to_screen(Vec2::new(world_x as f32, world_y as f32));
+        for local in LocalTilePos::all() {
+            let pos = chunk.pos.tile(local);
+            let (corner, size) = pos.world_rect();
+            let screen_pos = camera.world_to_screen(corner);
 
-                // Only draw if on screen
-                if screen_pos.x >= -config::TILE_SIZE as f32
-                    && screen_pos.x <= screen_width()
-                    && screen_pos.y >= -config::TILE_SIZE as f32
-                    && screen_pos.y <= screen_height()
-                {
-                    // Get the appropriate texture for this tile
-                    let texture = self.texture_manager.get_texture(world_x, world_y);
+            // Only draw if on screen
+            if screen_pos.x >= -size.x
+                && screen_pos.x <= screen_width()
+                && screen_pos.y >= -size.y
+                && screen_pos.y <= screen_height()
+            {
+                if !chunk.is_explored(local) {
+                    // The ground pass covers the tile, the layers above leave it hidden
+                    if layer != Layer::Ground {
+                        continue;
+                    }
+                    draw_rectangle(screen_pos.x, screen_pos.y, size.x, size.y, FOG_COLOR);
+                    continue;
+                }
 
-                    draw_texture(
-                        Arc::as_ref(&texture), // Dereference the Arc to get the Texture2D
-                        screen_pos.x,
-                        screen_pos.y,
-                        WHITE,
-                    );
+                let tile = chunk.get(layer, local);
+                if tile == tile::EMPTY {
+                    continue;
+                }
+
+                // Get the appropriate texture for this tile
+                let texture = texture_manager.get_tile_texture(tile, self.clock);
+
+                let color = tint(pos);
+                draw_texture(
+                    Arc::as_ref(&texture), // Dereference the Arc to get the Texture2D
+                    screen_pos.x,
+                    screen_pos.y,
+                    color,
+                );
+
+                // Edges of neighboring terrain spilling over this tile
+                let mask = chunk.edge_mask(local);
+                if layer == Layer::Ground && mask != 0 {
+                    for (neighbor, sides) in
+                        autotile::transitions(pos, mask, |pos| self.get_tile(layer, pos))
+                    {
+                        if let Some(edge) = texture_manager.get_transition_texture(neighbor, sides)
+                        {
+                            draw_texture(&edge, screen_pos.x, screen_pos.y, color);
+                        }
+                    }
                 }
             }
         }
     }
 
     #[cfg(debug_assertions)]
-    pub fn draw_debug_info(&self) {
+    pub fn draw_debug_info(&self, player_tile: TilePos) {
+        let standing_on: Vec<&str> = Layer::ALL
+            .iter()
+            .filter_map(|&layer| self.get_tile(layer, player_tile))
+            .filter(|&tile| tile != tile::EMPTY)
+            .map(|tile| tile::tile_def(tile).name)
+            .collect();
+        if !standing_on.is_empty() {
+            draw_text(
+                format!("Standing on: {}", standing_on.join(", ")),
+                10.0,
+                150.0,
+                20.0,
+                BLACK,
+            );
+        }
+
         draw_text(
-            &format!("Active Chunks: {}", self.chunks.len()),
+            format!("Active Chunks: {}", self.chunks.len()),
             10.0,
             90.0,
             20.0,
             BLACK,
         );
+
+        draw_text(
+            format!("Active Entities: {}", self.entities.active_count()),
+            10.0,
+            130.0,
+            20.0,
+            BLACK,
+        );
+
+        let (gpu_bytes, ram_bytes) = self
+            .texture_manager
+            .as_ref()
+            .map_or((0, 0), TextureManager::get_memory_stats);
+        draw_text(
+            format!(
+                "Texture Memory: GPU {} KB / RAM {} KB",
+                gpu_bytes / 1024,
+                ram_bytes / 1024
+            ),
+            10.0,
+            110.0,
+            20.0,
+            BLACK,
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A world with the chunks around the origin loaded and a patch of open grass
+    /// around the chunk corner at tile (0, 0)
+    fn open_world() -> World {
+        let mut world = World::with_textures(7, None);
+        world.stream_chunks(ChunkPos::default());
+        let patch = TileRect {
+            min: TilePos { x: -12, y: -12 },
+            max: TilePos { x: 12, y: 12 },
+        };
+        for pos in patch.iter() {
+            world.set_tile(Layer::Ground, pos, tile::GRASS);
+            for layer in &Layer::ALL[1..] {
+                world.set_tile(*layer, pos, tile::EMPTY);
+            }
+        }
+        world
+    }
+
+    #[test]
+    fn chunks_stream_in_millions_of_tiles_out() {
+        let mut world = World::with_textures(7, None);
+        let far = TilePos {
+            x: 5_000_000,
+            y: -3_000_000,
+        };
+        let (chunk, _) = far.to_chunk_local();
+        world.stream_chunks(chunk);
+        assert!(world.loaded_chunks().any(|loaded| loaded == chunk));
+        assert!(world.get_tile(Layer::Ground, far).is_some());
+    }
+
+    #[test]
+    fn unmodified_chunks_only_keep_their_fog_once_streamed_out() {
+        let mut world = World::with_textures(7, None);
+        world.stream_chunks(ChunkPos::default());
+        let seen = TilePos { x: 5, y: 5 };
+        world.look_around(seen, 2);
+        let explored = world.chunk(ChunkPos::default()).unwrap().explored;
+        assert!(!explored.is_empty());
+
+        let away = ChunkPos {
+            x: 3 * config::CACHE_CHUNKS_RADIUS,
+            y: 0,
+        };
+        world.stream_chunks(away);
+        assert!(world.stored_chunks.is_empty());
+        assert_eq!(world.stored_fog.get(&ChunkPos::default()), Some(&explored));
+        assert!(
+            world
+                .explored_chunks()
+                .any(|(pos, fog)| pos == ChunkPos::default() && fog == explored)
+        );
+        let (_, local) = seen.to_chunk_local();
+        assert!(
+            world
+                .map_chunk(ChunkPos::default())
+                .unwrap()
+                .is_explored(local)
+        );
+
+        world.stream_chunks(ChunkPos::default());
+        assert_eq!(world.chunk(ChunkPos::default()).unwrap().explored, explored);
+        assert!(!world.stored_fog.contains_key(&ChunkPos::default()));
+    }
+
+    #[test]
+    fn revisions_carry_on_after_a_chunk_is_streamed_out_and_in() {
+        let mut world = World::with_textures(7, None);
+        let home = ChunkPos::default();
+        world.stream_chunks(home);
+        world.look_around(TilePos { x: 5, y: 5 }, 2);
+        let seen = world.chunk_revision(home).unwrap();
+        assert!(seen > 0);
+
+        let away = ChunkPos {
+            x: 3 * config::CACHE_CHUNKS_RADIUS,
+            y: 0,
+        };
+        world.stream_chunks(away);
+        assert_eq!(world.chunk_revision(home), Some(seen));
+
+        // Generated again it looks the same, and what's seen next is a new revision
+        world.stream_chunks(home);
+        assert_eq!(world.chunk_revision(home), Some(seen));
+        world.look_around(TilePos { x: 12, y: 12 }, 2);
+        assert!(world.chunk_revision(home).unwrap() > seen);
+    }
+
+    #[test]
+    fn edges_follow_changes_on_the_other_side_of_a_chunk_border() {
+        use autotile::{EAST, NORTH, SOUTH, WEST};
+        let mut world = open_world();
+        let sand = TilePos { x: 0, y: 0 };
+        let water = TilePos { x: -1, y: 0 };
+        let edge_mask = |world: &World, pos: TilePos| {
+            let (chunk, local) = pos.to_chunk_local();
+            world.chunk(chunk).unwrap().edge_mask(local)
+        };
+
+        world.set_tile(Layer::Ground, sand, tile::SAND);
+        assert_eq!(edge_mask(&world, sand), NORTH | EAST | SOUTH | WEST);
+
+        // The west neighbor is in another chunk, changing it updates both sides
+        world.set_tile(Layer::Ground, water, tile::WATER);
+        assert_eq!(edge_mask(&world, sand), NORTH | EAST | SOUTH);
+        assert_eq!(edge_mask(&world, water), NORTH | EAST | SOUTH | WEST);
+        assert_ne!(sand.to_chunk_local().0, water.to_chunk_local().0);
+    }
+
+    #[test]
+    fn queries_past_the_loaded_world_say_so() {
+        let mut world = open_world();
+        let radius = config::CACHE_CHUNKS_RADIUS;
+        let beyond = ChunkPos {
+            x: radius + 1,
+            y: 0,
+        };
+        let far = beyond.tile_rect().min;
+
+        assert_eq!(
+            world.tile_at(Layer::Ground, far.world_center()),
+            Err(Unloaded(beyond))
+        );
+        let rect = TileRect {
+            min: TilePos { x: 0, y: 0 },
+            max: far,
+        };
+        assert_eq!(world.solid_tiles_in(rect), Err(Unloaded(beyond)));
+        // The last loaded tiles before the border are cleared of anything solid
+        let edge = TilePos { x: far.x - 3, y: 0 };
+        for x in edge.x..far.x {
+            world.set_tile(Layer::Ground, TilePos { x, y: 0 }, tile::GRASS);
+            for layer in &Layer::ALL[1..] {
+                world.set_tile(*layer, TilePos { x, y: 0 }, tile::EMPTY);
+            }
+        }
+        let (from, to) = (edge.world_center(), far.world_center());
+        assert_eq!(world.raycast(from, to), Err(Unloaded(beyond)));
+        // A wall on the way answers before the unloaded chunk is reached
+        let wall = TilePos { x: far.x - 1, y: 0 };
+        world.set_tile(Layer::Ground, wall, tile::STONE);
+        assert_eq!(world.raycast(from, to).unwrap().unwrap().tile, wall);
+    }
+
+    #[test]
+    fn solid_tiles_are_found_on_both_sides_of_a_chunk_border() {
+        let walls = [
+            TilePos { x: -1, y: 2 },
+            TilePos { x: 0, y: -1 },
+            TilePos { x: 4, y: 4 },
+        ];
+        let mut world = open_world();
+        for wall in walls {
+            world.set_tile(Layer::Ground, wall, tile::STONE);
+        }
+        world.set_tile(Layer::Object, TilePos { x: 1, y: 1 }, tile::TREE);
+        let rect = TileRect {
+            min: TilePos { x: -3, y: -3 },
+            max: TilePos { x: 3, y: 3 },
+        };
+        assert_eq!(
+            world.solid_tiles_in(rect),
+            Ok(vec![
+                TilePos { x: 0, y: -1 },
+                TilePos { x: 1, y: 1 },
+                TilePos { x: -1, y: 2 },
+            ])
+        );
+    }
+
+    #[test]
+    fn nearest_tile_looks_across_chunk_borders() {
+        let mut world = open_world();
+        let from = TilePos { x: 1, y: 1 };
+        assert_eq!(
+            world.nearest_tile(Layer::Ground, tile::FIRE, from, 6),
+            Ok(None)
+        );
+
+        world.set_tile(Layer::Ground, TilePos { x: 5, y: 1 }, tile::FIRE);
+        world.set_tile(Layer::Ground, TilePos { x: -1, y: -1 }, tile::FIRE);
+        assert_eq!(
+            world.nearest_tile(Layer::Ground, tile::FIRE, from, 6),
+            Ok(Some(TilePos { x: -1, y: -1 }))
+        );
+        // Out of reach
+        assert_eq!(
+            world.nearest_tile(Layer::Ground, tile::FIRE, from, 2),
+            Ok(None)
+        );
+        // Only the layer asked about counts
+        assert_eq!(
+            world.nearest_tile(Layer::Object, tile::FIRE, from, 6),
+            Ok(None)
+        );
+    }
+
+    #[test]
+    fn paths_change_when_a_tile_on_them_does() {
+        let mut world = open_world();
+        let from = TilePos { x: -4, y: 0 };
+        let to = TilePos { x: 4, y: 0 };
+        let path = world.find_path(from, to, Planner::Tiles).unwrap();
+        assert_eq!(path.cost, 8.0);
+
+        // Wading through water is slower than stepping around it
+        world.set_tile(Layer::Ground, TilePos { x: 0, y: 0 }, tile::WATER);
+        let path = world.find_path(from, to, Planner::Tiles).unwrap();
+        assert_eq!(path.cost, 10.0);
+        assert!(!path.tiles.contains(&TilePos { x: 0, y: 0 }));
+
+        // Walled off on every side
+        for wall in to.neighbors() {
+            world.set_tile(Layer::Object, wall, tile::TREE);
+        }
+        assert_eq!(world.find_path(from, to, Planner::Chunks), None);
+        assert_eq!(world.find_path(from, to, Planner::Tiles), None);
+        world.set_tile(Layer::Object, TilePos { x: 3, y: 0 }, tile::EMPTY);
+        assert!(world.find_path(from, to, Planner::Tiles).is_some());
+    }
+
+    #[test]
+    fn creatures_run_from_fire_nearby() {
+        let mut world = open_world();
+        let start = TilePos { x: 2, y: 0 }.world_center();
+        let creature = world.entities.spawn(
+            start,
+            Sprite {
+                color: MAROON,
+                size: 24.0,
+            },
+        );
+        creature.ai = Some(Ai::Wander {
+            direction: -Vec2::X,
+            frames_left: 60,
+        });
+        let id = creature.id;
+
+        world.set_tile(Layer::Ground, TilePos { x: 0, y: 0 }, tile::FIRE);
+        world.update_entities(0, TilePos { x: 100, y: 0 });
+        let moved = world.entities.get(id).unwrap().position - start;
+        assert!(moved.x > 0.0, "walked towards the fire: {moved:?}");
+    }
+
+    #[test]
+    fn creatures_walk_around_walls_up_to_the_wizard() {
+        let mut world = open_world();
+        let wizard = TilePos { x: 3, y: 0 };
+        // A wall between them, open at the bottom
+        for y in -3..=2 {
+            world.set_tile(Layer::Object, TilePos { x: 0, y }, tile::TREE);
+        }
+        let creature = world.entities.spawn(
+            TilePos { x: -2, y: 0 }.world_center(),
+            Sprite {
+                color: MAROON,
+                size: 24.0,
+            },
+        );
+        creature.ai = Some(Ai::Wander {
+            direction: Vec2::ZERO,
+            frames_left: 0,
+        });
+        let id = creature.id;
+
+        let mut lowest = 0;
+        for tick in 0..600 {
+            world.update_entities(tick, wizard);
+            lowest = lowest.max(world.entities.get(id).unwrap().tile_pos().y);
+        }
+        let tile = world.entities.get(id).unwrap().tile_pos();
+        assert_eq!(tile.distance(wizard), 1.0, "stopped at {tile:?}");
+        assert!(lowest > 2, "never went round the wall");
     }
 }